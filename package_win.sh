@@ -19,6 +19,7 @@ echo Creating pack dir
 rm -rf pack
 mkdir pack
 cp target/release/tf2-demo-player.exe pack/
+cp target/release/tf2-demo-cli.exe pack/
 cd pack
  
 echo Copying libraries
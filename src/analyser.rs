@@ -1,11 +1,19 @@
 use std::collections::{BTreeMap, HashMap};
-use std::ops::Index;
+use std::ops::{Index, IndexMut};
 
 use itertools::Itertools;
-use tf_demo_parser::demo::gameevent_gen::{PlayerConnectClientEvent, PlayerDisconnectEvent};
+use serde::{Deserialize, Serialize};
+use tf_demo_parser::demo::data::game_state::PlayerState;
+use tf_demo_parser::demo::data::MaybeUtf8String;
+use tf_demo_parser::demo::gameevent_gen::{
+    MedicDeathEvent, PlayerChargeDeployedEvent, PlayerConnectClientEvent, PlayerDisconnectEvent,
+    TeamPlayCaptureBlockedEvent, TeamPlayFlagEventEvent, TeamPlayPointCapturedEvent,
+};
 use tf_demo_parser::demo::message::usermessage::{SayText2Message, TextMessage};
 use tf_demo_parser::demo::message::ServerInfoMessage;
+use tf_demo_parser::demo::packet::datatable::{ParseSendTable, ServerClass};
 use tf_demo_parser::demo::parser::analyser::Spawn;
+use tf_demo_parser::demo::parser::gamestateanalyser::GameStateAnalyser;
 use tf_demo_parser::ReadResult;
 use tf_demo_parser::{
     demo::{
@@ -27,8 +35,50 @@ use tf_demo_parser::{
     MessageType, ParserState, Stream,
 };
 
-#[derive(Debug, Clone, Default)]
+/// [`Class`] only has an untagged `Deserialize` impl, which the bitcode cache can't read.
+/// These read it back the same way it gets serialized.
+mod class_serde {
+    use serde::{Deserialize, Deserializer};
+    use tf_demo_parser::demo::{data::DemoTick, parser::analyser::Class};
+
+    #[derive(Deserialize)]
+    #[serde(remote = "Class", rename_all = "lowercase")]
+    enum ClassDef {
+        Other,
+        Scout,
+        Sniper,
+        Soldier,
+        Demoman,
+        Medic,
+        Heavy,
+        Pyro,
+        Spy,
+        Engineer,
+    }
+
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(with = "ClassDef")] Class);
+
+    pub fn class<'de, D: Deserializer<'de>>(d: D) -> Result<Class, D::Error> {
+        ClassDef::deserialize(d)
+    }
+
+    pub fn option<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Class>, D::Error> {
+        Ok(Option::<Wrapper>::deserialize(d)?.map(|w| w.0))
+    }
+
+    pub fn switches<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<(DemoTick, Class)>, D::Error> {
+        Ok(Vec::<(DemoTick, Wrapper)>::deserialize(d)?
+            .into_iter()
+            .map(|(t, w)| (t, w.0))
+            .collect())
+    }
+}
+
+/// Everything known about a single player, indexed by [`StableUserId`] in [`MatchState::users`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserInfo {
+    #[serde(deserialize_with = "class_serde::option")]
     pub last_class: Option<Class>,
     pub name: Option<String>,
     pub user_id: Option<UserId>,
@@ -37,7 +87,40 @@ pub struct UserInfo {
     pub last_team: Option<Team>,
 
     pub connection_events: Vec<(DemoTick, ConnectionEventType)>,
+    #[serde(deserialize_with = "class_serde::switches")]
     pub class_switches: Vec<(DemoTick, Class)>,
+    /// Damage and healing, one entry per class the player dealt or took damage or healed as
+    pub combat: Vec<CombatTotals>,
+    /// Only filled by the [`PositionAnalyser`]
+    pub positions: Vec<PositionSample>,
+}
+
+/// Damage and healing of a player while playing a class, hits and heals happen too often to keep
+/// them as events
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CombatTotals {
+    #[serde(deserialize_with = "class_serde::option")]
+    pub class: Option<Class>,
+    /// Damage dealt to other players, as reported by the server (including overkill)
+    pub damage: u32,
+    pub damage_taken: u32,
+    /// Healing done to other players
+    pub healing: u32,
+}
+
+/// State of a player at a tick, sampled from entity updates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSample {
+    pub tick: DemoTick,
+    /// World coordinates in hammer units
+    pub origin: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub health: u16,
+    #[serde(deserialize_with = "class_serde::class")]
+    pub class: Class,
+    pub team: Team,
+    pub alive: bool,
 }
 
 impl From<&tf_demo_parser::demo::data::UserInfo> for UserInfo {
@@ -52,7 +135,7 @@ impl From<&tf_demo_parser::demo::data::UserInfo> for UserInfo {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Vote {
     pub start_tick: DemoTick,
     pub end_tick: DemoTick,
@@ -69,7 +152,7 @@ impl Vote {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub enum VoteTeam {
     #[default]
     Unknown,
@@ -87,7 +170,7 @@ impl ToString for VoteTeam {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Death {
     pub weapon: String,
     pub victim: StableUserId,
@@ -101,7 +184,7 @@ pub struct Death {
     pub deadringer: bool,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub enum CritType {
     #[default]
     None,
@@ -165,7 +248,43 @@ impl Death {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UberDeployed {
+    pub medic: StableUserId,
+    pub target: Option<StableUserId>,
+}
+
+impl UberDeployed {
+    pub fn from_event(event: &PlayerChargeDeployedEvent, analyser: &mut Analyser) -> Self {
+        UberDeployed {
+            medic: analyser.user_by_id(event.user_id),
+            target: (event.target_id != 0).then(|| analyser.user_by_id(event.target_id)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicDeath {
+    pub medic: StableUserId,
+    pub killer: Option<StableUserId>,
+    /// Healing done by the medic during this life
+    pub healing: u16,
+    /// Died with a full charge, i.e. dropped uber
+    pub charged: bool,
+}
+
+impl MedicDeath {
+    pub fn from_event(event: &MedicDeathEvent, analyser: &mut Analyser) -> Self {
+        MedicDeath {
+            medic: analyser.user_by_id(event.user_id),
+            killer: (event.attacker != 0).then(|| analyser.user_by_id(event.attacker)),
+            healing: event.healing,
+            charged: event.charged,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Round {
     pub winner: Team,
     pub length: f32,
@@ -180,7 +299,126 @@ impl From<&TeamPlayRoundWinEvent> for Round {
     }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StalemateReason {
+    JoinMid,
+    Timer,
+    ServerTimelimit,
+    Unknown(u8),
+}
+
+impl From<u8> for StalemateReason {
+    fn from(reason: u8) -> Self {
+        match reason {
+            0 => StalemateReason::JoinMid,
+            1 => StalemateReason::Timer,
+            2 => StalemateReason::ServerTimelimit,
+            r => StalemateReason::Unknown(r),
+        }
+    }
+}
+
+/// A control point (or payload checkpoint) was captured
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PointCapture {
+    pub point: u8,
+    pub name: String,
+    pub team: Team,
+    pub cappers: Vec<StableUserId>,
+}
+
+impl PointCapture {
+    pub fn from_event(event: &TeamPlayPointCapturedEvent, analyser: &Analyser) -> Self {
+        // every byte of `cappers` is the entity index of one player
+        let cappers = match &event.cappers {
+            MaybeUtf8String::Valid(s) => s.as_bytes(),
+            MaybeUtf8String::Invalid(b) => b.as_slice(),
+        };
+        PointCapture {
+            point: event.cp,
+            name: event.cp_name.to_string(),
+            team: Team::new(event.team),
+            cappers: cappers
+                .iter()
+                .filter_map(|e| analyser.user_by_entity((*e as u32).into()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureBlock {
+    pub point: u8,
+    pub name: String,
+    pub blocker: Option<StableUserId>,
+    /// The capping player that got killed, if any
+    pub victim: Option<StableUserId>,
+}
+
+impl CaptureBlock {
+    pub fn from_event(event: &TeamPlayCaptureBlockedEvent, analyser: &Analyser) -> Self {
+        CaptureBlock {
+            point: event.cp,
+            name: event.cp_name.to_string(),
+            blocker: analyser.user_by_entity((event.blocker as u32).into()),
+            victim: analyser.user_by_entity((event.victim as u32).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FlagEventType {
+    PickedUp,
+    Captured,
+    Defended,
+    Dropped,
+    Returned,
+    Unknown(u16),
+}
+
+impl std::fmt::Display for FlagEventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlagEventType::PickedUp => write!(f, "picked up"),
+            FlagEventType::Captured => write!(f, "captured"),
+            FlagEventType::Defended => write!(f, "defended"),
+            FlagEventType::Dropped => write!(f, "dropped"),
+            FlagEventType::Returned => write!(f, "returned"),
+            FlagEventType::Unknown(t) => write!(f, "unknown flag event {t}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagEvent {
+    pub kind: FlagEventType,
+    pub player: Option<StableUserId>,
+    pub carrier: Option<StableUserId>,
+    /// Team the flag belongs to
+    pub team: Team,
+    pub home: bool,
+}
+
+impl FlagEvent {
+    pub fn from_event(event: &TeamPlayFlagEventEvent, analyser: &Analyser) -> Self {
+        FlagEvent {
+            kind: match event.event_type {
+                1 => FlagEventType::PickedUp,
+                2 => FlagEventType::Captured,
+                3 => FlagEventType::Defended,
+                4 => FlagEventType::Dropped,
+                5 => FlagEventType::Returned,
+                t => FlagEventType::Unknown(t),
+            },
+            player: analyser.user_by_entity((event.player as u32).into()),
+            carrier: analyser.user_by_entity((event.carrier as u32).into()),
+            team: Team::new(event.team),
+            home: event.home != 0,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ServerInfo {
     pub name: String,
     pub maxplayers: u8,
@@ -201,13 +439,13 @@ impl From<&Box<ServerInfoMessage>> for ServerInfo {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConnectionEventType {
     Join,
     Leave(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionEvent {
     pub user: StableUserId,
     pub name: String,
@@ -235,7 +473,7 @@ impl ConnectionEvent {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub kind: ChatMessageKind,
     pub from: String,
@@ -309,7 +547,8 @@ fn resolve_string(string: &str) -> &str {
     }
 }
 
-#[derive(Debug, Clone)]
+/// The kinds of events the [`Analyser`] extracts from a demo
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MatchEventType {
     Kill(Death),
     RoundEnd(Round),
@@ -317,7 +556,21 @@ pub enum MatchEventType {
     Connection(ConnectionEvent),
     VoteStarted(Vote),
     TeamSwitch(StableUserId, Team),
-    ClassSwitch(StableUserId, Class),
+    ClassSwitch(
+        StableUserId,
+        #[serde(deserialize_with = "class_serde::class")] Class,
+    ),
+    UberDeployed(UberDeployed),
+    MedicDeath(MedicDeath),
+    RoundStart {
+        full_reset: bool,
+    },
+    SetupFinished,
+    Overtime,
+    Stalemate(StalemateReason),
+    PointCaptured(PointCapture),
+    CaptureBlocked(CaptureBlock),
+    Flag(FlagEvent),
 }
 
 impl MatchEventType {
@@ -330,10 +583,21 @@ impl MatchEventType {
             MatchEventType::VoteStarted(_) => "Vote",
             MatchEventType::TeamSwitch(_, _) => "Team Switch",
             MatchEventType::ClassSwitch(_, _) => "Class Switch",
+            MatchEventType::UberDeployed(_) => "Uber",
+            MatchEventType::MedicDeath(_) => "Medic Death",
+            MatchEventType::RoundStart { .. } => "Round Start",
+            MatchEventType::SetupFinished => "Setup Finished",
+            MatchEventType::Overtime => "Overtime",
+            MatchEventType::Stalemate(_) => "Stalemate",
+            MatchEventType::PointCaptured(_) => "Point Captured",
+            MatchEventType::CaptureBlocked(_) => "Capture Blocked",
+            MatchEventType::Flag(_) => "Flag",
         }
     }
 
-    fn to_string(&self, users: &[UserInfo]) -> String {
+    /// Human readable description of the event, `users` resolves the player names
+    pub fn to_string(&self, users: &[UserInfo]) -> String {
+        let name = |id: &StableUserId| users[id.0].name.as_deref().unwrap_or("unknown");
         match self {
             MatchEventType::Kill(death) => format!(
                 "\"{}\" was killed by \"{}\" using {}{}",
@@ -385,23 +649,66 @@ impl MatchEventType {
                     .map_or("unknown", |u| u.as_str()),
                 class
             ),
+            MatchEventType::UberDeployed(uber) => match &uber.target {
+                Some(target) => format!(
+                    "\"{}\" deployed uber on \"{}\"",
+                    name(&uber.medic),
+                    name(target)
+                ),
+                None => format!("\"{}\" deployed uber", name(&uber.medic)),
+            },
+            MatchEventType::MedicDeath(death) => format!(
+                "Medic \"{}\" was killed by \"{}\" after healing {}{}",
+                name(&death.medic),
+                death.killer.as_ref().map_or("world", name),
+                death.healing,
+                if death.charged { " (dropped uber)" } else { "" }
+            ),
+            MatchEventType::RoundStart { full_reset } => format!(
+                "Round started{}",
+                if *full_reset { " (full reset)" } else { "" }
+            ),
+            MatchEventType::SetupFinished => "Setup finished".to_string(),
+            MatchEventType::Overtime => "Overtime".to_string(),
+            MatchEventType::Stalemate(reason) => format!("Stalemate ({reason:?})"),
+            MatchEventType::PointCaptured(cap) => format!(
+                "{} captured {} ({})",
+                cap.team,
+                cap.name,
+                cap.cappers.iter().map(name).join(", ")
+            ),
+            MatchEventType::CaptureBlocked(block) => format!(
+                "\"{}\" blocked the capture of {}",
+                block.blocker.as_ref().map_or("unknown", name),
+                block.name
+            ),
+            MatchEventType::Flag(flag) => format!(
+                "\"{}\" {} the {} flag",
+                flag.player.as_ref().map_or("unknown", name),
+                flag.kind,
+                flag.team
+            ),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// A [`MatchEventType`] together with the tick it happened at
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchEvent {
     pub tick: DemoTick,
     pub value: MatchEventType,
 }
 
 impl MatchEvent {
+    /// Human readable description of the event, `users` resolves the player names
     pub fn to_string(&self, users: &[UserInfo]) -> String {
         format!("{}: {}", self.tick, self.value.to_string(users))
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Index into [`MatchState::users`] that stays the same for a player across reconnects,
+/// unlike the [`UserId`] the server assigns
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct StableUserId(usize);
 
 impl From<usize> for StableUserId {
@@ -417,7 +724,14 @@ impl<T> Index<&StableUserId> for Vec<T> {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+impl<T> IndexMut<&StableUserId> for Vec<T> {
+    fn index_mut(&mut self, index: &StableUserId) -> &mut Self::Output {
+        &mut self[index.0]
+    }
+}
+
+/// Result of analysing a demo: the players, server info and all events in tick order
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct MatchState {
     pub users: Vec<UserInfo>,
     pub server_info: ServerInfo,
@@ -427,6 +741,10 @@ pub struct MatchState {
     pub events: Vec<MatchEvent>,
 }
 
+/// [`MessageHandler`] that builds a [`MatchState`] while a demo is being parsed.
+///
+/// Usually used through [`crate::demo_manager::Demo::full_analysis`], or directly with
+/// [`tf_demo_parser::DemoParser::new_with_analyser`].
 #[derive(Default, Debug)]
 pub struct Analyser {
     state: MatchState,
@@ -523,6 +841,45 @@ impl Analyser {
             .into()
     }
 
+    /// Looks up a player by the [`UserId`] the server currently assigns to them
+    fn user_by_id(&mut self, user_id: u16) -> StableUserId {
+        self.stable_user(
+            |u| u.user_id == Some(user_id.into()),
+            || UserInfo {
+                user_id: Some(user_id.into()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Totals of the player for the class they are currently playing
+    fn combat(&mut self, user: StableUserId) -> &mut CombatTotals {
+        let user = &mut self.state.users[&user];
+        let class = user.class_switches.last().map(|(_, c)| *c);
+        match user.combat.iter().position(|c| c.class == class) {
+            Some(i) => &mut user.combat[i],
+            None => {
+                user.combat.push(CombatTotals {
+                    class,
+                    ..Default::default()
+                });
+                user.combat.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Looks up a player by their entity index, `None` if nobody has it
+    fn user_by_entity(&self, entity: EntityId) -> Option<StableUserId> {
+        if entity == 0 {
+            return None;
+        }
+        self.state
+            .users
+            .iter()
+            .position(|u| u.entity_id == Some(entity))
+            .map(Into::into)
+    }
+
     fn handle_user_message(&mut self, message: &UserMessage, tick: DemoTick) {
         match message {
             UserMessage::SayText2(text_message) => {
@@ -572,6 +929,72 @@ impl Analyser {
                 let value = MatchEventType::Kill(Death::from_event(event, self));
                 self.state.events.push(MatchEvent { tick: tick, value });
             }
+            GameEvent::PlayerHurt(event) => {
+                let victim = self.user_by_id(event.user_id);
+                let amount = event.damage_amount as u32;
+                self.combat(victim).damage_taken += amount;
+                if event.attacker != 0 {
+                    let attacker = self.user_by_id(event.attacker);
+                    if attacker != victim {
+                        self.combat(attacker).damage += amount;
+                    }
+                }
+            }
+            GameEvent::PlayerHealed(event) => {
+                let patient = self.user_by_id(event.patient);
+                if event.healer != 0 {
+                    let healer = self.user_by_id(event.healer);
+                    if healer != patient {
+                        self.combat(healer).healing += event.amount as u32;
+                    }
+                }
+            }
+            GameEvent::PlayerChargeDeployed(event) => {
+                let value = MatchEventType::UberDeployed(UberDeployed::from_event(event, self));
+                self.state.events.push(MatchEvent { tick, value });
+            }
+            GameEvent::MedicDeath(event) => {
+                let value = MatchEventType::MedicDeath(MedicDeath::from_event(event, self));
+                self.state.events.push(MatchEvent { tick, value });
+            }
+            GameEvent::TeamPlayRoundStart(event) => {
+                self.state.events.push(MatchEvent {
+                    tick,
+                    value: MatchEventType::RoundStart {
+                        full_reset: event.full_reset,
+                    },
+                });
+            }
+            GameEvent::TeamPlaySetupFinished(_) => {
+                self.state.events.push(MatchEvent {
+                    tick,
+                    value: MatchEventType::SetupFinished,
+                });
+            }
+            GameEvent::TeamPlayOvertimeBegin(_) => {
+                self.state.events.push(MatchEvent {
+                    tick,
+                    value: MatchEventType::Overtime,
+                });
+            }
+            GameEvent::TeamPlayRoundStalemate(event) => {
+                self.state.events.push(MatchEvent {
+                    tick,
+                    value: MatchEventType::Stalemate(event.reason.into()),
+                });
+            }
+            GameEvent::TeamPlayPointCaptured(event) => {
+                let value = MatchEventType::PointCaptured(PointCapture::from_event(event, self));
+                self.state.events.push(MatchEvent { tick, value });
+            }
+            GameEvent::TeamPlayCaptureBlocked(event) => {
+                let value = MatchEventType::CaptureBlocked(CaptureBlock::from_event(event, self));
+                self.state.events.push(MatchEvent { tick, value });
+            }
+            GameEvent::TeamPlayFlagEvent(event) => {
+                let value = MatchEventType::Flag(FlagEvent::from_event(event, self));
+                self.state.events.push(MatchEvent { tick, value });
+            }
             GameEvent::TeamPlayRoundWin(event) => {
                 self.state.events.push(MatchEvent {
                     tick,
@@ -761,3 +1184,92 @@ impl Analyser {
         Ok(())
     }
 }
+
+/// [`Analyser`] that additionally samples the state of every player from entity updates every
+/// `interval` ticks into [`UserInfo::positions`].
+///
+/// Parsing the entities makes the analysis quite a bit slower, that's why this is a separate handler.
+#[derive(Default, Debug)]
+pub struct PositionAnalyser {
+    analyser: Analyser,
+    entities: GameStateAnalyser,
+    interval: u32,
+    next_sample: DemoTick,
+}
+
+impl PositionAnalyser {
+    pub fn new(interval: u32) -> Self {
+        Self {
+            interval: interval.max(1),
+            ..Default::default()
+        }
+    }
+
+    fn sample(&mut self, tick: DemoTick) {
+        for player in &self.entities.state.players {
+            if !player.in_pvs || player.class == Class::Other {
+                continue;
+            }
+            let Some(suid) = self.analyser.user_by_entity(player.entity) else {
+                continue;
+            };
+            self.analyser.state.users[&suid]
+                .positions
+                .push(PositionSample {
+                    tick,
+                    origin: [player.position.x, player.position.y, player.position.z],
+                    yaw: player.view_angle,
+                    pitch: player.pitch_angle,
+                    health: player.health,
+                    class: player.class,
+                    team: player.team,
+                    alive: player.state == PlayerState::Alive,
+                });
+        }
+    }
+}
+
+impl MessageHandler for PositionAnalyser {
+    type Output = MatchState;
+
+    fn does_handle(message_type: MessageType) -> bool {
+        Analyser::does_handle(message_type) || message_type == MessageType::PacketEntities
+    }
+
+    fn handle_message(&mut self, message: &Message, tick: DemoTick, parser_state: &ParserState) {
+        if let Message::PacketEntities(_) = message {
+            self.entities.handle_message(message, tick, parser_state);
+            if tick >= self.next_sample {
+                self.sample(tick);
+                self.next_sample = tick + self.interval;
+            }
+        } else {
+            self.analyser.handle_message(message, tick, parser_state);
+        }
+    }
+
+    fn handle_string_entry(
+        &mut self,
+        table: &str,
+        index: usize,
+        entry: &StringTableEntry,
+        parser_state: &ParserState,
+    ) {
+        self.analyser
+            .handle_string_entry(table, index, entry, parser_state);
+    }
+
+    fn handle_data_tables(
+        &mut self,
+        tables: &[ParseSendTable],
+        server_classes: &[ServerClass],
+        parser_state: &ParserState,
+    ) {
+        self.entities
+            .handle_data_tables(tables, server_classes, parser_state);
+    }
+
+    fn into_output(self, state: &ParserState) -> Self::Output {
+        self.analyser.into_output(state)
+    }
+}
@@ -0,0 +1,20 @@
+//! The command line interface without the GUI, builds without GTK (`--no-default-features`)
+use clap::{CommandFactory, FromArgMatches};
+use tf2_demo_player::{cli, paths};
+
+#[async_std::main]
+async fn main() {
+    let matches = cli::Cli::command()
+        .name("tf2-demo-cli")
+        .about("Manage TF2 demos from the command line")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .get_matches();
+    let cli = cli::Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(dir) = cli.config_dir {
+        paths::set_override(dir);
+    }
+    if let Some(command) = cli.command {
+        cli::run_and_exit(command).await;
+    }
+}
@@ -0,0 +1,357 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use regex::Regex;
+
+use simplelog::{Config, TermLogger};
+
+use crate::{
+    demo_manager::{self, Demo, DemoManager, Event, LibraryOptions},
+    export::DemoExport,
+    highlights, paths,
+    search::{self, Query, SearchIndex},
+    settings::Settings,
+};
+
+/// Manage and play back TF2 demos, starts the GUI if no subcommand is given
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+    /// Keep settings, caches and the log in this folder instead of the user directories
+    #[arg(long, global = true, value_name = "DIR")]
+    pub config_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// List the demos in a folder (defaults to the configured demo folder)
+    List {
+        folder: Option<PathBuf>,
+        /// List all demos of the library instead, with the folder they are in
+        #[arg(long, conflicts_with = "folder")]
+        library: bool,
+    },
+    /// Parse a demo and print its events
+    Inspect {
+        demo: PathBuf,
+        /// Print the full analysis as json instead
+        #[arg(long)]
+        json: bool,
+        /// Also sample player positions every TICKS ticks (included in the json output)
+        #[arg(long, value_name = "TICKS")]
+        positions: Option<u32>,
+    },
+    /// Print the bookmarks of a demo
+    Bookmarks { demo: PathBuf },
+    /// Detect highlights of the recording player (multi-kills, killstreaks,...)
+    Highlights {
+        demo: PathBuf,
+        /// Add the highlights to the bookmarks of the demo
+        #[arg(long)]
+        save: bool,
+    },
+    /// Analyse the demos of the library and add them to the search index
+    Index {
+        /// Index this folder and its subfolders instead (defaults to the library, or the
+        /// configured demo folder if the library has no folders)
+        folder: Option<PathBuf>,
+        /// Also reanalyse demos that are already indexed
+        #[arg(long)]
+        force: bool,
+    },
+    /// Search all indexed demos, every given filter has to match
+    Search {
+        /// Steam ID in any format or part of a player name
+        #[arg(long)]
+        player: Option<String>,
+        /// Part of the map name
+        #[arg(long)]
+        map: Option<String>,
+        /// Regex chat messages have to match
+        #[arg(long, value_name = "REGEX")]
+        chat: Option<String>,
+        /// Part of a weapon name, only counts kills of --player if given
+        #[arg(long)]
+        weapon: Option<String>,
+    },
+    /// Convert a demo to a replay
+    ConvertReplay {
+        demo: PathBuf,
+        /// Title to save the replay under (defaults to the file name)
+        #[arg(short, long)]
+        title: Option<String>,
+        /// Replay folder to use instead of the one in the configured TF2 folder
+        #[arg(long)]
+        replays_folder: Option<PathBuf>,
+    },
+    /// Delete unfinished or unmarked demos and obsolete replays
+    Clean {
+        /// Demo folder to clean (defaults to the configured demo folder)
+        folder: Option<PathBuf>,
+        /// Delete demos that are empty or couldn't be read
+        #[arg(long)]
+        unfinished: bool,
+        /// Delete demos without bookmarks, notes, tags or rating
+        #[arg(long)]
+        unmarked: bool,
+        /// Delete replays whose demo file no longer exists
+        #[arg(long)]
+        replays: bool,
+        /// Only print what would be deleted
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+}
+
+/// Runs the command with warnings logged to stderr, exits with 1 if it fails
+pub async fn run_and_exit(command: CliCommand) -> ! {
+    TermLogger::init(
+        log::LevelFilter::Warn,
+        Config::default(),
+        simplelog::TerminalMode::Stderr,
+        simplelog::ColorChoice::Auto,
+    )
+    .unwrap();
+    paths::migrate();
+    if let Err(e) = run(command).await {
+        eprintln!("Error: {e:#}");
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+pub async fn run(command: CliCommand) -> Result<()> {
+    let settings = Settings::load();
+    match command {
+        CliCommand::List { folder, library } => {
+            let manager = if library {
+                let manager = Mutex::new(DemoManager::new());
+                DemoManager::load_library(&manager, &settings.library, |_, _, _| {});
+                manager.into_inner().unwrap()
+            } else {
+                load_folder(folder.or(settings.demo_folder_path))?
+            };
+            let mut demos: Vec<&Demo> = manager.get_demos().values().collect();
+            demos.sort_by(|a, b| a.path.cmp(&b.path));
+            for demo in demos {
+                let header = demo.header.as_ref();
+                let name = if library {
+                    demo.path.display().to_string()
+                } else {
+                    demo.filename.clone()
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    name,
+                    header.map_or("", |h| &h.map),
+                    header.map_or("", |h| &h.nick),
+                    crate::util::sec_to_timestamp(header.map_or(0.0, |h| h.duration)),
+                    demo.events.len()
+                );
+            }
+        }
+        CliCommand::Inspect {
+            demo,
+            json,
+            positions,
+        } => {
+            let mut demo = read_demo(demo)?;
+            let state = match positions {
+                Some(interval) => demo.full_analysis_with_positions(interval).await?,
+                None => demo.full_analysis().await?,
+            };
+            if json {
+                println!("{}", DemoExport::new(Some(&demo), &state).to_json()?);
+                return Ok(());
+            }
+            println!("Server: {}", state.server_info.name);
+            if let Some(header) = &demo.header {
+                println!("Map: {}", header.map);
+                println!("Recording user: {}", header.nick);
+            }
+            println!("Players: {}", state.users.len());
+            for event in &state.events {
+                println!("{}", event.to_string(&state.users));
+            }
+        }
+        CliCommand::Bookmarks { demo } => {
+            let demo = read_demo(demo)?;
+            for event in &demo.events {
+                println!("{}\t{}\t{}", event.tick, event.ev_type, event.title);
+            }
+            if !demo.tags.is_empty() {
+                println!("\nTags: {}", demo.tags.join(", "));
+            }
+            if let Some(rating) = demo.rating {
+                println!("Rating: {rating}/{}", demo_manager::MAX_RATING);
+            }
+            if let Some(notes) = &demo.notes {
+                println!("\n{notes}");
+            }
+        }
+        CliCommand::Highlights { demo, save } => {
+            let mut demo = read_demo(demo)?;
+            let state = demo.full_analysis().await?;
+            let Some(user) = highlights::recording_user(&demo, &state) else {
+                bail!("Couldn't find the recording player, highlights only work for POV demos");
+            };
+            let found: Vec<Event> =
+                highlights::detect(&state, user, demo.tps(), &settings.highlights)
+                    .into_iter()
+                    .filter(|h| !demo.events.contains(h))
+                    .collect();
+            for event in &found {
+                println!("{}\t{}", event.tick, event.title);
+            }
+            if save && !found.is_empty() {
+                demo.events.extend(found);
+                demo.events.sort_by_key(|e| e.tick);
+                demo.save_json().await;
+            }
+        }
+        CliCommand::Index { folder, force } => {
+            let demos = match folder {
+                Some(folder) => LibraryOptions {
+                    folders: vec![folder],
+                    ..Default::default()
+                }
+                .find_demos(),
+                None => {
+                    if settings.library.folders.is_empty() && settings.demo_folder_path.is_none() {
+                        bail!("No demo folder given and no library or demo folder configured");
+                    }
+                    search::library_demos(&settings.library, settings.demo_folder_path.as_deref())
+                }
+            };
+            let index = Mutex::new(SearchIndex::load());
+            let indexed = search::index_demos(&index, demos, force, |current, total| {
+                eprint!("\r{current}/{total}");
+            })?;
+            eprintln!();
+            println!(
+                "Indexed {indexed} demos, {} in total",
+                index.lock().unwrap().len()
+            );
+        }
+        CliCommand::Search {
+            player,
+            map,
+            chat,
+            weapon,
+        } => {
+            let query = Query {
+                player,
+                map,
+                chat: chat.as_deref().map(Regex::new).transpose()?,
+                weapon,
+            };
+            if query.is_empty() {
+                bail!("Nothing to search for, pass --player, --map, --chat and/or --weapon");
+            }
+            for result in SearchIndex::load().search(&query) {
+                println!("{}\t{}", result.demo.path.display(), result.demo.map);
+                for m in &result.matches {
+                    println!(
+                        "\t{}\t{}",
+                        m.tick.map_or(String::new(), |t| {
+                            crate::util::ticks_to_timestamp(t, result.demo.tps)
+                        }),
+                        m.text
+                    );
+                }
+            }
+        }
+        CliCommand::ConvertReplay {
+            demo,
+            title,
+            replays_folder,
+        } => {
+            let mut demo = read_demo(demo)?;
+            if demo.header.is_none() {
+                bail!("Couldn't read demo header of {}", demo.path.display());
+            }
+            let Some(replays_folder) = replays_folder.or_else(|| settings.replays_folder()) else {
+                bail!("TF2 folder path not set up, pass --replays-folder");
+            };
+            let replays_folder: async_std::path::PathBuf = replays_folder.into();
+            if !replays_folder.is_dir().await {
+                bail!(
+                    "Replay folder {} does not exist or cannot be accessed",
+                    replays_folder.display()
+                );
+            }
+            if demo.has_replay(&replays_folder).await {
+                bail!("Demo already converted");
+            }
+            let title = title.unwrap_or_else(|| demo.filename.clone());
+            demo.convert_to_replay(&replays_folder, &title).await?;
+            println!("Replay created successfully");
+        }
+        CliCommand::Clean {
+            folder,
+            unfinished,
+            unmarked,
+            replays,
+            dry_run,
+        } => {
+            if !unfinished && !unmarked && !replays {
+                bail!("Nothing to clean, pass --unfinished, --unmarked and/or --replays");
+            }
+            if unfinished || unmarked {
+                let mut manager = load_folder(folder.or(settings.demo_folder_path.clone()))?;
+                let mut paths = Vec::new();
+                if unfinished {
+                    paths.extend(manager.empty_demos());
+                }
+                if unmarked {
+                    paths.extend(manager.unmarked_demos());
+                }
+                paths.sort();
+                paths.dedup();
+                for path in paths {
+                    println!("{}", path.file_name().unwrap_or_default().to_string_lossy());
+                    if !dry_run {
+                        manager.delete_demo(&path).await;
+                    }
+                }
+            }
+            if replays {
+                let Some(replays_folder) = settings.replays_folder() else {
+                    bail!("TF2 folder path not set up");
+                };
+                let obsoletes = crate::util::find_obsolete_replays(replays_folder).await?;
+                for dmx in &obsoletes {
+                    println!("{}", dmx.display());
+                }
+                if !dry_run && !obsoletes.is_empty() {
+                    async_std::task::spawn_blocking(|| trash::delete_all(obsoletes)).await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn load_folder(folder: Option<PathBuf>) -> Result<DemoManager> {
+    let Some(folder) = folder else {
+        bail!("No demo folder given and none configured");
+    };
+    if !folder.is_dir() {
+        bail!("{} is not a folder", folder.display());
+    }
+    let manager = Mutex::new(DemoManager::new());
+    DemoManager::load_demos(&manager, &folder, |_, _, _| {})?;
+    Ok(manager.into_inner().unwrap())
+}
+
+fn read_demo(path: PathBuf) -> Result<Demo> {
+    if !path.is_file() {
+        bail!("{} is not a file", path.display());
+    }
+    let mut demo = Demo::new(path);
+    demo.read_data();
+    Ok(demo)
+}
@@ -4,18 +4,59 @@ use chrono::{Datelike, Timelike};
 use glob::glob;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc, time::SystemTime};
+use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 use std::{fs, io::Read};
 use tf_demo_parser::demo::header::Header;
+use tf_demo_parser::demo::parser::MessageHandler;
+
+use crate::analyser::MatchState;
+use crate::paths;
 use trash;
 
+/// Folder with one file per analysed demo, in the cache directory next to [`DEMO_CACHE_FILE`]
+const ANALYSIS_CACHE_FOLDER: &str = "analysis_cache";
+const DEMO_CACHE_FILE: &str = "demos.cache";
+/// Bump when [`MatchState`] changes, analyses cached by older versions are ignored
+const ANALYSIS_CACHE_VERSION: u32 = 2;
+/// Highest star rating of a demo
+pub const MAX_RATING: u8 = 5;
+/// Demos written to more recently than this are considered to still be recording
+pub const RECORDING_IDLE: Duration = Duration::from_secs(5);
+/// How often demos read while loading a folder are handed out
+const LOAD_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A [`MatchState`] cached on disk, only valid as long as the demo file is unchanged
+#[derive(Serialize, Deserialize)]
+struct CachedAnalysis<S> {
+    version: u32,
+    path: std::path::PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+    /// Interval positions were sampled with, 0 if they weren't
+    position_interval: u32,
+    state: S,
+}
+
 #[derive(Serialize, Deserialize)]
 struct EventContainer {
     events: Vec<Event>,
     #[serde(skip_serializing_if = "Option::is_none")]
     notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rating: Option<u8>,
 }
 
+/// A bookmark, stored in the json file next to the demo in the same format as the in-game demo tools
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct Event {
     pub tick: u32,
@@ -25,6 +66,7 @@ pub struct Event {
     pub ev_type: String,
 }
 
+/// A demo file together with its header, bookmarks, notes, tags and rating
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Demo {
     pub path: std::path::PathBuf,
@@ -32,8 +74,19 @@ pub struct Demo {
     pub header: Option<Header>,
     pub events: Vec<Event>,
     pub notes: Option<String>,
+    /// Free-form tags, sorted and without duplicates
+    pub tags: Vec<String>,
+    /// 1 to 5 stars
+    pub rating: Option<u8>,
     pub created: Option<SystemTime>,
     pub size: Option<u64>,
+    /// Modification times of the demo and bookmark file when they were read, to tell if a cached
+    /// copy is still valid
+    pub modified: Option<SystemTime>,
+    pub bookmarks_modified: Option<SystemTime>,
+    /// The game is still writing the demo, so the header isn't final yet
+    #[serde(skip)]
+    pub recording: bool,
     #[serde(skip)]
     pub inspection: Option<Arc<crate::analyser::MatchState>>,
 }
@@ -48,12 +101,18 @@ impl Demo {
             header: None,
             events: Vec::new(),
             notes: None,
+            tags: Vec::new(),
+            rating: None,
             created: None,
             size: None,
+            modified: None,
+            bookmarks_modified: None,
+            recording: false,
             inspection: None,
         }
     }
 
+    /// Reads the header, bookmark file and file metadata, does nothing if the header is already loaded
     pub fn read_data(&mut self) {
         if let Some(_) = self.header {
             return;
@@ -80,6 +139,7 @@ impl Demo {
         let mut bookmark_file = self.path.clone();
         bookmark_file.set_extension("json");
 
+        self.bookmarks_modified = fs::metadata(&bookmark_file).and_then(|m| m.modified()).ok();
         let file = fs::read(bookmark_file);
         if let Ok(char_bytes) = file {
             match serde_json::from_slice::<EventContainer>(&char_bytes) {
@@ -87,6 +147,8 @@ impl Demo {
                     self.events = parsed.events;
                     self.events.sort_by_key(|e| e.tick);
                     self.notes = parsed.notes;
+                    self.tags = parse_tags(parsed.tags.iter().map(String::as_str));
+                    self.rating = parsed.rating.filter(|r| (1..=MAX_RATING).contains(r));
                 }
                 Err(e) => log::warn!(
                     "Failed to parse event file for {}, {}",
@@ -103,22 +165,125 @@ impl Demo {
             .ok();
 
         self.size = meta.as_ref().map(|m| m.len());
+        self.modified = meta.as_ref().and_then(|m| m.modified().ok());
+        self.recording = self
+            .modified
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|t| t < RECORDING_IDLE);
         self.created = meta.and_then(|m| m.created().ok());
     }
 
-    pub async fn full_analysis(&mut self) -> Result<Arc<crate::analyser::MatchState>> {
-        let f = async_std::fs::read(&self.path).await?;
-        let demo = tf_demo_parser::Demo::new(&f);
-        let parser = tf_demo_parser::DemoParser::new_with_analyser(
-            demo.get_stream(),
-            crate::analyser::Analyser::new(),
-        );
+    /// Whether the demo has bookmarks, notes, tags or a rating
+    pub fn is_marked(&self) -> bool {
+        !self.events.is_empty()
+            || self.notes.as_ref().is_some_and(|n| !n.is_empty())
+            || !self.tags.is_empty()
+            || self.rating.is_some()
+    }
+
+    /// Whether the demo and bookmark file are unchanged since they were read
+    pub fn is_current(&self) -> bool {
+        let meta = fs::metadata(&self.path).ok();
+        let mut bookmark_file = self.path.clone();
+        bookmark_file.set_extension("json");
+        meta.as_ref().map(|m| m.len()) == self.size
+            && meta.and_then(|m| m.modified().ok()) == self.modified
+            && fs::metadata(bookmark_file).and_then(|m| m.modified()).ok()
+                == self.bookmarks_modified
+    }
 
-        let (_, state) = parser.parse()?;
+    /// Parses the whole demo and stores the result in [`Demo::inspection`], a cached analysis
+    /// is used if the demo didn't change since
+    pub async fn full_analysis(&mut self) -> Result<Arc<MatchState>> {
+        self.analyse(crate::analyser::Analyser::new(), None).await
+    }
+
+    /// Like [`Demo::full_analysis`], but also samples player positions every `interval` ticks
+    pub async fn full_analysis_with_positions(&mut self, interval: u32) -> Result<Arc<MatchState>> {
+        self.analyse(
+            crate::analyser::PositionAnalyser::new(interval),
+            Some(interval),
+        )
+        .await
+    }
+
+    async fn analyse(
+        &mut self,
+        analyser: impl MessageHandler<Output = MatchState>,
+        position_interval: Option<u32>,
+    ) -> Result<Arc<MatchState>> {
+        if let Some(state) = self.load_cached_analysis(position_interval).await {
+            self.inspection = Some(Arc::new(state));
+            return Ok(self.inspection.as_ref().unwrap().clone());
+        }
+
+        let f = async_std::fs::read(&self.path).await?;
+        let state = {
+            let demo = tf_demo_parser::Demo::new(&f);
+            let parser = tf_demo_parser::DemoParser::new_with_analyser(demo.get_stream(), analyser);
+            parser.parse()?.1
+        };
+        self.save_cached_analysis(&state, position_interval.unwrap_or(0))
+            .await;
         self.inspection = Some(Arc::new(state));
         Ok(self.inspection.as_ref().unwrap().clone())
     }
 
+    fn analysis_cache_file(&self) -> std::path::PathBuf {
+        paths::cache_file(ANALYSIS_CACHE_FOLDER).join(analysis_cache_name(&self.path))
+    }
+
+    /// The cached analysis if the demo file is unchanged, with positions sampled at
+    /// `position_interval` if it is set
+    async fn load_cached_analysis(&self, position_interval: Option<u32>) -> Option<MatchState> {
+        let meta = async_std::fs::metadata(&self.path).await.ok()?;
+        let data = async_std::fs::read(self.analysis_cache_file()).await.ok()?;
+        let cached: CachedAnalysis<MatchState> = bitcode::deserialize(&data)
+            .inspect_err(|e| {
+                log::info!(
+                    "Discarding unreadable cached analysis of {}, {}",
+                    self.path.display(),
+                    e
+                )
+            })
+            .ok()?;
+        let valid = cached.version == ANALYSIS_CACHE_VERSION
+            && cached.path == std::path::absolute(&self.path).ok()?
+            && cached.size == meta.len()
+            && cached.modified == meta.modified().ok()
+            && position_interval.is_none_or(|i| i == cached.position_interval);
+        valid.then_some(cached.state)
+    }
+
+    async fn save_cached_analysis(&self, state: &MatchState, position_interval: u32) {
+        let res = async {
+            let meta = async_std::fs::metadata(&self.path).await?;
+            let cached = CachedAnalysis {
+                version: ANALYSIS_CACHE_VERSION,
+                path: std::path::absolute(&self.path)?,
+                size: meta.len(),
+                modified: meta.modified().ok(),
+                position_interval,
+                state,
+            };
+            async_std::fs::create_dir_all(paths::cache_file(ANALYSIS_CACHE_FOLDER)).await?;
+            async_std::fs::write(self.analysis_cache_file(), bitcode::serialize(&cached)?).await?;
+            anyhow::Ok(())
+        };
+        if let Err(e) = res.await {
+            log::warn!(
+                "Failed to cache analysis of {}, {:?}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    /// Removes the cached analysis, e.g. after the demo was deleted
+    pub async fn remove_cached_analysis(&self) {
+        let _ = async_std::fs::remove_file(self.analysis_cache_file()).await;
+    }
+
     pub async fn has_replay(&self, replays_folder: &async_std::path::Path) -> bool {
         return replays_folder.join(&self.filename).exists().await;
     }
@@ -127,6 +292,8 @@ impl Demo {
         self.path.display().to_string()
     }
 
+    /// Writes bookmarks, notes, tags and rating to the json file next to the demo, deletes it if all
+    /// of them are empty
     pub async fn save_json(&self) {
         let mut bookmark_file = self.path.clone();
         bookmark_file.set_extension("json");
@@ -137,7 +304,7 @@ impl Demo {
                 notes = None;
             }
         }
-        if notes.is_none() && self.events.is_empty() {
+        if !self.is_marked() {
             let _ = fs::remove_file(&bookmark_file).inspect_err(|e| {
                 log::info!(
                     "Couldn't delete bookmark file {}, {}",
@@ -154,6 +321,8 @@ impl Demo {
         let container = EventContainer {
             events: events,
             notes: notes,
+            tags: self.tags.clone(),
+            rating: self.rating,
         };
         let json = serde_json::to_string_pretty(&container).unwrap();
 
@@ -166,6 +335,7 @@ impl Demo {
         });
     }
 
+    /// Ticks per second calculated from the header, falls back to [`Demo::TICKRATE`]
     pub fn tps(&self) -> f32 {
         self.header
             .as_ref()
@@ -174,6 +344,7 @@ impl Demo {
             .unwrap_or(Demo::TICKRATE)
     }
 
+    /// Copies the demo into the replay folder and creates the dmx file TF2 needs to list it as a replay
     pub async fn convert_to_replay(
         &mut self,
         replays_folder: &async_std::path::Path,
@@ -242,19 +413,236 @@ async fn create_replay_index_file(replay_folder: &async_std::path::Path) -> Resu
     Ok(())
 }
 
+/// Folders that together make up the demo library, each scanned recursively
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryOptions {
+    pub folders: Vec<PathBuf>,
+    /// Glob patterns, matched against the path relative to the library folder (`*` also matches
+    /// `/`), a demo has to match one of them. Empty includes all demos
+    pub include: Vec<String>,
+    /// Demos matching any of these patterns are left out, e.g. `*/old/*`
+    pub exclude: Vec<String>,
+}
+
+impl LibraryOptions {
+    /// All demos in the library folders and their subfolders that pass the patterns
+    pub fn find_demos(&self) -> Vec<PathBuf> {
+        let filter = self.filter();
+        let mut demos = Vec::new();
+        for folder in self.roots() {
+            let mut found = Vec::new();
+            find_demos(&folder, &mut found);
+            demos.extend(
+                found
+                    .into_iter()
+                    .filter(|path| filter.matches(&folder, path)),
+            );
+        }
+        demos.sort();
+        demos.dedup();
+        demos
+    }
+
+    /// Whether the demo is in one of the library folders and passes the patterns
+    pub fn includes(&self, path: &Path) -> bool {
+        let filter = self.filter();
+        self.roots()
+            .into_iter()
+            .any(|folder| path.starts_with(&folder) && filter.matches(&folder, path))
+    }
+
+    /// The library folders as absolute paths, like the demo paths
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.folders
+            .iter()
+            .filter_map(|f| std::path::absolute(f).ok())
+            .collect()
+    }
+
+    fn filter(&self) -> PatternFilter {
+        let patterns = |patterns: &[String]| -> Vec<glob::Pattern> {
+            patterns
+                .iter()
+                .filter(|p| !p.trim().is_empty())
+                .filter_map(|p| {
+                    glob::Pattern::new(p.trim())
+                        .inspect_err(|e| log::warn!("Invalid library pattern {p}, {e}"))
+                        .ok()
+                })
+                .collect()
+        };
+        PatternFilter {
+            include: patterns(&self.include),
+            exclude: patterns(&self.exclude),
+        }
+    }
+}
+
+struct PatternFilter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl PatternFilter {
+    fn matches(&self, folder: &Path, path: &Path) -> bool {
+        let relative = path.strip_prefix(folder).unwrap_or(path);
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(relative)))
+            && !self.exclude.iter().any(|p| p.matches_path(relative))
+    }
+}
+
+/// Reads the demos on worker threads, `batch_cb` is called with the demos read since its last
+/// call every [`LOAD_BATCH_INTERVAL`]
+fn read_demos(paths: &[PathBuf], mut batch_cb: impl FnMut(Vec<Demo>)) {
+    let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..threads.min(paths.len()) {
+            let (sender, next) = (sender.clone(), &next);
+            scope.spawn(move || {
+                while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let mut demo = Demo::new(path);
+                    demo.read_data();
+                    if sender.send(demo).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut batch = Vec::new();
+        let mut last_batch = Instant::now();
+        for demo in receiver {
+            batch.push(demo);
+            if last_batch.elapsed() >= LOAD_BATCH_INTERVAL {
+                batch_cb(std::mem::take(&mut batch));
+                last_batch = Instant::now();
+            }
+        }
+        if !batch.is_empty() {
+            batch_cb(batch);
+        }
+    });
+}
+
+/// Collects the demos in `folder` and all of its subfolders, symlinked folders are skipped to
+/// avoid loops
+/// Name of the cached analysis of a demo, a hash of its absolute path that stays the same across
+/// builds and platforms (64 bit FNV-1a)
+fn analysis_cache_name(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let hash = path
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}.bin")
+}
+
+/// Removes the cached analyses in `folder` that don't belong to any of `demos`
+fn prune_analysis_cache<'a>(folder: &Path, demos: impl IntoIterator<Item = &'a PathBuf>) {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return;
+    };
+    let keep: std::collections::HashSet<String> =
+        demos.into_iter().map(|d| analysis_cache_name(d)).collect();
+    for entry in entries.flatten() {
+        if !keep.contains(entry.file_name().to_string_lossy().as_ref()) {
+            if let Err(e) = fs::remove_file(entry.path()) {
+                log::warn!("Failed to remove {}, {}", entry.path().display(), e);
+            }
+        }
+    }
+}
+
+fn find_demos(folder: &Path, demos: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Couldn't read folder {}, {}", folder.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => find_demos(&path, demos),
+            Ok(_) if path.extension().is_some_and(|e| e == "dem") => demos.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// Trimmed, sorted tags without empty ones or duplicates. Tags containing commas are split, so
+/// joined tags can be split again
+pub fn parse_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .flat_map(|t| t.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_owned)
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Name of the folder a demo is in, relative to the library folder it was found in
+pub fn folder_label(path: &Path, roots: &[PathBuf]) -> String {
+    let Some(parent) = path.parent() else {
+        return String::new();
+    };
+    let root = roots
+        .iter()
+        .filter(|r| parent.starts_with(r))
+        .max_by_key(|r| r.as_os_str().len());
+    match root {
+        Some(root) => {
+            let mut label = PathBuf::from(root.file_name().unwrap_or(root.as_os_str()));
+            let relative = parent.strip_prefix(root).unwrap();
+            if !relative.as_os_str().is_empty() {
+                label.push(relative);
+            }
+            label.display().to_string()
+        }
+        None => parent.display().to_string(),
+    }
+}
+
+/// The demos of the currently opened folder or library, keyed by path and backed by a cache of
+/// already read headers
 #[derive(Clone)]
 pub struct DemoManager {
-    cache: HashMap<std::path::PathBuf, Demo>,
-    demos: HashMap<String, Demo>,
+    cache: HashMap<PathBuf, Demo>,
+    demos: HashMap<PathBuf, Demo>,
+    /// Folders the current demos were loaded from
+    roots: Vec<PathBuf>,
+    /// Options of the open library, `None` while a single folder is open
+    library: Option<LibraryOptions>,
+    /// Counts the loads, demos read by an earlier one that is still running are dropped
+    load_id: u64,
+}
+
+impl Default for DemoManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DemoManager {
     pub fn new() -> Self {
         let cache = (|| {
-            if !std::fs::exists("demos.cache")? {
+            let file = paths::cache_file(DEMO_CACHE_FILE);
+            if !std::fs::exists(&file)? {
                 Ok(HashMap::new())
             } else {
-                let data = std::fs::read("demos.cache")?;
+                let data = std::fs::read(file)?;
                 Ok(bitcode::deserialize(&data)?)
             }
         })()
@@ -265,63 +653,223 @@ impl DemoManager {
         Self {
             cache: cache,
             demos: HashMap::new(),
+            roots: Vec::new(),
+            library: None,
+            load_id: 0,
         }
     }
 
     pub fn clear(&mut self) {
         self.demos.clear();
+        self.roots.clear();
+        self.library = None;
     }
 
+    /// Loads all demos in `folder_path`, `progress_cb` is called with (current, total, demos read
+    /// since the last call). `manager` is only locked for short moments, so it can be used while
+    /// loading, e.g. to show the demos read so far
     pub fn load_demos(
-        &mut self,
-        folder_path: impl Into<std::path::PathBuf>,
-        progress_cb: impl Fn(usize, usize),
+        manager: &Mutex<Self>,
+        folder_path: impl Into<PathBuf>,
+        progress_cb: impl FnMut(usize, usize, Vec<Demo>),
+    ) -> anyhow::Result<()> {
+        let folder_path: PathBuf = std::path::absolute(folder_path.into())?;
+        let pattern = format!(
+            "{}/*.dem",
+            glob::Pattern::escape(&folder_path.to_string_lossy())
+        );
+        let paths = glob(&pattern)?.filter_map(Result::ok).collect();
+        Self::load(manager, paths, vec![folder_path], None, progress_cb);
+        Ok(())
+    }
+
+    /// Loads all demos of the library, like [`Self::load_demos`]
+    pub fn load_library(
+        manager: &Mutex<Self>,
+        options: &LibraryOptions,
+        progress_cb: impl FnMut(usize, usize, Vec<Demo>),
     ) {
-        let folder_path: std::path::PathBuf = std::path::absolute(&folder_path.into()).unwrap();
-        self.demos.clear();
-        for path in glob(&format!("{}/*.dem", folder_path.display().to_string())).unwrap() {
-            let path = path.unwrap();
-            let d = self
-                .cache
-                .get(&path)
-                .cloned()
-                .unwrap_or_else(|| Demo::new(std::path::Path::new(&path)));
-            self.demos.insert(d.filename.to_owned(), d);
-        }
-        let total = self.demos.len();
-        progress_cb(1, total);
-        for (i, demo) in &mut self.demos.values_mut().enumerate() {
-            demo.read_data();
-            self.cache.entry(demo.path.clone()).or_insert(demo.clone());
-            progress_cb(i + 2, total);
+        Self::load(
+            manager,
+            options.find_demos(),
+            options.roots(),
+            Some(options.clone()),
+            progress_cb,
+        );
+    }
+
+    /// Reads the demos on worker threads, unchanged demos are taken from the cache.
+    /// `progress_cb` is called with (current, total, demos read since the last call)
+    fn load(
+        manager: &Mutex<Self>,
+        paths: Vec<PathBuf>,
+        roots: Vec<PathBuf>,
+        library: Option<LibraryOptions>,
+        mut progress_cb: impl FnMut(usize, usize, Vec<Demo>),
+    ) {
+        let total = paths.len();
+        let (load_id, cached, unread) = {
+            let mut dm = manager.lock().unwrap();
+            dm.load_id += 1;
+            dm.demos.clear();
+            dm.roots = roots;
+            dm.library = library;
+            let mut cached = Vec::new();
+            let mut unread = Vec::new();
+            for path in paths {
+                match dm.cache.get(&path).filter(|d| d.is_current()) {
+                    Some(demo) => cached.push(demo.clone()),
+                    None => unread.push(path),
+                }
+            }
+            for demo in &cached {
+                dm.demos.insert(demo.path.clone(), demo.clone());
+            }
+            (dm.load_id, cached, unread)
+        };
+        let mut current = cached.len();
+        progress_cb(current, total, cached);
+
+        let cache_changed = !unread.is_empty();
+        read_demos(&unread, |batch| {
+            {
+                let mut dm = manager.lock().unwrap();
+                // Another load started in the meantime
+                if dm.load_id != load_id {
+                    return;
+                }
+                for demo in &batch {
+                    dm.cache.insert(demo.path.clone(), demo.clone());
+                    dm.demos.insert(demo.path.clone(), demo.clone());
+                }
+            }
+            current += batch.len();
+            progress_cb(current, total, batch);
+        });
+
+        let (save, cached_paths) = {
+            let mut dm = manager.lock().unwrap();
+            // Demos that were deleted or moved since they were cached
+            let cached_count = dm.cache.len();
+            dm.cache.retain(|path, _| path.exists());
+            let save = (cache_changed || dm.cache.len() != cached_count).then(|| dm.save_cache());
+            (save, dm.cache.keys().cloned().collect::<Vec<_>>())
+        };
+        if let Some(save) = save {
+            pollster::block_on(save);
         }
-        pollster::block_on(self.update_cache());
+        prune_analysis_cache(&paths::cache_file(ANALYSIS_CACHE_FOLDER), &cached_paths);
     }
 
     async fn update_cache(&self) {
-        if let Err(e) =
-            async_std::fs::write("demos.cache", bitcode::serialize(&self.cache).unwrap()).await
-        {
-            log::warn!("Failed to save cache file: {e:?}");
+        self.save_cache().await
+    }
+
+    /// Serializes the cache right away, the returned future writes it without borrowing the
+    /// manager, so a lock on it doesn't have to be held while waiting
+    pub fn save_cache(&self) -> impl std::future::Future<Output = ()> + 'static {
+        let data = bitcode::serialize(&self.cache).unwrap();
+        async move {
+            if let Err(e) = async_std::fs::write(paths::cache_file(DEMO_CACHE_FILE), data).await {
+                log::warn!("Failed to save cache file: {e:?}");
+            }
         }
     }
 
-    pub fn get_demo(&self, name: &str) -> Option<&Demo> {
-        self.demos.get(name)
+    pub fn get_demo(&self, path: &Path) -> Option<&Demo> {
+        self.demos.get(path)
+    }
+
+    /// All tags of the loaded demos with the number of demos that have them
+    pub fn tags(&self) -> BTreeMap<String, usize> {
+        let mut tags = BTreeMap::new();
+        for tag in self.demos.values().flat_map(|d| &d.tags) {
+            *tags.entry(tag.clone()).or_default() += 1;
+        }
+        tags
     }
 
-    pub fn get_demos(&self) -> &HashMap<String, Demo> {
+    pub fn get_demos(&self) -> &HashMap<PathBuf, Demo> {
         &self.demos
     }
 
+    /// Folders the current demos were loaded from, see [`folder_label`]
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Whether demos in subfolders of the roots are included, which is the case for the library
+    pub fn recursive(&self) -> bool {
+        self.library.is_some()
+    }
+
+    /// Whether any of the demos is still being recorded
+    pub fn recording(&self) -> bool {
+        self.demos.values().any(|d| d.recording)
+    }
+
+    /// Rereads the demos after these files or folders changed on disk, as well as all demos that
+    /// are still being recorded. Returns the demos that were added or changed, and the paths of
+    /// removed ones with `None`. The cache isn't saved, see [`DemoManager::save_cache`]
+    pub fn apply_changes(
+        &mut self,
+        changed: impl IntoIterator<Item = PathBuf>,
+    ) -> Vec<(PathBuf, Option<Demo>)> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for path in changed {
+            if path.is_dir() {
+                // A folder that was moved into a library folder
+                if self.recursive() {
+                    find_demos(&path, &mut paths);
+                }
+            } else if path.extension().is_some_and(|e| e == "json") {
+                paths.push(path.with_extension("dem"));
+            } else if path.extension().is_some_and(|e| e == "dem") {
+                paths.push(path);
+            } else {
+                // A folder that was removed or moved away
+                paths.extend(self.demos.keys().filter(|p| p.starts_with(&path)).cloned());
+            }
+        }
+        paths.extend(
+            self.demos
+                .values()
+                .filter(|d| d.recording)
+                .map(|d| d.path.clone()),
+        );
+        paths.sort();
+        paths.dedup();
+
+        paths.into_iter().filter_map(|p| self.refresh(p)).collect()
+    }
+
+    fn refresh(&mut self, path: PathBuf) -> Option<(PathBuf, Option<Demo>)> {
+        let included = match &self.library {
+            Some(library) => library.includes(&path),
+            None => self.roots.iter().any(|r| path.parent() == Some(r)),
+        };
+        if !included || !path.is_file() {
+            return self.demos.remove(&path).map(|_| (path, None));
+        }
+        let mut demo = Demo::new(&path);
+        demo.read_data();
+        self.cache.insert(path.clone(), demo.clone());
+        self.demos.insert(path.clone(), demo.clone());
+        Some((path, Some(demo)))
+    }
+
     pub async fn insert(&mut self, demo: Demo) {
         self.cache.insert(demo.path.clone(), demo.clone());
-        self.demos.insert(demo.filename.clone(), demo);
+        self.demos.insert(demo.path.clone(), demo);
         self.update_cache().await;
     }
 
-    pub async fn delete_demo(&mut self, name: &str) {
-        let demo = self.demos.remove(name).unwrap();
+    /// Moves the demo and its bookmark file to the trash
+    pub async fn delete_demo(&mut self, path: &Path) {
+        let Some(demo) = self.demos.remove(path) else {
+            return;
+        };
+        demo.remove_cached_analysis().await;
 
         let mut bookmark_path = demo.path.clone();
         bookmark_path.set_extension("json");
@@ -338,27 +886,218 @@ impl DemoManager {
         .await;
     }
 
-    pub async fn delete_empty_demos(&mut self) {
-        let empties: Vec<String> = self
-            .demos
+    /// Paths of demos that are shorter than half a second or couldn't be read. Demos that are
+    /// still being recorded are left out, they don't have their final header yet
+    pub fn empty_demos(&self) -> Vec<PathBuf> {
+        self.demos
             .values()
-            .filter(|d| d.header.as_ref().map_or(true, |h| h.duration < 0.5))
-            .map(|d| d.filename.clone())
-            .collect();
-        for demo in empties {
+            .filter(|d| !d.recording && d.header.as_ref().is_none_or(|h| h.duration < 0.5))
+            .map(|d| d.path.clone())
+            .collect()
+    }
+
+    /// Paths of demos without bookmarks, notes, tags or rating
+    pub fn unmarked_demos(&self) -> Vec<PathBuf> {
+        self.demos
+            .values()
+            .filter(|d| !d.is_marked())
+            .map(|d| d.path.clone())
+            .collect()
+    }
+
+    pub async fn delete_empty_demos(&mut self) {
+        for demo in self.empty_demos() {
             self.delete_demo(&demo).await;
         }
     }
 
     pub async fn delete_unmarked_demos(&mut self) {
-        let unmarkeds: Vec<String> = self
-            .demos
-            .values()
-            .filter(|d| d.events.is_empty())
-            .map(|d| d.filename.clone())
-            .collect();
-        for demo in unmarkeds {
+        for demo in self.unmarked_demos() {
             self.delete_demo(&demo).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFolder;
+
+    fn library(folders: &[&Path], include: &[&str], exclude: &[&str]) -> LibraryOptions {
+        LibraryOptions {
+            folders: folders.iter().map(|f| f.to_path_buf()).collect(),
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn library_finds_demos_recursively() {
+        let folder = TempFolder::new("library");
+        let a = folder.touch("a.dem");
+        let b = folder.touch("scrims/2024/b.dem");
+        folder.touch("scrims/b.json");
+        folder.touch("notes.txt");
+
+        let library = library(&[&folder.0, &folder.0.join("scrims")], &[], &[]);
+        assert_eq!(library.find_demos(), vec![a, b]);
+    }
+
+    #[test]
+    fn library_patterns() {
+        let root = Path::new("/demos");
+        let library = library(&[root], &["scrims/*", "*pl_*"], &["*/old/*"]);
+
+        assert!(library.includes(Path::new("/demos/scrims/a.dem")));
+        assert!(library.includes(Path::new("/demos/scrims/2024/a.dem")));
+        assert!(library.includes(Path::new("/demos/auto-pl_upward.dem")));
+        assert!(!library.includes(Path::new("/demos/auto-cp_process.dem")));
+        assert!(!library.includes(Path::new("/demos/scrims/old/a.dem")));
+        assert!(!library.includes(Path::new("/other/scrims/a.dem")));
+
+        // Patterns are matched relative to the library folder
+        let library = self::library(&[root], &["demos/*"], &[]);
+        assert!(!library.includes(Path::new("/demos/a.dem")));
+    }
+
+    #[test]
+    fn invalid_and_empty_patterns_are_ignored() {
+        let library = library(&[Path::new("/demos")], &["", "[", " "], &["  "]);
+        assert!(library.includes(Path::new("/demos/a.dem")));
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        assert_eq!(
+            parse_tags(" scrim, cheater,,scrim ,frag movie".split(',')),
+            vec!["cheater", "frag movie", "scrim"]
+        );
+        assert!(parse_tags([" ", ""]).is_empty());
+    }
+
+    #[test]
+    fn tags_with_commas_are_split() {
+        // A hand edited bookmark file can contain tags the entry would have split
+        let tags = parse_tags(["frag, movie", "b,a"]);
+        assert_eq!(tags, vec!["a", "b", "frag", "movie"]);
+        assert_eq!(parse_tags(tags.join(", ").split(", ")), tags);
+    }
+
+    #[test]
+    fn tags_and_rating_are_read_from_the_bookmark_file() {
+        let folder = TempFolder::new("tags");
+        let path = folder.touch("a.dem");
+        fs::write(
+            folder.0.join("a.json"),
+            r#"{"events":[],"tags":["scrim","frag, movie"],"rating":9}"#,
+        )
+        .unwrap();
+
+        let mut demo = Demo::new(&path);
+        demo.read_data();
+        assert_eq!(demo.tags, vec!["frag", "movie", "scrim"]);
+        assert_eq!(demo.rating, None);
+
+        demo.rating = Some(4);
+        pollster::block_on(demo.save_json());
+        let mut reread = Demo::new(&path);
+        reread.read_data();
+        assert_eq!(reread.tags, demo.tags);
+        assert_eq!(reread.rating, Some(4));
+
+        demo.tags.clear();
+        demo.rating = None;
+        pollster::block_on(demo.save_json());
+        assert!(!folder.0.join("a.json").exists());
+    }
+
+    #[test]
+    fn tagged_or_rated_demos_are_marked() {
+        let demo = |name: &str, f: fn(&mut Demo)| {
+            let mut demo = Demo::new(format!("/demos/{name}.dem"));
+            f(&mut demo);
+            (demo.path.clone(), demo)
+        };
+        let manager = DemoManager {
+            cache: HashMap::new(),
+            demos: HashMap::from([
+                demo("empty", |_| {}),
+                demo("empty_notes", |d| d.notes = Some(String::new())),
+                demo("bookmark", |d| d.events.push(Event::default())),
+                demo("notes", |d| d.notes = Some("good round".to_owned())),
+                demo("tags", |d| d.tags = vec!["scrim".to_owned()]),
+                demo("rating", |d| d.rating = Some(1)),
+            ]),
+            roots: Vec::new(),
+            library: None,
+            load_id: 0,
+        };
+        let mut unmarked = manager.unmarked_demos();
+        unmarked.sort();
+        assert_eq!(
+            unmarked,
+            vec![
+                PathBuf::from("/demos/empty.dem"),
+                PathBuf::from("/demos/empty_notes.dem")
+            ]
+        );
+    }
+
+    #[test]
+    fn recording_demos_are_not_empty() {
+        let mut recording = Demo::new("/demos/recording.dem");
+        recording.recording = true;
+        let manager = DemoManager {
+            cache: HashMap::new(),
+            demos: HashMap::from([
+                (recording.path.clone(), recording),
+                ("/demos/broken.dem".into(), Demo::new("/demos/broken.dem")),
+            ]),
+            roots: Vec::new(),
+            library: None,
+            load_id: 0,
+        };
+        assert_eq!(
+            manager.empty_demos(),
+            vec![PathBuf::from("/demos/broken.dem")]
+        );
+    }
+
+    #[test]
+    fn folder_label_is_relative_to_the_closest_root() {
+        let roots = [PathBuf::from("/demos"), PathBuf::from("/demos/scrims")];
+        let label = |path: &str| folder_label(Path::new(path), &roots);
+        assert_eq!(label("/demos/a.dem"), "demos");
+        assert_eq!(label("/demos/pubs/a.dem"), "demos/pubs");
+        assert_eq!(label("/demos/scrims/2024/a.dem"), "scrims/2024");
+        assert_eq!(label("/other/a.dem"), "/other");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn analysis_cache_names_are_stable() {
+        assert_eq!(
+            analysis_cache_name(Path::new("/demos/a.dem")),
+            "ad1eaaac9e946e92.bin"
+        );
+    }
+
+    #[test]
+    fn analyses_of_uncached_demos_are_pruned() {
+        let demos = TempFolder::new("prune-demos");
+        let cache = TempFolder::new("prune-cache");
+        let kept = demos.touch("kept.dem");
+        let removed = demos.touch("removed.dem");
+        cache.touch(&analysis_cache_name(&kept));
+        cache.touch(&analysis_cache_name(&removed));
+        cache.touch("0123456789abcdef.bin");
+
+        prune_analysis_cache(&cache.0, [&kept]);
+
+        let left: Vec<_> = fs::read_dir(&cache.0)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(left, vec![analysis_cache_name(&kept)]);
+    }
+}
@@ -0,0 +1,47 @@
+//! Machine readable export of analysis results.
+//!
+//! The json document has the following top level fields:
+//! + `schema_version`: [`SCHEMA_VERSION`], increased whenever existing fields change or get removed
+//!   (new fields can be added without a version bump)
+//! + `demo`: file name and header of the demo, if known
+//! + `match`: the [`MatchState`], users are referenced by their index in `match.users`
+//!   everywhere else and ticks are counted from the start of the demo
+
+use anyhow::Result;
+use serde::Serialize;
+use tf_demo_parser::demo::header::Header;
+
+use crate::{analyser::MatchState, demo_manager::Demo};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct DemoExport<'a> {
+    pub schema_version: u32,
+    pub demo: Option<DemoFileInfo<'a>>,
+    #[serde(rename = "match")]
+    pub state: &'a MatchState,
+}
+
+#[derive(Serialize)]
+pub struct DemoFileInfo<'a> {
+    pub filename: &'a str,
+    pub header: Option<&'a Header>,
+}
+
+impl<'a> DemoExport<'a> {
+    pub fn new(demo: Option<&'a Demo>, state: &'a MatchState) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            demo: demo.map(|d| DemoFileInfo {
+                filename: &d.filename,
+                header: d.header.as_ref(),
+            }),
+            state,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
@@ -0,0 +1,294 @@
+//! Detection of highlights of the recording player, which can be added to a demo as bookmarks
+
+use serde::{Deserialize, Serialize};
+use tf_demo_parser::demo::{data::DemoTick, parser::analyser::Team};
+
+use crate::{
+    analyser::{CritType, Death, MatchEventType, MatchState, StableUserId},
+    demo_manager::{Demo, Event},
+};
+
+/// [`Event::ev_type`] of generated bookmarks
+pub const HIGHLIGHT_TYPE: &str = "Highlight";
+
+/// Weapon names of explosive projectiles, crit kills with these are counted as highlights
+const PROJECTILE_WEAPONS: &[&str] = &[
+    "rocket",
+    "quake_rl",
+    "liberty_launcher",
+    "blackbox",
+    "airstrike",
+    "cow_mangler",
+    "tf_projectile_pipe",
+    "iron_bomber",
+    "loch_n_load",
+    "loose_cannon",
+    "quickiebomb_launcher",
+    "sticky_resistance",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightOptions {
+    /// Seconds in which `min_multikill` kills have to happen
+    pub multikill_window: f32,
+    pub min_multikill: usize,
+    /// Kills without dying
+    pub min_killstreak: usize,
+    /// Seconds before a won round in which `min_clutch_kills` kills count as a clutch
+    pub clutch_window: f32,
+    pub min_clutch_kills: usize,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        Self {
+            multikill_window: 10.0,
+            min_multikill: 3,
+            min_killstreak: 5,
+            clutch_window: 10.0,
+            min_clutch_kills: 2,
+        }
+    }
+}
+
+/// The player that recorded the demo, `None` for STV demos
+pub fn recording_user(demo: &Demo, state: &MatchState) -> Option<StableUserId> {
+    if state.server_info.is_stv {
+        return None;
+    }
+    let nick = &demo.header.as_ref()?.nick;
+    state
+        .users
+        .iter()
+        .position(|u| u.name.as_ref() == Some(nick))
+        .map(Into::into)
+}
+
+/// Finds multi-kills, killstreaks, crit projectile kills, dominations, revenges and clutch round
+/// wins of `user`, sorted by tick
+pub fn detect(
+    state: &MatchState,
+    user: StableUserId,
+    tps: f32,
+    options: &HighlightOptions,
+) -> Vec<Event> {
+    let name = |u: &StableUserId| state.users[u].name.as_deref().unwrap_or("unknown");
+    let secs_to_ticks = |secs: f32| (secs * tps) as u32;
+    let mut highlights = Vec::new();
+    let mut add = |tick: DemoTick, title: String| {
+        highlights.push(Event {
+            tick: tick.into(),
+            title,
+            ev_type: HIGHLIGHT_TYPE.to_owned(),
+        })
+    };
+
+    let kills: Vec<(DemoTick, &Death)> = state
+        .events
+        .iter()
+        .filter_map(|e| match &e.value {
+            MatchEventType::Kill(death)
+                if death.killer == Some(user) && death.victim != user && !death.deadringer =>
+            {
+                Some((e.tick, death))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let window = secs_to_ticks(options.multikill_window);
+    let mut i = 0;
+    while i < kills.len() {
+        let end = kills[i..].partition_point(|(t, _)| u32::from(*t - kills[i].0) <= window) + i;
+        let count = end - i;
+        if count >= options.min_multikill {
+            let secs = crate::util::ticks_to_sec((kills[end - 1].0 - kills[i].0).into(), tps);
+            add(kills[i].0, format!("{count} kills in {secs:.0}s"));
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+
+    for (tick, death) in &kills {
+        let victim = name(&death.victim);
+        if death.domination {
+            add(*tick, format!("Dominated {victim}"));
+        }
+        if death.revenge {
+            add(*tick, format!("Revenge on {victim}"));
+        }
+        if matches!(death.crit_type, CritType::Full)
+            && PROJECTILE_WEAPONS.iter().any(|w| death.weapon.contains(w))
+        {
+            add(*tick, format!("Crit {} on {victim}", death.weapon));
+        }
+    }
+
+    let mut streak: Vec<DemoTick> = Vec::new();
+    let mut team: Option<Team> = None;
+    let clutch_window = secs_to_ticks(options.clutch_window);
+    for event in &state.events {
+        match &event.value {
+            MatchEventType::Kill(death) if death.victim == user && !death.deadringer => {
+                if streak.len() >= options.min_killstreak {
+                    add(streak[0], format!("{} kill streak", streak.len()));
+                }
+                streak.clear();
+            }
+            MatchEventType::Kill(death)
+                if death.killer == Some(user) && death.victim != user && !death.deadringer =>
+            {
+                streak.push(event.tick);
+            }
+            MatchEventType::TeamSwitch(u, t) if *u == user => team = Some(*t),
+            MatchEventType::RoundEnd(round) if team == Some(round.winner) => {
+                let start = kills.partition_point(|(t, _)| *t + clutch_window < event.tick);
+                let end = kills.partition_point(|(t, _)| *t <= event.tick);
+                let count = end - start;
+                if count > 0 && count >= options.min_clutch_kills {
+                    add(kills[start].0, format!("Clutch round win ({count} kills)"));
+                }
+            }
+            _ => {}
+        }
+    }
+    if streak.len() >= options.min_killstreak {
+        add(streak[0], format!("{} kill streak", streak.len()));
+    }
+
+    highlights.sort_by_key(|e| e.tick);
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::{MatchEvent, Round, UserInfo};
+
+    const TPS: f32 = 100.0;
+
+    fn kill(tick: u32, killer: usize, victim: usize, weapon: &str) -> MatchEvent {
+        MatchEvent {
+            tick: tick.into(),
+            value: MatchEventType::Kill(Death {
+                weapon: weapon.to_owned(),
+                victim: victim.into(),
+                assister: None,
+                killer: Some(killer.into()),
+                crit_type: CritType::None,
+                domination: false,
+                revenge: false,
+                assist_dom: false,
+                assist_revg: false,
+                deadringer: false,
+            }),
+        }
+    }
+
+    fn detect_all(events: Vec<MatchEvent>) -> Vec<(u32, String)> {
+        let state = MatchState {
+            users: vec![
+                UserInfo {
+                    name: Some("player".to_owned()),
+                    ..Default::default()
+                },
+                UserInfo {
+                    name: Some("enemy".to_owned()),
+                    ..Default::default()
+                },
+            ],
+            events,
+            ..Default::default()
+        };
+        detect(&state, 0.into(), TPS, &HighlightOptions::default())
+            .into_iter()
+            .map(|e| {
+                assert_eq!(e.ev_type, HIGHLIGHT_TYPE);
+                (e.tick, e.title)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn multikill_inside_window() {
+        let highlights = detect_all(vec![
+            kill(100, 0, 1, "scattergun"),
+            kill(300, 0, 1, "scattergun"),
+            kill(1100, 0, 1, "scattergun"),
+            // Outside of the 10s window of the first kill
+            kill(1200, 0, 1, "scattergun"),
+        ]);
+        assert_eq!(highlights, vec![(100, "3 kills in 10s".to_owned())]);
+    }
+
+    #[test]
+    fn killstreak_ends_with_death() {
+        let mut events: Vec<MatchEvent> = (1..=5)
+            .map(|i| kill(i * 2000, 0, 1, "scattergun"))
+            .collect();
+        events.push(kill(20000, 1, 0, "minigun"));
+        events.extend((1..=4).map(|i| kill(20000 + i * 2000, 0, 1, "scattergun")));
+        assert_eq!(detect_all(events), vec![(2000, "5 kill streak".to_owned())]);
+    }
+
+    #[test]
+    fn crit_projectiles_dominations_and_revenges() {
+        let mut rocket = kill(100, 0, 1, "tf_projectile_rocket");
+        let mut shotgun = kill(2000, 0, 1, "shotgun_soldier");
+        if let (MatchEventType::Kill(rocket), MatchEventType::Kill(shotgun)) =
+            (&mut rocket.value, &mut shotgun.value)
+        {
+            rocket.crit_type = CritType::Full;
+            rocket.revenge = true;
+            shotgun.crit_type = CritType::Full;
+            shotgun.domination = true;
+        }
+        assert_eq!(
+            detect_all(vec![rocket, shotgun]),
+            vec![
+                (100, "Revenge on enemy".to_owned()),
+                (100, "Crit tf_projectile_rocket on enemy".to_owned()),
+                (2000, "Dominated enemy".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn feigned_deaths_are_ignored() {
+        let mut feign = kill(100, 0, 1, "scattergun");
+        if let MatchEventType::Kill(death) = &mut feign.value {
+            death.deadringer = true;
+        }
+        assert!(detect_all(vec![feign.clone(), feign.clone(), feign]).is_empty());
+    }
+
+    #[test]
+    fn clutch_only_for_won_rounds() {
+        let team = |team| MatchEvent {
+            tick: 0.into(),
+            value: MatchEventType::TeamSwitch(0.into(), team),
+        };
+        let round_end = |tick: u32, winner| MatchEvent {
+            tick: tick.into(),
+            value: MatchEventType::RoundEnd(Round {
+                winner,
+                length: 0.0,
+            }),
+        };
+        let highlights = detect_all(vec![
+            team(Team::Red),
+            kill(5000, 0, 1, "scattergun"),
+            kill(5500, 0, 1, "scattergun"),
+            round_end(6000, Team::Red),
+            kill(15000, 0, 1, "scattergun"),
+            kill(15500, 0, 1, "scattergun"),
+            round_end(16000, Team::Blue),
+        ]);
+        assert_eq!(
+            highlights,
+            vec![(5000, "Clutch round win (2 kills)".to_owned())]
+        );
+    }
+}
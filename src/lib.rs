@@ -0,0 +1,46 @@
+//! Demo parsing and management core of the TF2 Demo Player.
+//!
+//! The GTK front end and the `tf2-demo-cli` command line tool are separate binaries built on top
+//! of this crate. The main entry points are
+//! [`demo_manager::DemoManager`] for loading demo folders, [`demo_manager::Demo`] for working with a
+//! single demo and [`analyser::Analyser`], which turns a demo into a [`analyser::MatchState`].
+//! [`rcon_manager::RconManager`] controls a running TF2 instance.
+//!
+//! Without the default `gui` feature no GTK libraries are needed:
+//! ```toml
+//! tf2-demo-player = { git = "https://github.com/Nocrex/tf2-demo-player", default-features = false }
+//! ```
+//!
+//! ```no_run
+//! use tf2_demo_player::demo_manager::Demo;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut demo = Demo::new("demos/auto-20240101-1200-pl_upward.dem");
+//! demo.read_data();
+//! let state = demo.full_analysis().await?;
+//! for event in &state.events {
+//!     println!("{}", event.to_string(&state.users));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod analyser;
+pub mod cli;
+pub mod demo_manager;
+pub mod export;
+pub mod highlights;
+pub mod macros;
+pub mod paths;
+pub mod postprocess;
+pub mod rcon_manager;
+pub mod recorder;
+pub mod render_queue;
+pub mod search;
+pub mod secrets;
+pub mod settings;
+pub mod stats;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod util;
+pub mod watcher;
@@ -0,0 +1,114 @@
+//! User defined console command macros that are sent to TF2 over rcon
+//!
+//! The commands can contain these placeholders:
+//! + `{tick}`: the playhead tick, or the tick of the event when run after jumping to one
+//! + `{player}`: name of the player that recorded the demo
+//! + `{demo}`: file name of the demo
+
+use serde::{Deserialize, Serialize};
+
+use crate::demo_manager::Demo;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandMacro {
+    pub name: String,
+    /// Console commands, one per line or separated by `;`
+    pub commands: String,
+    /// Key binding in the format of `gtk::accelerator_parse`, empty for none
+    pub shortcut: String,
+    /// Runs the macro every time playback jumps to an event
+    pub after_seek: bool,
+}
+
+impl Default for CommandMacro {
+    fn default() -> Self {
+        Self {
+            name: "New macro".to_owned(),
+            commands: String::new(),
+            shortcut: String::new(),
+            after_seek: false,
+        }
+    }
+}
+
+impl CommandMacro {
+    /// The commands as a single line with the placeholders replaced
+    pub fn expand(&self, tick: u32, demo: Option<&Demo>) -> String {
+        let player = demo
+            .and_then(|d| d.header.as_ref())
+            .map_or("", |h| h.nick.as_str());
+        let demo = demo.map_or("", |d| d.filename.as_str());
+        self.commands
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>()
+            .join("; ")
+            .replace("{tick}", &tick.to_string())
+            .replace("{player}", &sanitize(player))
+            .replace("{demo}", &sanitize(demo))
+    }
+}
+
+/// Removes characters that would end a quoted argument or start another command
+pub(crate) fn sanitize(value: &str) -> String {
+    value.replace(['"', ';', '\n'], "")
+}
+
+#[cfg(test)]
+mod tests {
+    use tf_demo_parser::demo::header::Header;
+
+    use super::*;
+
+    fn demo(nick: &str) -> Demo {
+        let mut demo = Demo::new("/demos/auto-20240101-1200-pl_upward.dem");
+        demo.header = Some(Header {
+            demo_type: "HL2DEMO".to_owned(),
+            version: 3,
+            protocol: 24,
+            server: String::new(),
+            nick: nick.to_owned(),
+            map: "pl_upward".to_owned(),
+            game: "tf".to_owned(),
+            duration: 0.0,
+            ticks: 0,
+            frames: 0,
+            signon: 0,
+        });
+        demo
+    }
+
+    fn command_macro(commands: &str) -> CommandMacro {
+        CommandMacro {
+            commands: commands.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lines_are_joined() {
+        let m = command_macro("  cl_drawhud 0\n\nthirdperson  \n");
+        assert_eq!(m.expand(0, None), "cl_drawhud 0; thirdperson");
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        let m = command_macro("spec_player \"{player}\"\necho {demo} {tick}");
+        assert_eq!(
+            m.expand(1234, Some(&demo("Scout Main"))),
+            "spec_player \"Scout Main\"; echo auto-20240101-1200-pl_upward.dem 1234"
+        );
+        assert_eq!(m.expand(5, None), "spec_player \"\"; echo  5");
+    }
+
+    #[test]
+    fn names_cant_inject_commands() {
+        let m = command_macro("spec_player \"{player}\"");
+        assert_eq!(
+            m.expand(0, Some(&demo("a\"; quit; echo \"b\n"))),
+            "spec_player \"a quit echo b\""
+        );
+    }
+}
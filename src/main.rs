@@ -1,14 +1,12 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-mod analyser;
-mod demo_manager;
-mod rcon_manager;
-mod settings;
-
-mod util;
-
+use clap::Parser;
 use relm4::RelmApp;
 mod ui;
 use simplelog::{Config, TermLogger, WriteLogger};
+use tf2_demo_player::{
+    analyser, cli, demo_manager, export, highlights, macros, paths, postprocess, rcon_manager,
+    recorder, render_queue, search, settings, stats, util, watcher,
+};
 use ui::DemoPlayerModel;
 
 mod load_icons {
@@ -36,8 +34,34 @@ mod load_icons {
     }
 }
 
+/// Release builds on Windows have no console of their own, the output of subcommands goes to the
+/// one they were started from
+fn attach_console() {
+    #[cfg(all(windows, not(debug_assertions)))]
+    {
+        const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+        extern "system" {
+            fn AttachConsole(process_id: u32) -> i32;
+        }
+        unsafe {
+            AttachConsole(ATTACH_PARENT_PROCESS);
+        }
+    }
+}
+
 #[async_std::main]
 async fn main() {
+    let cli = cli::Cli::parse();
+    if let Some(dir) = cli.config_dir {
+        paths::set_override(dir);
+    }
+    if let Some(command) = cli.command {
+        attach_console();
+        cli::run_and_exit(command).await;
+    }
+
+    // Before the log file is opened, so an old one is moved too
+    paths::migrate();
     simplelog::CombinedLogger::init(if cfg!(debug_assertions) {
         vec![simplelog::TermLogger::new(
             log::LevelFilter::Debug,
@@ -59,7 +83,7 @@ async fn main() {
                 std::fs::OpenOptions::new()
                     .append(true)
                     .create(true)
-                    .open("log.txt")
+                    .open(paths::state_file("log.txt"))
                     .unwrap(),
             ),
         ]
@@ -75,7 +99,9 @@ async fn main() {
 
     load_icons::setup();
 
-    let app = RelmApp::new("com.github.nocrex.tf2demoplayer");
+    // The arguments were already handled by clap, GTK would reject them
+    let app = RelmApp::new("com.github.nocrex.tf2demoplayer")
+        .with_args(std::env::args().take(1).collect());
     app.run_async::<DemoPlayerModel>(());
     log::info!("Exited")
 }
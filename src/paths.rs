@@ -0,0 +1,260 @@
+//! Where settings, caches and logs are stored
+//!
+//! By default these are the platform directories, on Linux `~/.config/tf2-demo-player` for the
+//! settings and overviews, `~/.cache/tf2-demo-player` for the demo, analysis and search caches
+//! and `~/.local/state/tf2-demo-player` for the log and the render queue. With
+//! [`set_override`] everything goes into a single folder instead, e.g. for portable installs.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use directories::ProjectDirs;
+
+static OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Puts all files into `folder`, has to be called before any of them is used
+pub fn set_override(folder: PathBuf) {
+    let _ = OVERRIDE.set(folder);
+}
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("com.github", "nocrex", "tf2-demo-player")
+}
+
+fn dir(pick: fn(&ProjectDirs) -> PathBuf) -> PathBuf {
+    let dir = match OVERRIDE.get() {
+        Some(folder) => folder.clone(),
+        // The working directory, like before these directories were used
+        None => project_dirs().map_or_else(PathBuf::new, |d| pick(&d)),
+    };
+    if let Err(e) = fs::create_dir_all(&dir) {
+        log::warn!("Couldn't create {}, {}", dir.display(), e);
+    }
+    dir
+}
+
+pub fn config_dir() -> PathBuf {
+    dir(|d| d.config_dir().to_owned())
+}
+
+pub fn cache_dir() -> PathBuf {
+    dir(|d| d.cache_dir().to_owned())
+}
+
+/// Falls back to the local data directory on systems without a state directory
+pub fn state_dir() -> PathBuf {
+    dir(|d| d.state_dir().unwrap_or(d.data_local_dir()).to_owned())
+}
+
+pub fn config_file(name: &str) -> PathBuf {
+    config_dir().join(name)
+}
+
+pub fn cache_file(name: &str) -> PathBuf {
+    cache_dir().join(name)
+}
+
+pub fn state_file(name: &str) -> PathBuf {
+    state_dir().join(name)
+}
+
+/// Marks the files of older versions as moved, so that is only tried once
+const MIGRATED_MARKER: &str = ".migrated";
+
+/// Where the files of older versions go
+struct Folders {
+    config: PathBuf,
+    cache: PathBuf,
+    state: PathBuf,
+}
+
+/// Moves the files older versions wrote into the working directory to their new place, also
+/// looks next to the executable. Files that already exist there are left alone. Once everything
+/// was moved, or there was nothing to move, later starts skip this
+pub fn migrate() {
+    let marker = config_file(MIGRATED_MARKER);
+    if marker.exists() {
+        return;
+    }
+    let folders = Folders {
+        config: config_dir(),
+        cache: cache_dir(),
+        state: state_dir(),
+    };
+    let mut old_dirs = vec![];
+    if let Ok(dir) = std::env::current_dir() {
+        old_dirs.push(dir);
+    }
+    if let Some(dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_owned))
+        .filter(|dir| !old_dirs.contains(dir))
+    {
+        old_dirs.push(dir);
+    }
+    let mut done = true;
+    for old_dir in old_dirs {
+        done &= migrate_from(&old_dir, &folders);
+    }
+    if !done {
+        return;
+    }
+    if let Err(e) = fs::write(&marker, "") {
+        log::warn!("Couldn't create {}, {}", marker.display(), e);
+    }
+}
+
+/// Moves the files out of `old_dir` if it holds settings or a demo cache of an older version, so
+/// unrelated files in a working directory are left alone. Returns false if a move failed
+fn migrate_from(old_dir: &Path, folders: &Folders) -> bool {
+    let settings = old_dir.join("settings.json");
+    if !crate::settings::Settings::is_settings_file(&settings)
+        && !old_dir.join("demos.cache").exists()
+    {
+        return true;
+    }
+    let moves = [
+        ("settings.json", &folders.config),
+        ("overviews", &folders.config),
+        ("demos.cache", &folders.cache),
+        ("analysis_cache", &folders.cache),
+        ("search.index", &folders.cache),
+        ("render_queue.json", &folders.state),
+        ("log.txt", &folders.state),
+    ];
+    let mut done = true;
+    for (name, folder) in moves {
+        let old = old_dir.join(name);
+        let new = folder.join(name);
+        if !old.exists() || new.exists() {
+            continue;
+        }
+        if old == settings && !crate::settings::Settings::is_settings_file(&old) {
+            log::info!(
+                "{} isn't a settings file of this app, leaving it",
+                old.display()
+            );
+            continue;
+        }
+        match fs::create_dir_all(folder).and_then(|_| move_path(&old, &new)) {
+            Ok(()) => log::info!("Moved {} to {}", old.display(), new.display()),
+            Err(e) => {
+                log::warn!(
+                    "Couldn't move {} to {}, {}",
+                    old.display(),
+                    new.display(),
+                    e
+                );
+                done = false;
+            }
+        }
+    }
+    done
+}
+
+/// Renames, or copies and deletes if the destination is on another file system
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            move_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::remove_dir(from)
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folders(root: &Path) -> Folders {
+        Folders {
+            config: root.join("config"),
+            cache: root.join("cache"),
+            state: root.join("state"),
+        }
+    }
+
+    #[test]
+    fn old_files_are_moved() {
+        let root = std::env::temp_dir().join(format!("tf2dp-{}-paths", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let old = root.join("old");
+        let new = folders(&root);
+        fs::create_dir_all(old.join("overviews")).unwrap();
+
+        fs::write(old.join("settings.json"), r#"{"theme": "dark"}"#).unwrap();
+        fs::write(old.join("log.txt"), "old log").unwrap();
+        fs::write(old.join("demos.cache"), "").unwrap();
+        fs::write(old.join("overviews/pl_upward.png"), "").unwrap();
+        assert!(migrate_from(&old, &new));
+        assert!(old.join("settings.json").exists(), "foreign settings moved");
+        assert!(!new.config.join("settings.json").exists());
+        assert_eq!(
+            fs::read_to_string(new.state.join("log.txt")).unwrap(),
+            "old log"
+        );
+        assert!(new.cache.join("demos.cache").exists());
+        assert!(new.config.join("overviews/pl_upward.png").exists());
+        assert!(!old.join("overviews").exists());
+
+        fs::write(old.join("settings.json"), r#"{"demo_folder_path": null}"#).unwrap();
+        fs::write(old.join("log.txt"), "older log").unwrap();
+        assert!(migrate_from(&old, &new));
+        assert!(new.config.join("settings.json").exists());
+        assert!(!old.join("settings.json").exists());
+        // Files that already exist in the new place are kept
+        assert_eq!(
+            fs::read_to_string(new.state.join("log.txt")).unwrap(),
+            "old log"
+        );
+        assert!(old.join("log.txt").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn unrelated_folders_are_left_alone() {
+        let root = std::env::temp_dir().join(format!("tf2dp-{}-paths-other", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let old = root.join("old");
+        let new = folders(&root);
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("log.txt"), "someone else's log").unwrap();
+
+        assert!(migrate_from(&old, &new));
+        assert!(old.join("log.txt").exists());
+        assert!(!new.state.exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn failed_moves_are_reported() {
+        let root = std::env::temp_dir().join(format!("tf2dp-{}-paths-failed", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let old = root.join("old");
+        let new = folders(&root);
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("demos.cache"), "").unwrap();
+        fs::write(old.join("log.txt"), "old log").unwrap();
+        // The state folder can't be created where a file is
+        fs::write(&new.state, "").unwrap();
+
+        assert!(!migrate_from(&old, &new));
+        assert!(old.join("log.txt").exists());
+        assert!(new.cache.join("demos.cache").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,512 @@
+//! Runs ffmpeg on recorded clips, e.g. to encode TGA frames and WAV audio into a single video
+//!
+//! The ffmpeg arguments come from a [`Preset`] template with these placeholders:
+//! + `{input}`: the `-i` arguments for the recorded frames, audio or video, has to be on its own
+//! + `{output}`: the output file
+//! + `{framerate}`: framerate of the recording
+//! + `{duration}`: length of the clip in seconds
+//! + `{fade}` and `{fade_out}`: fade length of the preset, and the time the fade out starts at
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub name: String,
+    /// Argument template, split on whitespace before the placeholders are replaced
+    pub args: String,
+    /// Extension of the output file
+    pub extension: String,
+    /// Seconds, used for the `{fade}` and `{fade_out}` placeholders
+    pub fade: f32,
+    /// Keeps the recorded files after processing them
+    pub keep_input: bool,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self {
+            name: "H.264".to_owned(),
+            args: "-y {input} -c:v libx264 -crf 18 -pix_fmt yuv420p -c:a aac -b:a 192k {output}"
+                .to_owned(),
+            extension: "mp4".to_owned(),
+            fade: 0.0,
+            keep_input: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FfmpegSettings {
+    /// The ffmpeg executable, looked up in PATH if it isn't a full path
+    pub path: PathBuf,
+    pub presets: Vec<Preset>,
+}
+
+impl Default for FfmpegSettings {
+    fn default() -> Self {
+        Self {
+            path: "ffmpeg".into(),
+            presets: vec![
+                Preset::default(),
+                Preset {
+                    name: "H.264 with fades".to_owned(),
+                    args: "-y {input} \
+                        -vf fade=t=in:d={fade},fade=t=out:st={fade_out}:d={fade} \
+                        -af afade=t=in:d={fade},afade=t=out:st={fade_out}:d={fade} \
+                        -c:v libx264 -crf 18 -pix_fmt yuv420p -c:a aac -b:a 192k {output}"
+                        .to_owned(),
+                    fade: 0.5,
+                    ..Default::default()
+                },
+                Preset {
+                    name: "WebM (VP9)".to_owned(),
+                    args: "-y {input} -c:v libvpx-vp9 -crf 30 -b:v 0 -c:a libopus {output}"
+                        .to_owned(),
+                    extension: "webm".to_owned(),
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+}
+
+impl FfmpegSettings {
+    pub fn processor(&self, preset: Option<&str>) -> Option<PostProcessor> {
+        let preset = self
+            .presets
+            .iter()
+            .find(|p| Some(p.name.as_str()) == preset)?;
+        Some(PostProcessor {
+            ffmpeg: self.path.clone(),
+            preset: preset.clone(),
+        })
+    }
+}
+
+/// The files `startmovie` produced for a clip
+#[derive(Debug, Default)]
+struct Inputs {
+    /// First TGA frame
+    frames: Option<PathBuf>,
+    audio: Option<PathBuf>,
+    video: Option<PathBuf>,
+}
+
+impl Inputs {
+    fn sort(files: &[PathBuf]) -> Self {
+        let mut inputs = Self::default();
+        let mut files = files.to_vec();
+        files.sort();
+        for file in files {
+            match file.extension().and_then(|e| e.to_str()) {
+                Some("tga") => {
+                    inputs.frames.get_or_insert(file);
+                }
+                Some("wav") => inputs.audio = Some(file),
+                _ => inputs.video = Some(file),
+            }
+        }
+        inputs
+    }
+
+    fn args(&self, framerate: u32) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        if let Some(frame) = &self.frames {
+            // TF2 numbers the frames with 4 digits after the name
+            let stem = frame.file_stem().unwrap_or_default().to_string_lossy();
+            let Some(name) = stem.get(..stem.len().saturating_sub(4)) else {
+                bail!("Unexpected frame name {}", frame.display());
+            };
+            args.extend([
+                "-framerate".to_owned(),
+                framerate.to_string(),
+                "-i".to_owned(),
+                frame
+                    .with_file_name(format!("{name}%04d.tga"))
+                    .display()
+                    .to_string(),
+            ]);
+        }
+        for file in [&self.audio, &self.video].into_iter().flatten() {
+            args.extend(["-i".to_owned(), file.display().to_string()]);
+        }
+        if args.is_empty() {
+            bail!("No recorded files to process");
+        }
+        Ok(args)
+    }
+}
+
+/// Value of an `out_time_us` line of ffmpeg's `-progress` output, in seconds
+fn parse_progress(line: &str) -> Option<f32> {
+    let value = line
+        .strip_prefix("out_time_us=")
+        .or_else(|| line.strip_prefix("out_time_ms="))?;
+    Some(value.trim().parse::<i64>().ok()? as f32 / 1_000_000.0)
+}
+
+#[derive(Clone)]
+pub struct PostProcessor {
+    pub ffmpeg: PathBuf,
+    pub preset: Preset,
+}
+
+impl PostProcessor {
+    /// The ffmpeg arguments for processing `files` into `output`
+    pub fn args(
+        &self,
+        files: &[PathBuf],
+        framerate: u32,
+        duration: f32,
+        output: &Path,
+    ) -> Result<Vec<String>> {
+        let inputs = Inputs::sort(files).args(framerate)?;
+        let fade = self.preset.fade;
+        let mut args = Vec::new();
+        for arg in self.preset.args.split_whitespace() {
+            if arg == "{input}" {
+                args.extend(inputs.iter().cloned());
+                continue;
+            }
+            args.push(
+                arg.replace("{output}", &output.display().to_string())
+                    .replace("{framerate}", &framerate.to_string())
+                    .replace("{duration}", &duration.to_string())
+                    .replace("{fade_out}", &(duration - fade).max(0.0).to_string())
+                    .replace("{fade}", &fade.to_string()),
+            );
+        }
+        Ok(args)
+    }
+
+    /// Processes the files recorded for one clip into `output`, removes them afterwards unless
+    /// the preset keeps them. `progress_cb` is called with the processed fraction.
+    pub fn process(
+        &self,
+        files: &[PathBuf],
+        framerate: u32,
+        duration: f32,
+        output: &Path,
+        progress_cb: impl Fn(f32),
+    ) -> Result<PathBuf> {
+        let args = self.args(files, framerate, duration, output)?;
+        run(&self.ffmpeg, &args, Some(duration), progress_cb)?;
+        if !self.preset.keep_input {
+            for file in files {
+                if let Err(e) = std::fs::remove_file(file) {
+                    log::warn!("Couldn't remove {}, {}", file.display(), e);
+                }
+            }
+        }
+        Ok(output.to_owned())
+    }
+}
+
+/// Joins clips with the same codecs into `output` without reencoding them
+pub fn concat(ffmpeg: &Path, clips: &[PathBuf], output: &Path) -> Result<PathBuf> {
+    // The list goes into the temp folder, next to the output it could replace a file of the user
+    let list = std::env::temp_dir().join(format!(
+        "tf2dp-concat-{}-{:08x}.txt",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    let content: String = clips
+        .iter()
+        .map(|c| {
+            let path = std::path::absolute(c).unwrap_or(c.clone());
+            format!(
+                "file '{}'\n",
+                path.display().to_string().replace('\'', "'\\''")
+            )
+        })
+        .collect();
+    std::fs::File::create_new(&list)?.write_all(content.as_bytes())?;
+
+    let res = run(
+        ffmpeg,
+        &[
+            "-y".to_owned(),
+            "-f".to_owned(),
+            "concat".to_owned(),
+            "-safe".to_owned(),
+            "0".to_owned(),
+            "-i".to_owned(),
+            list.display().to_string(),
+            "-c".to_owned(),
+            "copy".to_owned(),
+            output.display().to_string(),
+        ],
+        None,
+        |_| {},
+    );
+    let _ = std::fs::remove_file(&list);
+    res.map(|_| output.to_owned())
+}
+
+/// Runs ffmpeg and reports progress if the length of the output is known
+fn run(
+    ffmpeg: &Path,
+    args: &[String],
+    duration: Option<f32>,
+    progress_cb: impl Fn(f32),
+) -> Result<()> {
+    log::debug!("Running {} {}", ffmpeg.display(), args.join(" "));
+    let mut child = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostats", "-progress", "pipe:1"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Couldn't run {}", ffmpeg.display()))?;
+
+    // stderr is read on another thread so ffmpeg can't block on a full pipe
+    let stderr = child.stderr.take().unwrap();
+    let errors = std::thread::spawn(move || {
+        let mut lines: Vec<String> = BufReader::new(stderr)
+            .lines()
+            .map_while(Result::ok)
+            .collect();
+        lines.split_off(lines.len().saturating_sub(5)).join("\n")
+    });
+
+    for line in BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map_while(Result::ok)
+    {
+        if let (Some(time), Some(duration)) = (parse_progress(&line), duration) {
+            if duration > 0.0 {
+                progress_cb((time / duration).clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    let errors = errors.join().unwrap_or_default();
+    if !status.success() {
+        bail!("ffmpeg failed ({status}): {errors}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::test_util::TempFolder;
+
+    fn paths(files: &[&str]) -> Vec<PathBuf> {
+        files.iter().map(PathBuf::from).collect()
+    }
+
+    fn preset(name: &str) -> PostProcessor {
+        FfmpegSettings::default().processor(Some(name)).unwrap()
+    }
+
+    #[test]
+    fn frames_and_audio_become_inputs() {
+        let files = paths(&[
+            "rec/clip_1-2.wav",
+            "rec/clip_1-20001.tga",
+            "rec/clip_1-20000.tga",
+        ]);
+        let args = preset("H.264")
+            .args(&files, 60, 10.0, Path::new("out/clip.mp4"))
+            .unwrap();
+        assert_eq!(
+            args,
+            [
+                "-y",
+                "-framerate",
+                "60",
+                "-i",
+                "rec/clip_1-2%04d.tga",
+                "-i",
+                "rec/clip_1-2.wav",
+                "-c:v",
+                "libx264",
+                "-crf",
+                "18",
+                "-pix_fmt",
+                "yuv420p",
+                "-c:a",
+                "aac",
+                "-b:a",
+                "192k",
+                "out/clip.mp4",
+            ]
+        );
+    }
+
+    #[test]
+    fn fades_are_placed_at_both_ends() {
+        let args = preset("H.264 with fades")
+            .args(&paths(&["clip.webm"]), 60, 10.0, Path::new("clip.mp4"))
+            .unwrap();
+        assert_eq!(args[1..3], ["-i", "clip.webm"]);
+        assert_eq!(
+            args[3..5],
+            ["-vf", "fade=t=in:d=0.5,fade=t=out:st=9.5:d=0.5"]
+        );
+        assert_eq!(
+            args[5..7],
+            ["-af", "afade=t=in:d=0.5,afade=t=out:st=9.5:d=0.5"]
+        );
+    }
+
+    #[test]
+    fn nothing_recorded_is_an_error() {
+        assert!(preset("H.264")
+            .args(&[], 60, 10.0, Path::new("clip.mp4"))
+            .is_err());
+    }
+
+    #[test]
+    fn progress_lines_are_parsed() {
+        // A block of `-progress pipe:1` output
+        let output = "frame=120\n\
+            fps=59.94\n\
+            stream_0_0_q=28.0\n\
+            bitrate=1804.3kbits/s\n\
+            total_size=451072\n\
+            out_time_us=2000000\n\
+            out_time_ms=2000000\n\
+            out_time=00:00:02.000000\n\
+            dup_frames=0\n\
+            drop_frames=0\n\
+            speed=3.98x\n\
+            progress=continue";
+        let times: Vec<f32> = output.lines().filter_map(parse_progress).collect();
+        assert_eq!(times, [2.0, 2.0]);
+        // Before the first frame is written
+        assert_eq!(parse_progress("out_time_us=N/A"), None);
+        assert_eq!(parse_progress("out_time=00:00:02.000000"), None);
+    }
+
+    /// Path of a fake ffmpeg, it writes its arguments to `<output>.args`, the concat list to
+    /// `<output>.list` and fails for inputs containing "missing"
+    #[cfg(unix)]
+    fn stub_ffmpeg() -> &'static Path {
+        use std::os::unix::fs::PermissionsExt;
+        static STUB: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+        STUB.get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("tf2dp-{}-ffmpeg", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let script = dir.join("ffmpeg");
+            std::fs::write(
+                &script,
+                r#"#!/bin/sh
+for arg; do
+    case "$prev $arg" in
+        "-i "*missing*) echo "$arg: No such file or directory" >&2; exit 1 ;;
+        "-i "*.txt) list=$arg ;;
+    esac
+    prev=$arg
+done
+printf '%s\n' "$@" > "$arg.args"
+[ -n "$list" ] && cp "$list" "$arg.list"
+printf 'frame=60\nout_time_us=2000000\nout_time_ms=2000000\nprogress=continue\n'
+printf 'frame=120\nout_time_us=4000000\nout_time_ms=4000000\nprogress=end\n'
+: > "$arg"
+"#,
+            )
+            .unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+            script
+        })
+    }
+
+    /// A preset that runs the fake ffmpeg
+    #[cfg(unix)]
+    fn stub_preset(name: &str) -> PostProcessor {
+        PostProcessor {
+            ffmpeg: stub_ffmpeg().to_path_buf(),
+            ..preset(name)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn process_runs_ffmpeg_and_reports_progress() {
+        let folder = TempFolder::new("process");
+        let input = folder.0.join("clip.webm");
+        std::fs::write(&input, b"movie").unwrap();
+        let output = folder.0.join("clip.mp4");
+
+        let progress = std::sync::Mutex::new(Vec::new());
+        let processed = stub_preset("H.264")
+            .process(std::slice::from_ref(&input), 60, 4.0, &output, |p| {
+                progress.lock().unwrap().push(p)
+            })
+            .unwrap();
+
+        assert_eq!(processed, output);
+        assert!(output.exists());
+        assert!(!input.exists());
+        let mut progress = progress.into_inner().unwrap();
+        progress.dedup();
+        assert_eq!(progress, [0.5, 1.0]);
+        let args = std::fs::read_to_string(folder.0.join("clip.mp4.args")).unwrap();
+        assert!(args.starts_with("-hide_banner\n-nostats\n-progress\npipe:1\n-y\n-i\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failures_keep_the_input_and_report_ffmpeg_errors() {
+        let folder = TempFolder::new("process-failed");
+        let input = folder.0.join("missing.webm");
+        std::fs::write(&input, b"movie").unwrap();
+
+        let err = stub_preset("H.264")
+            .process(
+                std::slice::from_ref(&input),
+                60,
+                4.0,
+                &folder.0.join("out.mp4"),
+                |_| {},
+            )
+            .unwrap_err();
+
+        assert!(
+            err.to_string()
+                .ends_with("missing.webm: No such file or directory"),
+            "{err}"
+        );
+        assert!(input.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn concat_lists_clips_in_a_temp_file() {
+        let folder = TempFolder::new("concat");
+        let user_file = folder.0.join("joined.txt");
+        std::fs::write(&user_file, b"keep me").unwrap();
+        let clips = [folder.0.join("a.mp4"), folder.0.join("it's.mp4")];
+        let output = folder.0.join("joined.mp4");
+
+        assert_eq!(concat(stub_ffmpeg(), &clips, &output).unwrap(), output);
+
+        let list = std::fs::read_to_string(folder.0.join("joined.mp4.list")).unwrap();
+        assert_eq!(
+            list,
+            format!(
+                "file '{}'\nfile '{}'\n",
+                clips[0].display(),
+                folder.0.join("it'\\''s.mp4").display()
+            )
+        );
+        assert_eq!(std::fs::read(&user_file).unwrap(), b"keep me");
+        let args = std::fs::read_to_string(folder.0.join("joined.mp4.args")).unwrap();
+        let list_file = args.lines().skip_while(|a| *a != "-i").nth(1).unwrap();
+        assert!(!Path::new(list_file).exists());
+    }
+}
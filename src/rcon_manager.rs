@@ -1,14 +1,55 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_std::channel::{self, Receiver, Sender};
 use rcon::{AsyncStdStream, Connection, Error};
+use serde::{Deserialize, Serialize};
 
 use crate::demo_manager::Demo;
-use anyhow::Result;
+use crate::recorder::Codec;
+use anyhow::{anyhow, Result};
+
+/// Commands that take longer are considered failed and the connection is reset
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Reconnect delay after the first failed attempt, doubles with every further failure
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// An empty command is sent after being idle this long, to notice TF2 being closed
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The timeouts and delays of the connection, the constants above unless changed for tests
+#[derive(Debug, Clone, Copy)]
+struct Timeouts {
+    command: Duration,
+    connect: Duration,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    keepalive: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            command: COMMAND_TIMEOUT,
+            connect: CONNECT_TIMEOUT,
+            min_backoff: MIN_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+            keepalive: KEEPALIVE_INTERVAL,
+        }
+    }
+}
 
+/// Console commands that can be sent to TF2
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum Command<'a> {
     PlayDemo(&'a Demo),
     SkipToTick(u32, bool),
-    SkipRelative(u32, bool),
+    SkipRelative(i32, bool),
     SetEndTick(u32),
     DemoDebug(bool),
     PausePlayback(),
@@ -16,13 +57,19 @@ pub enum Command<'a> {
     TogglePlayback(),
     StopPlayback(),
     SetPlaybackSpeed(f32),
-    /* StartRecording(&'a str, Codec), */
+    HostFramerate(u32),
+    StartRecording(&'a str, Codec),
+    StopRecording(),
+    DemoInfo(),
+    SpectatePlayer(&'a str),
+    /// Any console command, e.g. from a user defined macro
+    Raw(&'a str),
 }
 
 impl Command<'_> {
     pub fn get_command(&self) -> String {
         match self {
-            Command::PlayDemo(d) => format!("disconnect; playdemo \"{}\"", d.get_path()),
+            Command::PlayDemo(d) => play_command(&d.get_path()),
             Command::SkipToTick(t, p) => format!("demo_gototick {} 0 {}", t, *p as u8),
             Command::SkipRelative(t, p) => format!("demo_gototick {} 1 {}", t, *p as u8),
             Command::SetEndTick(t) => format!("demo_setendtick {}", t),
@@ -32,81 +79,848 @@ impl Command<'_> {
             Command::SetPlaybackSpeed(s) => format!("demo_timescale {:.2}", s),
             Command::TogglePlayback() => "demo_togglepause".to_owned(),
             Command::StopPlayback() => "stopdemo".to_owned(),
-            /* Command::StartRecording(name, codec) => format!("startmovie \"{}\" {}", name, codec.params()), */
+            Command::HostFramerate(f) => format!("host_framerate {}", f),
+            Command::StartRecording(name, codec) => {
+                format!("startmovie \"{}\" {}", name, codec.params())
+            }
+            Command::StopRecording() => "endmovie".to_owned(),
+            Command::DemoInfo() => "demo_info".to_owned(),
+            Command::SpectatePlayer(name) => {
+                format!("spec_player \"{}\"", name.replace(['"', ';'], ""))
+            }
+            Command::Raw(command) => command.to_string(),
+        }
+    }
+}
+
+fn play_command(path: &str) -> String {
+    format!("disconnect; playdemo \"{}\"", path)
+}
+
+/// Where the game sees the demo folder, for games running on another machine
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathMapping {
+    /// Demo folder on this machine
+    pub local: PathBuf,
+    /// The same folder on the machine running the game, e.g. a network share
+    pub remote: String,
+}
+
+impl PathMapping {
+    /// Translates a local path, paths outside of the local folder are kept as they are
+    pub fn apply(&self, path: &Path) -> String {
+        let Ok(relative) = path.strip_prefix(&self.local) else {
+            return path.display().to_string();
+        };
+        // The separator of the remote path, in case the machines run different systems
+        let separator = if self.remote.contains('\\') {
+            "\\"
+        } else {
+            "/"
+        };
+        let mut remote = self.remote.trim_end_matches(['/', '\\']).to_owned();
+        for part in relative.iter() {
+            remote.push_str(separator);
+            remote.push_str(&part.to_string_lossy());
+        }
+        remote
+    }
+}
+
+/// A TF2 instance to control
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RconProfile {
+    /// Identifies the password in the keyring
+    pub id: u64,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// Only written to the settings file if it couldn't be stored in the keyring
+    pub password: String,
+    /// The password is kept in the keyring instead of the settings file
+    pub password_in_keyring: bool,
+    /// The password was read from the keyring, an empty password then means it was cleared
+    #[serde(skip)]
+    pub password_loaded: bool,
+    /// Needed if the game can't open the demos under the same path as this app
+    pub path_mapping: Option<PathMapping>,
+}
+
+impl Default for RconProfile {
+    fn default() -> Self {
+        Self {
+            id: rand::random(),
+            name: "Local".to_owned(),
+            host: "localhost".to_owned(),
+            port: 27015,
+            password: String::new(),
+            password_in_keyring: false,
+            password_loaded: false,
+            path_mapping: None,
         }
     }
 }
 
+impl std::fmt::Debug for RconProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RconProfile")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("password_in_keyring", &self.password_in_keyring)
+            .field("path_mapping", &self.path_mapping)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RconProfile {
+    pub fn keyring_key(&self) -> String {
+        format!("rcon-{:016x}", self.id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionState {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    /// TF2 rejected the password, not retried until the next command
+    AuthFailed,
+}
+
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConnectionState::Disconnected => "Disconnected",
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Connected => "Connected",
+            ConnectionState::AuthFailed => "Wrong password",
+        })
+    }
+}
+
+enum Request {
+    Connect(Sender<Result<()>>),
+    Command(String, Sender<Result<String>>),
+    Subscribe(Sender<ConnectionState>),
+}
+
+/// RCon connection to a running TF2
+///
+/// The connection is owned by a background task that keeps reconnecting with an increasing delay
+/// while TF2 isn't reachable. Commands are queued and sent one after another, so they can't
+/// interleave. Clones share the same connection, the task stops once all clones are dropped.
+#[derive(Clone)]
 pub struct RconManager {
-    conn: Option<Connection<AsyncStdStream>>,
-    password: String,
-    port: u16,
+    requests: Sender<Request>,
+    state: Arc<Mutex<ConnectionState>>,
+    path_mapping: Option<PathMapping>,
+    /// Playback position of the demo last started with [`Command::PlayDemo`]
+    clock: Arc<Mutex<Option<PlaybackClock>>>,
 }
 
 impl RconManager {
-    pub fn new(password: &str, port: u16) -> Self {
-        RconManager {
+    pub fn new(profile: &RconProfile) -> Self {
+        Self::with_timeouts(profile, Timeouts::default())
+    }
+
+    fn with_timeouts(profile: &RconProfile, timeouts: Timeouts) -> Self {
+        let (requests, receiver) = channel::unbounded();
+        let state = Arc::new(Mutex::new(ConnectionState::Disconnected));
+        let supervisor = Supervisor {
+            address: format!("{}:{}", profile.host, profile.port),
+            password: profile.password.clone(),
             conn: None,
-            password: password.to_string(),
-            port,
+            state: state.clone(),
+            subscribers: Vec::new(),
+            timeouts,
+        };
+        async_std::task::spawn(supervisor.run(receiver));
+        RconManager {
+            requests,
+            state,
+            path_mapping: profile.path_mapping.clone(),
+            clock: Arc::default(),
         }
     }
 
+    pub fn state(&self) -> ConnectionState {
+        *self.state.lock().unwrap()
+    }
+
     pub fn is_connected(&self) -> bool {
-        self.conn.is_some()
+        self.state() == ConnectionState::Connected
     }
 
-    pub async fn connect(&mut self) -> Result<()> {
-        let mut err: Result<()> = Ok(());
-        match <Connection<AsyncStdStream>>::builder()
-            .connect(&format!("localhost:{}", self.port), &self.password)
-            .await
-        {
-            Ok(c) => self.conn = Some(c),
-            Err(e) => err = Err(e.into()),
+    /// Receives the current connection state and every change after that
+    pub fn subscribe(&self) -> Receiver<ConnectionState> {
+        let (sender, receiver) = channel::unbounded();
+        let _ = self.requests.try_send(Request::Subscribe(sender));
+        receiver
+    }
+
+    /// Connects right away instead of waiting for the next attempt, errors are [`rcon::Error`]s
+    /// unless the attempt timed out
+    pub async fn connect(&self) -> Result<()> {
+        let (sender, receiver) = channel::bounded(1);
+        self.requests.send(Request::Connect(sender)).await?;
+        receiver.recv().await?
+    }
+
+    /// Queues a command and returns the console output, connects first if there is no connection
+    pub async fn send_command(&self, command: Command<'_>) -> Result<String> {
+        let command_line = match (&command, &self.path_mapping) {
+            (Command::PlayDemo(demo), Some(mapping)) => play_command(&mapping.apply(&demo.path)),
+            _ => command.get_command(),
         };
+        let (sender, receiver) = channel::bounded(1);
+        self.requests
+            .send(Request::Command(command_line, sender))
+            .await?;
+        let output = receiver.recv().await??;
 
-        if let Err(e) = &err {
-            log::error!("RCon connection failed: {:?}", e);
-        } else {
-            log::info!("Successfully connected to TF2");
+        let mut clock = self.clock.lock().unwrap();
+        match &command {
+            Command::PlayDemo(demo) => {
+                let length = demo.header.as_ref().map(|h| h.ticks).filter(|t| *t > 0);
+                *clock = Some(PlaybackClock::new(demo.tps(), length));
+            }
+            Command::StopPlayback() => *clock = None,
+            command => {
+                if let Some(clock) = clock.as_mut() {
+                    clock.apply(command, Instant::now());
+                }
+            }
         }
+        Ok(output)
+    }
+
+    pub async fn play_demo(&self, demo: &Demo) -> Result<String> {
+        self.send_command(Command::PlayDemo(demo)).await
+    }
+
+    pub async fn skip_to_tick(&self, tick: u32, pause: bool) -> Result<String> {
+        self.send_command(Command::SkipToTick(tick, pause)).await
+    }
+
+    pub async fn stop_playback(&self) -> Result<String> {
+        self.send_command(Command::StopPlayback()).await
+    }
 
-        err
+    /// The tick the game is currently playing, estimated by a [`PlaybackClock`]. `None` if no
+    /// demo is loaded or it wasn't started from here. The clock starts running once the game
+    /// reports the demo as playing, so the loading time isn't counted.
+    pub async fn demo_tick(&self) -> Result<Option<u32>> {
+        let output = self.send_command(Command::DemoInfo()).await?;
+        if !demo_playing(&output) {
+            return Ok(None);
+        }
+        let now = Instant::now();
+        let mut clock = self.clock.lock().unwrap();
+        Ok(clock.as_mut().map(|c| {
+            c.start(now);
+            c.tick_at(now)
+        }))
     }
+}
+
+/// Whether the output of `demo_info` says a demo is loaded, it prints the demo header then (the
+/// `Ticks` in there are the length of the demo, not the playback position)
+pub fn demo_playing(output: &str) -> bool {
+    let output = output.to_lowercase();
+    !output.trim().is_empty()
+        && !output.contains("no demo")
+        && !output.contains("not currently playing")
+}
+
+/// Follows the playback position of a demo from the commands sent to the game, as the console
+/// output doesn't include it. Seeking, pausing and speed changes done in the game itself aren't
+/// noticed, the next seek from here corrects the position.
+#[derive(Debug, Clone)]
+pub struct PlaybackClock {
+    /// Position at `since`
+    tick: f32,
+    since: Instant,
+    tps: f32,
+    speed: f32,
+    paused: bool,
+    /// The game reported the demo as playing, it is still loading before that
+    started: bool,
+    /// Playback pauses here, set with `demo_setendtick`
+    end_tick: Option<u32>,
+    /// Ticks in the demo header
+    length: Option<u32>,
+}
+
+impl PlaybackClock {
+    /// A demo that was just sent to the game, it plays from its beginning once [`Self::start`]ed
+    pub fn new(tps: f32, length: Option<u32>) -> Self {
+        Self {
+            tick: 0.0,
+            since: Instant::now(),
+            tps,
+            speed: 1.0,
+            paused: false,
+            started: false,
+            end_tick: None,
+            length,
+        }
+    }
+
+    /// Lets the clock run from `now` on if it isn't running already
+    pub fn start(&mut self, now: Instant) {
+        if !self.started {
+            self.started = true;
+            self.since = now;
+        }
+    }
+
+    fn position(&self, now: Instant) -> f32 {
+        let mut tick = self.tick;
+        if self.started && !self.paused {
+            tick += now.saturating_duration_since(self.since).as_secs_f32() * self.tps * self.speed;
+        }
+        [self.end_tick, self.length]
+            .into_iter()
+            .flatten()
+            .fold(tick, |tick, end| tick.min(end as f32))
+    }
+
+    pub fn tick_at(&self, now: Instant) -> u32 {
+        self.position(now) as u32
+    }
+
+    /// Updates the position for a command the game received at `now`
+    pub fn apply(&mut self, command: &Command, now: Instant) {
+        self.tick = self.position(now);
+        self.since = now;
+        match *command {
+            Command::SkipToTick(tick, pause) => {
+                self.tick = tick as f32;
+                self.paused = pause;
+            }
+            Command::SkipRelative(ticks, pause) => {
+                self.tick = (self.tick + ticks as f32).max(0.0);
+                self.paused = pause;
+            }
+            Command::PausePlayback() => self.paused = true,
+            Command::ResumePlayback() => self.paused = false,
+            Command::TogglePlayback() => self.paused = !self.paused,
+            Command::SetPlaybackSpeed(speed) => self.speed = speed,
+            Command::SetEndTick(tick) => self.end_tick = Some(tick),
+            _ => {}
+        }
+    }
+}
+
+struct Supervisor {
+    address: String,
+    password: String,
+    conn: Option<Connection<AsyncStdStream>>,
+    state: Arc<Mutex<ConnectionState>>,
+    subscribers: Vec<Sender<ConnectionState>>,
+    timeouts: Timeouts,
+}
 
-    pub async fn send_command(&mut self, command: Command<'_>) -> Result<String> {
-        log::debug!("Sending command: {}", command.get_command());
-        if !self.is_connected() {
+impl Supervisor {
+    async fn run(mut self, requests: Receiver<Request>) {
+        let mut backoff = Duration::ZERO;
+        loop {
+            let state = *self.state.lock().unwrap();
+            let wait = match state {
+                ConnectionState::Connected => Some(self.timeouts.keepalive),
+                ConnectionState::AuthFailed => None,
+                _ => Some(backoff),
+            };
+            let request = match wait {
+                Some(wait) => async_std::future::timeout(wait, requests.recv()).await,
+                None => Ok(requests.recv().await),
+            };
+
+            match request {
+                // All managers were dropped
+                Ok(Err(_)) => break,
+                Ok(Ok(Request::Connect(result))) => {
+                    let res = self.connect().await;
+                    backoff = if res.is_ok() {
+                        self.timeouts.min_backoff
+                    } else {
+                        backoff
+                    };
+                    let _ = result.send(res).await;
+                }
+                Ok(Ok(Request::Command(cmd, result))) => {
+                    let _ = result.send(self.command(&cmd).await).await;
+                }
+                Ok(Ok(Request::Subscribe(subscriber))) => {
+                    if subscriber.send(state).await.is_ok() {
+                        self.subscribers.push(subscriber);
+                    }
+                }
+                Err(_) if state == ConnectionState::Connected => {
+                    let _ = self.command("").await;
+                }
+                Err(_) => {
+                    backoff = match self.connect().await {
+                        Ok(_) => self.timeouts.min_backoff,
+                        Err(_) => (backoff * 2)
+                            .clamp(self.timeouts.min_backoff, self.timeouts.max_backoff),
+                    };
+                }
+            }
+        }
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        {
+            let mut current = self.state.lock().unwrap();
+            if *current == state {
+                return;
+            }
+            *current = state;
+        }
+        self.subscribers.retain(|s| s.try_send(state).is_ok());
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        self.conn = None;
+        self.set_state(ConnectionState::Connecting);
+        let res = async_std::future::timeout(
+            self.timeouts.connect,
+            <Connection<AsyncStdStream>>::builder().connect(&self.address, &self.password),
+        )
+        .await;
+
+        match res {
+            Ok(Ok(c)) => {
+                log::info!("Successfully connected to TF2");
+                self.conn = Some(c);
+                self.set_state(ConnectionState::Connected);
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                log::debug!("RCon connection failed: {:?}", e);
+                self.set_state(match e {
+                    Error::Auth => ConnectionState::AuthFailed,
+                    _ => ConnectionState::Disconnected,
+                });
+                Err(e.into())
+            }
+            Err(_) => {
+                log::debug!("RCon connection timed out");
+                self.set_state(ConnectionState::Disconnected);
+                Err(anyhow!("Connection timed out"))
+            }
+        }
+    }
+
+    async fn command(&mut self, cmd: &str) -> Result<String> {
+        if self.conn.is_none() {
             if let Err(e) = self.connect().await {
-                return Err(e.into());
+                log::error!("RCon connection failed: {:?}", e);
+                return Err(e);
             }
         }
 
+        log::debug!("Sending command: {}", redacted(cmd));
         let conn = self.conn.as_mut().unwrap();
-        let cmd = command.get_command();
-        log::debug!("Sending command: {}", cmd);
-        let res = conn.cmd(&cmd).await;
+        let res = match async_std::future::timeout(self.timeouts.command, conn.cmd(cmd)).await {
+            Ok(res) => res.map_err(anyhow::Error::from),
+            Err(_) => Err(anyhow!("Command timed out: {}", redacted(cmd))),
+        };
         log::debug!("Response: {:?}", res);
         if let Err(e) = &res {
-            match e {
-                Error::Io(_) => self.conn = None,
-                Error::Auth => self.conn = None,
-                Error::CommandTooLong => {}
+            if !matches!(e.downcast_ref(), Some(Error::CommandTooLong)) {
+                self.conn = None;
+                self.set_state(ConnectionState::Disconnected);
             }
         }
-        Ok(res?)
+        res
     }
+}
 
-    pub async fn play_demo(&mut self, demo: &Demo) -> Result<String> {
-        self.send_command(Command::PlayDemo(demo)).await
+/// Hides commands that could contain a password, like `rcon_password` in a macro, from the log
+fn redacted(cmd: &str) -> &str {
+    if cmd.to_lowercase().contains("password") {
+        "<hidden>"
+    } else {
+        cmd
     }
+}
 
-    pub async fn skip_to_tick(&mut self, tick: u32, pause: bool) -> Result<String> {
-        self.send_command(Command::SkipToTick(tick, pause)).await
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        time::Instant,
+    };
+
+    use super::*;
+
+    /// What the fake server does with a command
+    pub(crate) enum Reply {
+        Output(String),
+        /// Never answers
+        Hang,
     }
 
-    pub async fn stop_playback(&mut self) -> Result<String> {
-        self.send_command(Command::StopPlayback()).await
+    struct ServerState {
+        password: String,
+        /// Connections are closed right away while this is set
+        refuse: bool,
+        connections: Vec<Instant>,
+        commands: Vec<String>,
+        respond: Box<dyn Fn(&str) -> Reply + Send>,
+    }
+
+    /// RCON server on localhost that records the commands it gets
+    #[derive(Clone)]
+    pub(crate) struct FakeServer {
+        port: u16,
+        state: Arc<Mutex<ServerState>>,
+    }
+
+    impl FakeServer {
+        pub fn start(password: &str, respond: impl Fn(&str) -> Reply + Send + 'static) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let server = FakeServer {
+                port: listener.local_addr().unwrap().port(),
+                state: Arc::new(Mutex::new(ServerState {
+                    password: password.to_owned(),
+                    refuse: false,
+                    connections: Vec::new(),
+                    commands: Vec::new(),
+                    respond: Box::new(respond),
+                })),
+            };
+            let accepting = server.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().map_while(Result::ok) {
+                    let server = accepting.clone();
+                    std::thread::spawn(move || server.serve(stream));
+                }
+            });
+            server
+        }
+
+        pub fn profile(&self, password: &str) -> RconProfile {
+            RconProfile {
+                host: "127.0.0.1".to_owned(),
+                port: self.port,
+                password: password.to_owned(),
+                ..Default::default()
+            }
+        }
+
+        pub fn commands(&self) -> Vec<String> {
+            self.state.lock().unwrap().commands.clone()
+        }
+
+        /// Times the connections were opened at
+        fn connections(&self) -> Vec<Instant> {
+            self.state.lock().unwrap().connections.clone()
+        }
+
+        /// Closes open connections on their next packet and refuses new ones
+        fn set_refuse(&self, refuse: bool) {
+            self.state.lock().unwrap().refuse = refuse;
+        }
+
+        fn serve(&self, mut stream: TcpStream) -> std::io::Result<()> {
+            {
+                let mut state = self.state.lock().unwrap();
+                state.connections.push(Instant::now());
+                if state.refuse {
+                    return Ok(());
+                }
+            }
+            loop {
+                let (id, kind, body) = read_packet(&mut stream)?;
+                let mut state = self.state.lock().unwrap();
+                if state.refuse {
+                    return Ok(());
+                }
+                if kind == 3 {
+                    let accepted = body == state.password;
+                    // Source servers send an empty response before the auth result
+                    write_packet(&mut stream, id, 0, "")?;
+                    write_packet(&mut stream, if accepted { id } else { -1 }, 2, "")?;
+                    if !accepted {
+                        return Ok(());
+                    }
+                } else if body.is_empty() {
+                    // Marks the end of a response, or a keepalive
+                    write_packet(&mut stream, id, 0, "")?;
+                } else {
+                    state.commands.push(body.clone());
+                    let reply = (state.respond)(&body);
+                    drop(state);
+                    match reply {
+                        Reply::Output(output) => write_packet(&mut stream, id, 0, &output)?,
+                        Reply::Hang => {
+                            std::io::copy(&mut stream, &mut std::io::sink())?;
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> std::io::Result<(i32, i32, String)> {
+        let mut int = [0; 4];
+        stream.read_exact(&mut int)?;
+        let mut packet = vec![0; i32::from_le_bytes(int) as usize];
+        stream.read_exact(&mut packet)?;
+        let id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+        let kind = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+        let body = String::from_utf8_lossy(&packet[8..packet.len() - 2]).into_owned();
+        Ok((id, kind, body))
+    }
+
+    fn write_packet(stream: &mut TcpStream, id: i32, kind: i32, body: &str) -> std::io::Result<()> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        stream.write_all(&packet)
+    }
+
+    fn echo(command: &str) -> Reply {
+        Reply::Output(format!("echo {command}"))
+    }
+
+    fn fast_timeouts() -> Timeouts {
+        Timeouts {
+            command: Duration::from_millis(500),
+            connect: Duration::from_millis(500),
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(400),
+            keepalive: Duration::from_millis(50),
+        }
+    }
+
+    async fn wait_for(states: &Receiver<ConnectionState>, state: ConnectionState) {
+        let wait = async { while states.recv().await.unwrap() != state {} };
+        async_std::future::timeout(Duration::from_secs(5), wait)
+            .await
+            .unwrap_or_else(|_| panic!("never reached {state}"));
+    }
+
+    #[async_std::test]
+    async fn concurrent_commands_are_sent_in_order() {
+        let server = FakeServer::start("pw", |command| {
+            std::thread::sleep(Duration::from_millis(5));
+            echo(command)
+        });
+        let rcon = RconManager::with_timeouts(&server.profile("pw"), fast_timeouts());
+
+        let commands: Vec<String> = (0..20).map(|i| format!("say {i}")).collect();
+        let outputs = futures::future::join_all(
+            commands
+                .iter()
+                .map(|command| rcon.send_command(Command::Raw(command))),
+        )
+        .await;
+
+        for (command, output) in commands.iter().zip(outputs) {
+            assert_eq!(output.unwrap(), format!("echo {command}"));
+        }
+        assert_eq!(server.commands(), commands);
+        assert_eq!(server.connections().len(), 1);
+    }
+
+    #[async_std::test]
+    async fn reconnects_with_backoff_after_the_server_drops() {
+        let server = FakeServer::start("pw", echo);
+        let timeouts = fast_timeouts();
+        let rcon = RconManager::with_timeouts(&server.profile("pw"), timeouts);
+        rcon.connect().await.unwrap();
+        let states = rcon.subscribe();
+        // The supervisor might have connected on its own before
+        let connected = server.connections().len();
+
+        server.set_refuse(true);
+        wait_for(&states, ConnectionState::Disconnected).await;
+        async_std::task::sleep(Duration::from_millis(1500)).await;
+        let attempts = server.connections();
+        let gaps: Vec<Duration> = attempts[connected..]
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .collect();
+        assert!(gaps.len() >= 3, "{gaps:?}");
+        assert!(gaps[0] >= timeouts.min_backoff * 2, "{gaps:?}");
+        assert!(gaps[1] >= timeouts.max_backoff, "{gaps:?}");
+        assert!(
+            gaps.iter().all(|g| *g < timeouts.max_backoff * 2),
+            "{gaps:?}"
+        );
+
+        server.set_refuse(false);
+        wait_for(&states, ConnectionState::Connected).await;
+        let output = rcon.send_command(Command::Raw("say back")).await;
+        assert_eq!(output.unwrap(), "echo say back");
+    }
+
+    #[async_std::test]
+    async fn wrong_password_is_not_retried() {
+        let server = FakeServer::start("pw", echo);
+        let rcon = RconManager::with_timeouts(&server.profile("wrong"), fast_timeouts());
+
+        let err = rcon.connect().await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::Auth)));
+        assert_eq!(rcon.state(), ConnectionState::AuthFailed);
+        // The supervisor might have tried on its own before
+        let attempts = server.connections().len();
+        async_std::task::sleep(Duration::from_millis(600)).await;
+        assert_eq!(server.connections().len(), attempts);
+
+        // Sending a command tries again
+        assert!(rcon.send_command(Command::Raw("say hi")).await.is_err());
+        assert_eq!(server.connections().len(), attempts + 1);
+        assert_eq!(rcon.state(), ConnectionState::AuthFailed);
+        assert!(server.commands().is_empty());
+    }
+
+    #[async_std::test]
+    async fn commands_time_out() {
+        let server = FakeServer::start("pw", |command| match command {
+            "hang" => Reply::Hang,
+            _ => echo(command),
+        });
+        let rcon = RconManager::with_timeouts(&server.profile("pw"), fast_timeouts());
+
+        let started = Instant::now();
+        let err = rcon.send_command(Command::Raw("hang")).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+        assert!(started.elapsed() >= fast_timeouts().command);
+
+        // The hanging connection is dropped, the next command gets a new one
+        let output = rcon.send_command(Command::Raw("say hi")).await;
+        assert_eq!(output.unwrap(), "echo say hi");
+        assert_eq!(server.connections().len(), 2);
+    }
+
+    /// `demo_info` while a demo plays, its `Ticks` are the length of the demo
+    const DEMO_INFO: &str = "Demo contents for demos/2024-05-01_20-15-33.dem:\n\
+        Demo protocol 3, Network protocol 24\n\
+        Server: 192.168.1.10:27015\n\
+        Client: Nocrex\n\
+        Map: cp_process_final\n\
+        Game: tf\n\
+        Time: 1834.455, Ticks: 122297, Frames: 120354\n";
+    const NOT_PLAYING: &str = "Error - Not currently playing back a demo.\n";
+
+    #[test]
+    fn demo_info_tells_if_a_demo_plays() {
+        assert!(demo_playing(DEMO_INFO));
+        assert!(!demo_playing(NOT_PLAYING));
+        assert!(!demo_playing("No demo file open.\n"));
+        assert!(!demo_playing(""));
+        assert!(!demo_playing("\n"));
+    }
+
+    #[test]
+    fn clock_advances_with_the_speed() {
+        let start = Instant::now();
+        let mut clock = PlaybackClock::new(66.0, None);
+        clock.start(start);
+        clock.apply(&Command::SkipToTick(1000, false), start);
+        assert_eq!(clock.tick_at(start + Duration::from_secs(2)), 1132);
+
+        clock.apply(
+            &Command::SetPlaybackSpeed(0.5),
+            start + Duration::from_secs(2),
+        );
+        assert_eq!(clock.tick_at(start + Duration::from_secs(4)), 1198);
+    }
+
+    #[test]
+    fn clock_stops_while_paused() {
+        let start = Instant::now();
+        let mut clock = PlaybackClock::new(66.0, None);
+        clock.start(start);
+        clock.apply(&Command::SkipToTick(500, true), start);
+        assert_eq!(clock.tick_at(start + Duration::from_secs(3)), 500);
+
+        clock.apply(
+            &Command::SkipRelative(-10, true),
+            start + Duration::from_secs(3),
+        );
+        assert_eq!(clock.tick_at(start + Duration::from_secs(4)), 490);
+
+        clock.apply(&Command::TogglePlayback(), start + Duration::from_secs(4));
+        assert_eq!(clock.tick_at(start + Duration::from_secs(5)), 556);
+        clock.apply(&Command::PausePlayback(), start + Duration::from_secs(5));
+        assert_eq!(clock.tick_at(start + Duration::from_secs(9)), 556);
+    }
+
+    #[test]
+    fn clock_waits_for_the_demo_to_load() {
+        let start = Instant::now();
+        let mut clock = PlaybackClock::new(66.0, Some(10000));
+        assert_eq!(clock.tick_at(start + Duration::from_secs(5)), 0);
+        clock.apply(
+            &Command::SkipToTick(100, false),
+            start + Duration::from_secs(5),
+        );
+        assert_eq!(clock.tick_at(start + Duration::from_secs(8)), 100);
+
+        clock.start(start + Duration::from_secs(8));
+        assert_eq!(clock.tick_at(start + Duration::from_secs(9)), 166);
+        // Starting again doesn't reset the clock
+        clock.start(start + Duration::from_secs(9));
+        assert_eq!(clock.tick_at(start + Duration::from_secs(10)), 232);
+        assert_eq!(clock.tick_at(start + Duration::from_secs(1000)), 10000);
+    }
+
+    #[test]
+    fn clock_stops_at_the_end_tick() {
+        let start = Instant::now();
+        let mut clock = PlaybackClock::new(66.0, None);
+        clock.start(start);
+        clock.apply(&Command::SetEndTick(200), start);
+        clock.apply(&Command::SkipToTick(100, false), start);
+        assert_eq!(clock.tick_at(start + Duration::from_secs(10)), 200);
+    }
+
+    #[async_std::test]
+    async fn demo_tick_follows_the_sent_commands() {
+        let playing = Arc::new(Mutex::new(false));
+        let server = {
+            let playing = playing.clone();
+            FakeServer::start("pw", move |command| match command {
+                "demo_info" if *playing.lock().unwrap() => Reply::Output(DEMO_INFO.to_owned()),
+                "demo_info" => Reply::Output(NOT_PLAYING.to_owned()),
+                _ => Reply::Output(String::new()),
+            })
+        };
+        let rcon = RconManager::with_timeouts(&server.profile("pw"), fast_timeouts());
+        let demo = Demo::new("/demos/match.dem");
+
+        assert_eq!(rcon.demo_tick().await.unwrap(), None);
+        rcon.play_demo(&demo).await.unwrap();
+        assert_eq!(rcon.demo_tick().await.unwrap(), None);
+
+        // The clock starts with the first report of the demo playing, not with playdemo
+        async_std::task::sleep(Duration::from_millis(300)).await;
+        *playing.lock().unwrap() = true;
+        assert_eq!(rcon.demo_tick().await.unwrap(), Some(0));
+        async_std::task::sleep(Duration::from_millis(150)).await;
+        let tick = rcon.demo_tick().await.unwrap().unwrap();
+        assert!((9..60).contains(&tick), "{tick}");
+
+        rcon.skip_to_tick(4000, true).await.unwrap();
+        assert_eq!(rcon.demo_tick().await.unwrap(), Some(4000));
+
+        rcon.stop_playback().await.unwrap();
+        assert_eq!(rcon.demo_tick().await.unwrap(), None);
     }
 }
@@ -1,42 +1,511 @@
-// Useless file with ideas for a possible automatic demo recording system
+//! Records clips of demos by remote controlling TF2 over RCON.
+//!
+//! TF2 writes the movie files into its `tf` folder, so recording is considered done once these
+//! stop growing or TF2 reports that no demo is playing anymore. The files are then moved into the
+//! configured output folder and optionally processed with ffmpeg.
 
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    demo_manager::Demo,
+    macros,
+    postprocess::PostProcessor,
+    rcon_manager::{demo_playing, Command, RconManager},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum Codec {
+    #[default]
     WebM,
     H264,
-    TGA_WAV,
-    TGA,
-    WAV,
+    TgaWav,
+    Tga,
+    Wav,
 }
 
 impl Codec {
-    pub fn params(&self) -> String{
+    pub const ALL: [Codec; 5] = [
+        Codec::WebM,
+        Codec::H264,
+        Codec::TgaWav,
+        Codec::Tga,
+        Codec::Wav,
+    ];
+
+    /// Argument for `startmovie`
+    pub fn params(&self) -> &'static str {
+        match self {
+            Codec::WebM => "webm",
+            Codec::H264 => "h264",
+            Codec::TgaWav => "raw",
+            Codec::Tga => "tga",
+            Codec::Wav => "wav",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
         match self {
-            Codec::WebM => "webm".to_owned(),
-            Codec::H264 => "h264".to_owned(),
-            Codec::TGA_WAV => "raw".to_owned(),
-            Codec::TGA => "tga".to_owned(),
-            Codec::WAV => "wav".to_owned(),
+            Codec::WebM => "WebM",
+            Codec::H264 => "H.264",
+            Codec::TgaWav => "TGA + WAV",
+            Codec::Tga => "TGA",
+            Codec::Wav => "WAV",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordOptions {
+    pub framerate: u32,
+    pub codec: Codec,
+    /// Folder the recorded files are moved to, they stay in the tf folder if unset
+    pub output_folder: Option<PathBuf>,
+    /// Seconds recorded before a bookmark when queueing it
+    pub padding_before: f32,
+    /// Seconds recorded after a bookmark when queueing it
+    pub padding_after: f32,
+    /// Name of the ffmpeg preset the recorded files are processed with
+    pub postprocess: Option<String>,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        Self {
+            framerate: 60,
+            codec: Codec::default(),
+            output_folder: None,
+            padding_before: 5.0,
+            padding_after: 5.0,
+            postprocess: None,
+        }
+    }
+}
+
+/// A part of a demo to record
+#[derive(Debug, Clone)]
+pub struct Clip {
+    pub demo: Demo,
+    pub start_tick: u32,
+    pub end_tick: u32,
+}
+
+impl Clip {
+    /// Name of the recorded files: demo name plus in and out point, safe to quote in a console
+    /// command
+    pub fn name(&self) -> String {
+        let stem = self
+            .demo
+            .path
+            .file_stem()
+            .map_or("demo".into(), |s| s.to_string_lossy());
+        format!(
+            "{}_{}-{}",
+            macros::sanitize(&stem),
+            self.start_tick,
+            self.end_tick
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordStatus {
+    Loading,
+    /// Bytes written so far
+    Recording(u64),
+    Collecting,
+    /// Fraction processed by ffmpeg
+    Processing(f32),
+}
+
+/// Runs the command sequence for recording a [`Clip`]
+pub struct Recorder {
+    /// The `tf` folder inside the game folder, where TF2 writes movies to
+    pub tf_folder: PathBuf,
+    pub options: RecordOptions,
+    /// Time TF2 gets to load the demo before seeking
+    pub load_delay: Duration,
+    /// Recording fails if no files show up in this time
+    pub start_timeout: Duration,
+    /// Recording is done when the files didn't grow for this long
+    pub stall_timeout: Duration,
+    pub poll_interval: Duration,
+    pub postprocess: Option<PostProcessor>,
+}
+
+impl Recorder {
+    pub fn new(game_folder: &Path, options: RecordOptions) -> Self {
+        Self {
+            tf_folder: game_folder.join("tf"),
+            options,
+            load_delay: Duration::from_secs(10),
+            start_timeout: Duration::from_secs(30),
+            stall_timeout: Duration::from_secs(5),
+            poll_interval: Duration::from_millis(500),
+            postprocess: None,
+        }
+    }
+
+    /// Commands that start playing back and recording the clip
+    pub fn start_commands<'a>(&self, clip: &'a Clip, name: &'a str) -> Vec<Command<'a>> {
+        vec![
+            Command::SkipToTick(clip.start_tick, true),
+            Command::SetEndTick(clip.end_tick),
+            Command::HostFramerate(self.options.framerate),
+            Command::StartRecording(name, self.options.codec),
+            Command::ResumePlayback(),
+        ]
+    }
+
+    /// Commands that stop recording and restore the game settings
+    pub fn stop_commands(&self) -> Vec<Command<'static>> {
+        vec![
+            Command::StopRecording(),
+            Command::StopPlayback(),
+            Command::HostFramerate(0),
+        ]
+    }
+
+    /// Records the clip and returns the produced files
+    pub async fn record(
+        &self,
+        rcon: &RconManager,
+        clip: &Clip,
+        status_cb: impl Fn(RecordStatus),
+    ) -> Result<Vec<PathBuf>> {
+        if clip.start_tick >= clip.end_tick {
+            bail!("The in point has to be before the out point");
+        }
+        let name = clip.name();
+        if !self.output_files(&name)?.is_empty() {
+            bail!(
+                "Files for {name} already exist in {}",
+                self.tf_folder.display()
+            );
+        }
+
+        status_cb(RecordStatus::Loading);
+        rcon.send_command(Command::PlayDemo(&clip.demo)).await?;
+        async_std::task::sleep(self.load_delay).await;
+
+        let res = self.run(rcon, clip, &name, &status_cb).await;
+        for command in self.stop_commands() {
+            if let Err(e) = rcon.send_command(command).await {
+                log::warn!("Failed to stop recording: {e:?}");
+            }
+        }
+        res?;
+
+        status_cb(RecordStatus::Collecting);
+        let files = self.collect(&name)?;
+
+        let Some(postprocess) = &self.postprocess else {
+            return Ok(files);
+        };
+        status_cb(RecordStatus::Processing(0.0));
+        let folder = self
+            .options
+            .output_folder
+            .as_ref()
+            .unwrap_or(&self.tf_folder);
+        let mut output = folder.join(format!("{name}.{}", postprocess.preset.extension));
+        if files.contains(&output) {
+            output.set_file_name(format!("{name}_processed.{}", postprocess.preset.extension));
+        }
+        let duration = (clip.end_tick - clip.start_tick) as f32 / clip.demo.tps();
+        // ffmpeg is waited for on a blocking thread, its progress comes back over a channel
+        let (progress, updates) = async_std::channel::unbounded();
+        let processing = {
+            let postprocess = postprocess.clone();
+            let files = files.clone();
+            let framerate = self.options.framerate;
+            async_std::task::spawn_blocking(move || {
+                postprocess.process(&files, framerate, duration, &output, |p| {
+                    let _ = progress.try_send(p);
+                })
+            })
+        };
+        while let Ok(progress) = updates.recv().await {
+            status_cb(RecordStatus::Processing(progress));
+        }
+        let output = processing.await?;
+        if postprocess.preset.keep_input {
+            Ok([files, vec![output]].concat())
+        } else {
+            Ok(vec![output])
+        }
+    }
+
+    async fn run(
+        &self,
+        rcon: &RconManager,
+        clip: &Clip,
+        name: &str,
+        status_cb: &impl Fn(RecordStatus),
+    ) -> Result<()> {
+        if !demo_playing(&rcon.send_command(Command::DemoInfo()).await?) {
+            bail!("TF2 couldn't load {}", clip.demo.filename);
+        }
+        for command in self.start_commands(clip, name) {
+            rcon.send_command(command).await?;
+        }
+
+        let started = Instant::now();
+        let mut last_size = 0;
+        let mut last_change = Instant::now();
+        loop {
+            async_std::task::sleep(self.poll_interval).await;
+            // Also notices TF2 being closed, send_command fails then
+            let playing = demo_playing(&rcon.send_command(Command::DemoInfo()).await?);
+            let size = self
+                .output_files(name)?
+                .iter()
+                .filter_map(|f| std::fs::metadata(f).ok())
+                .map(|m| m.len())
+                .sum();
+            if size != last_size {
+                last_size = size;
+                last_change = Instant::now();
+                status_cb(RecordStatus::Recording(size));
+            } else if size == 0 && started.elapsed() > self.start_timeout {
+                bail!(
+                    "TF2 didn't write any files to {}, check the TF2 folder setting",
+                    self.tf_folder.display()
+                );
+            } else if size > 0 && (!playing || last_change.elapsed() > self.stall_timeout) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// The movie files of the clip `name`: `name.ext`, or `name0000.tga` for numbered frames
+    fn output_files(&self, name: &str) -> Result<Vec<PathBuf>> {
+        let pattern = format!(
+            "{}/{}*",
+            glob::Pattern::escape(&self.tf_folder.to_string_lossy()),
+            glob::Pattern::escape(name)
+        );
+        Ok(glob::glob(&pattern)?
+            .filter_map(Result::ok)
+            .filter(|f| is_output_file(f, name))
+            .collect())
+    }
+
+    /// Moves the recorded files into the output folder
+    fn collect(&self, name: &str) -> Result<Vec<PathBuf>> {
+        let files = self.output_files(name)?;
+        let Some(folder) = &self.options.output_folder else {
+            return Ok(files);
+        };
+        std::fs::create_dir_all(folder)?;
+        let mut moved = Vec::new();
+        for file in files {
+            let target = folder.join(file.file_name().unwrap());
+            if std::fs::rename(&file, &target).is_err() {
+                // rename doesn't work across file systems
+                std::fs::copy(&file, &target)?;
+                std::fs::remove_file(&file)?;
+            }
+            moved.push(target);
         }
+        Ok(moved)
     }
 }
 
-// BIG TODO
+/// Whether `file` was written for the clip `name`, and not for one whose name starts the same,
+/// e.g. `demo_100-2000` for `demo_100-200`
+fn is_output_file(file: &Path, name: &str) -> bool {
+    let Some(suffix) = file
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.strip_prefix(name))
+    else {
+        return false;
+    };
+    suffix.is_empty()
+        || (suffix.len() == 4
+            && suffix.bytes().all(|b| b.is_ascii_digit())
+            && file.extension().is_some_and(|e| e == "tga"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        rcon_manager::tests::{FakeServer, Reply},
+        test_util::TempFolder,
+    };
+
+    const PLAYING: &str = "Demo contents for demos/match.dem:";
+    const NOT_PLAYING: &str = "Error - Not currently playing back a demo.";
+
+    /// Game folder with an empty `tf` folder
+    fn game_folder(name: &str) -> TempFolder {
+        let folder = TempFolder::new(name);
+        std::fs::create_dir_all(folder.0.join("tf")).unwrap();
+        folder
+    }
+
+    fn recorder(game_folder: &Path) -> Recorder {
+        Recorder {
+            load_delay: Duration::ZERO,
+            start_timeout: Duration::from_secs(2),
+            stall_timeout: Duration::from_millis(100),
+            poll_interval: Duration::from_millis(20),
+            ..Recorder::new(game_folder, RecordOptions::default())
+        }
+    }
+
+    fn clip() -> Clip {
+        Clip {
+            demo: Demo::new("/demos/match.dem"),
+            start_tick: 100,
+            end_tick: 200,
+        }
+    }
+
+    /// The commands the server got, without the polls for the playback state
+    fn sent(server: &FakeServer) -> Vec<String> {
+        server
+            .commands()
+            .into_iter()
+            .filter(|c| c != "demo_info")
+            .collect()
+    }
+
+    #[test]
+    fn output_files_only_match_the_clip() {
+        let folder = game_folder("output-files");
+        for file in [
+            "match_100-200.webm",
+            "match_100-200.wav",
+            "match_100-2000000.tga",
+            "match_100-2000001.tga",
+            "match_100-2000.webm",
+            "match_100-2000.wav",
+            "match_100-20000000.tga",
+            "match_100-2000000.webm",
+            "match_100-200.json.txt",
+        ] {
+            std::fs::write(folder.0.join("tf").join(file), b"").unwrap();
+        }
+
+        let mut files = recorder(&folder.0).output_files("match_100-200").unwrap();
+        files.sort();
+        let names: Vec<_> = files
+            .iter()
+            .map(|f| f.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "match_100-200.wav",
+                "match_100-200.webm",
+                "match_100-2000000.tga",
+                "match_100-2000001.tga",
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn records_with_the_command_sequence() {
+        let folder = game_folder("record");
+        let tf = folder.0.join("tf");
+        let server = FakeServer::start("pw", move |command| {
+            if command.starts_with("startmovie") {
+                std::fs::write(tf.join("match_100-200.webm"), b"movie").unwrap();
+            }
+            Reply::Output(if command == "demo_info" { PLAYING } else { "" }.to_owned())
+        });
+        let rcon = RconManager::new(&server.profile("pw"));
+
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let files = {
+            let statuses = statuses.clone();
+            recorder(&folder.0)
+                .record(&rcon, &clip(), move |s| statuses.lock().unwrap().push(s))
+                .await
+                .unwrap()
+        };
+
+        assert_eq!(files, vec![folder.0.join("tf/match_100-200.webm")]);
+        assert_eq!(
+            sent(&server),
+            [
+                "disconnect; playdemo \"/demos/match.dem\"",
+                "demo_gototick 100 0 1",
+                "demo_setendtick 200",
+                "host_framerate 60",
+                "startmovie \"match_100-200\" webm",
+                "demo_resume",
+                "endmovie",
+                "stopdemo",
+                "host_framerate 0",
+            ]
+        );
+        assert_eq!(
+            *statuses.lock().unwrap(),
+            [
+                RecordStatus::Loading,
+                RecordStatus::Recording(5),
+                RecordStatus::Collecting
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn fails_if_the_demo_does_not_load() {
+        let folder = game_folder("record-not-loaded");
+        let server = FakeServer::start("pw", |command| {
+            Reply::Output(
+                if command == "demo_info" {
+                    NOT_PLAYING
+                } else {
+                    ""
+                }
+                .to_owned(),
+            )
+        });
+        let rcon = RconManager::new(&server.profile("pw"));
 
-/*
-Idea: 
+        let err = recorder(&folder.0)
+            .record(&rcon, &clip(), |_| {})
+            .await
+            .unwrap_err();
 
-Have buttons to set In/Out Markers on the timeline
-Button "Record"
-Opens up Window with settings
-+ Framerate
-+ Codec
-+ Save location
-+ Postprocessing with ffmpeg?
+        assert_eq!(err.to_string(), "TF2 couldn't load match.dem");
+        assert_eq!(
+            sent(&server),
+            [
+                "disconnect; playdemo \"/demos/match.dem\"",
+                "endmovie",
+                "stopdemo",
+                "host_framerate 0",
+            ]
+        );
+    }
 
-Button to start recording
-Sends disconnect, playdemo to tf2, skips to in point and pauses, sets end tick to out point, set framerate, startmovie, resume playback
-name could be demoname + in/outpoint
-when playback finishes endmovie, copy generated files to selected directory
+    #[test]
+    fn clip_names_cannot_break_out_of_the_command() {
+        let clip = Clip {
+            demo: Demo::new("/demos/x\"; quit; \"y.dem"),
+            ..clip()
+        };
+        let name = clip.name();
+        assert_eq!(name, "x quit y_100-200");
 
-if ffmpeg is selected run that command
-*/
\ No newline at end of file
+        let commands = recorder(Path::new("/tf2")).start_commands(&clip, &name);
+        assert!(commands
+            .iter()
+            .map(|c| c.get_command())
+            .any(|c| c == "startmovie \"x quit y_100-200\" webm"));
+    }
+}
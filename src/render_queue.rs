@@ -0,0 +1,337 @@
+//! Queue of clips that are recorded one after another, saved to disk so it survives restarts
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    demo_manager::{Demo, Event},
+    paths,
+    rcon_manager::RconManager,
+    recorder::{Clip, RecordStatus, Recorder},
+};
+
+const QUEUE_FILE: &str = "render_queue.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum JobStatus {
+    Pending,
+    Recording,
+    /// Contains the recorded files
+    Done(Vec<PathBuf>),
+    Failed(String),
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Done(_) | JobStatus::Failed(_))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderJob {
+    pub id: u64,
+    pub demo: PathBuf,
+    /// Name of the bookmark the clip was created from
+    pub label: String,
+    pub start_tick: u32,
+    pub end_tick: u32,
+    pub status: JobStatus,
+}
+
+impl RenderJob {
+    /// Creates a job recording `before` seconds before to `after` seconds after the event
+    pub fn from_event(demo: &Demo, event: &Event, before: f32, after: f32) -> Self {
+        let tps = demo.tps();
+        let end = demo.header.as_ref().map_or(u32::MAX, |h| h.ticks);
+        Self {
+            id: 0,
+            demo: demo.path.clone(),
+            label: event.title.clone(),
+            start_tick: event.tick.saturating_sub((before * tps).round() as u32),
+            end_tick: event
+                .tick
+                .saturating_add((after * tps).round() as u32)
+                .min(end),
+            status: JobStatus::Pending,
+        }
+    }
+
+    /// Reads the demo again, it might have been moved since the job was queued
+    pub async fn clip(&self) -> Result<Clip> {
+        if !self.demo.exists() {
+            bail!("{} doesn't exist anymore", self.demo.display());
+        }
+        let path = self.demo.clone();
+        let demo = async_std::task::spawn_blocking(move || {
+            let mut demo = Demo::new(&path);
+            demo.read_data();
+            demo
+        })
+        .await;
+        Ok(Clip {
+            demo,
+            start_tick: self.start_tick,
+            end_tick: self.end_tick,
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RenderQueue {
+    next_id: u64,
+    pub jobs: Vec<RenderJob>,
+}
+
+impl RenderQueue {
+    /// Loads the queue file, jobs that were interrupted by closing the app are pending again
+    pub fn load() -> Self {
+        match std::fs::read(paths::state_file(QUEUE_FILE)) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn parse(content: &[u8]) -> Self {
+        let mut queue = serde_json::from_slice::<RenderQueue>(content).unwrap_or_else(|e| {
+            log::warn!("Couldn't parse render queue, {}", e);
+            Self::default()
+        });
+        for job in &mut queue.jobs {
+            if job.status == JobStatus::Recording {
+                job.status = JobStatus::Pending;
+            }
+        }
+        queue
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = std::fs::write(
+            paths::state_file(QUEUE_FILE),
+            serde_json::to_string_pretty(self).unwrap(),
+        ) {
+            log::warn!("Couldn't save render queue, {}", e);
+        }
+    }
+
+    /// Adds a job to the end of the queue and returns its id
+    pub fn push(&mut self, mut job: RenderJob) -> u64 {
+        job.id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(job);
+        self.next_id - 1
+    }
+
+    pub fn get(&self, id: u64) -> Option<&RenderJob> {
+        self.jobs.iter().find(|j| j.id == id)
+    }
+
+    /// Removes a job, unless it is being recorded
+    pub fn remove(&mut self, id: u64) {
+        self.jobs
+            .retain(|j| j.id != id || j.status == JobStatus::Recording);
+    }
+
+    /// Queues a finished job again
+    pub fn retry(&mut self, id: u64) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            if job.status.is_finished() {
+                job.status = JobStatus::Pending;
+            }
+        }
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|j| !j.status.is_finished());
+    }
+
+    pub fn set_status(&mut self, id: u64, status: JobStatus) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            job.status = status;
+        }
+    }
+
+    pub fn next_pending(&self) -> Option<&RenderJob> {
+        self.jobs.iter().find(|j| j.status == JobStatus::Pending)
+    }
+
+    pub fn pending(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|j| j.status == JobStatus::Pending)
+            .count()
+    }
+}
+
+/// Records the pending jobs in order until the queue is empty or `stop` is set, the queue is saved
+/// after every status change. `status_cb` is called with the id of the current job and its
+/// recording status, `None` once the job finished. Returns the number of recorded jobs.
+///
+/// The queue is only locked between jobs, so jobs can be added or removed in the meantime.
+pub async fn run(
+    queue: &Mutex<RenderQueue>,
+    rcon: &RconManager,
+    recorder: &Recorder,
+    stop: &AtomicBool,
+    status_cb: impl Fn(u64, Option<RecordStatus>),
+) -> usize {
+    let mut recorded = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let job = {
+            let mut queue = queue.lock().unwrap();
+            let Some(job) = queue.next_pending().cloned() else {
+                break;
+            };
+            queue.set_status(job.id, JobStatus::Recording);
+            queue.save();
+            job
+        };
+
+        let res = match job.clip().await {
+            Ok(clip) => {
+                recorder
+                    .record(rcon, &clip, |status| status_cb(job.id, Some(status)))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let status = match res {
+            Ok(files) => {
+                recorded += 1;
+                JobStatus::Done(files)
+            }
+            Err(e) => {
+                log::warn!("Failed to record {} ({}): {e:?}", job.label, job.id);
+                JobStatus::Failed(e.to_string())
+            }
+        };
+
+        {
+            let mut queue = queue.lock().unwrap();
+            queue.set_status(job.id, status);
+            queue.save();
+        }
+        status_cb(job.id, None);
+    }
+    recorded
+}
+
+#[cfg(test)]
+mod tests {
+    use tf_demo_parser::demo::header::Header;
+
+    use super::*;
+
+    fn job(label: &str, status: JobStatus) -> RenderJob {
+        RenderJob {
+            id: 0,
+            demo: PathBuf::from("/demos/a.dem"),
+            label: label.to_owned(),
+            start_tick: 0,
+            end_tick: 100,
+            status,
+        }
+    }
+
+    /// Queue with a recording, a pending, a done and a failed job, with ids 0 to 3
+    fn queue() -> RenderQueue {
+        let mut queue = RenderQueue::default();
+        queue.push(job("recording", JobStatus::Recording));
+        queue.push(job("pending", JobStatus::Pending));
+        queue.push(job("done", JobStatus::Done(vec![])));
+        queue.push(job("failed", JobStatus::Failed("error".to_owned())));
+        queue
+    }
+
+    fn labels(queue: &RenderQueue) -> Vec<&str> {
+        queue.jobs.iter().map(|j| j.label.as_str()).collect()
+    }
+
+    #[test]
+    fn interrupted_jobs_are_pending_after_loading() {
+        let content = serde_json::to_vec(&queue()).unwrap();
+        let mut queue = RenderQueue::parse(&content);
+        assert_eq!(queue.get(0).unwrap().status, JobStatus::Pending);
+        assert_eq!(queue.get(2).unwrap().status, JobStatus::Done(vec![]));
+        assert_eq!(queue.pending(), 2);
+        assert_eq!(queue.push(job("new", JobStatus::Pending)), 4);
+
+        assert!(RenderQueue::parse(b"not json").jobs.is_empty());
+    }
+
+    #[test]
+    fn recording_jobs_are_not_removed() {
+        let mut queue = queue();
+        queue.remove(0);
+        queue.remove(1);
+        assert_eq!(labels(&queue), ["recording", "done", "failed"]);
+    }
+
+    #[test]
+    fn only_finished_jobs_are_retried() {
+        let mut queue = queue();
+        for id in 0..4 {
+            queue.retry(id);
+        }
+        assert_eq!(queue.get(0).unwrap().status, JobStatus::Recording);
+        assert_eq!(queue.pending(), 3);
+    }
+
+    #[test]
+    fn finished_jobs_are_cleared() {
+        let mut queue = queue();
+        queue.clear_finished();
+        assert_eq!(labels(&queue), ["recording", "pending"]);
+    }
+
+    #[test]
+    fn jobs_are_recorded_in_order() {
+        let mut queue = queue();
+        queue.push(job("later", JobStatus::Pending));
+        assert_eq!(queue.next_pending().unwrap().label, "pending");
+        queue.set_status(1, JobStatus::Recording);
+        assert_eq!(queue.next_pending().unwrap().label, "later");
+        queue.set_status(4, JobStatus::Done(vec![]));
+        assert!(queue.next_pending().is_none());
+    }
+
+    #[test]
+    fn event_jobs_are_padded_and_capped() {
+        let mut demo = Demo::new("/demos/a.dem");
+        demo.header = Some(Header {
+            demo_type: "HL2DEMO".to_owned(),
+            version: 3,
+            protocol: 24,
+            server: String::new(),
+            nick: String::new(),
+            map: "pl_upward".to_owned(),
+            game: "tf".to_owned(),
+            duration: 100.0,
+            ticks: 6600,
+            frames: 0,
+            signon: 0,
+        });
+        let event = |tick| Event {
+            tick,
+            title: "Killstreak".to_owned(),
+            ev_type: "Killstreak".to_owned(),
+        };
+
+        let job = RenderJob::from_event(&demo, &event(1000), 5.0, 2.0);
+        assert_eq!((job.start_tick, job.end_tick), (670, 1132));
+        assert_eq!(job.label, "Killstreak");
+        assert_eq!(job.status, JobStatus::Pending);
+
+        let job = RenderJob::from_event(&demo, &event(100), 5.0, 2.0);
+        assert_eq!(job.start_tick, 0);
+        let job = RenderJob::from_event(&demo, &event(6500), 5.0, 2.0);
+        assert_eq!(job.end_tick, 6600);
+    }
+}
@@ -0,0 +1,518 @@
+//! Persistent index of analysed demos, for searching players, maps, chat and kills across the
+//! whole library
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analyser::{MatchEventType, MatchState, StableUserId},
+    demo_manager::{Demo, LibraryOptions},
+    paths,
+};
+
+const INDEX_FILE: &str = "search.index";
+/// Bump when [`IndexedDemo`] changes, older index files are discarded
+const INDEX_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedPlayer {
+    /// In the `[U:1:xxx]` format
+    pub steam_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedChat {
+    pub tick: u32,
+    pub from: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedKill {
+    pub tick: u32,
+    pub killer: Option<String>,
+    pub victim: String,
+    pub weapon: String,
+}
+
+/// The searchable parts of a [`MatchState`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedDemo {
+    pub path: PathBuf,
+    pub filename: String,
+    /// Used to detect changed files
+    pub size: Option<u64>,
+    pub modified: Option<SystemTime>,
+    pub map: String,
+    pub tps: f32,
+    pub players: Vec<IndexedPlayer>,
+    pub chat: Vec<IndexedChat>,
+    pub kills: Vec<IndexedKill>,
+}
+
+impl IndexedDemo {
+    pub fn new(demo: &Demo, state: &MatchState) -> Self {
+        let name = |u: &StableUserId| state.users[u].name.clone().unwrap_or_default();
+        Self {
+            path: demo.path.clone(),
+            filename: demo.filename.clone(),
+            size: demo.size,
+            modified: demo.modified,
+            map: demo
+                .header
+                .as_ref()
+                .map(|h| h.map.clone())
+                .unwrap_or_default(),
+            tps: demo.tps(),
+            players: state
+                .users
+                .iter()
+                .filter_map(|u| {
+                    Some(IndexedPlayer {
+                        steam_id: crate::util::steamid_to_32(u.steam_id.as_ref()?)?,
+                        name: u.name.clone().unwrap_or_default(),
+                    })
+                })
+                .collect(),
+            chat: state
+                .events
+                .iter()
+                .filter_map(|e| match &e.value {
+                    MatchEventType::Chat(msg) => Some(IndexedChat {
+                        tick: e.tick.into(),
+                        from: msg.from.clone(),
+                        text: msg.text.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect(),
+            kills: state
+                .events
+                .iter()
+                .filter_map(|e| match &e.value {
+                    MatchEventType::Kill(death) if !death.deadringer => Some(IndexedKill {
+                        tick: e.tick.into(),
+                        killer: death.killer.as_ref().map(name),
+                        victim: name(&death.victim),
+                        weapon: death.weapon.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    fn player(&self, query: &str) -> Vec<&IndexedPlayer> {
+        let steam_id = crate::util::steamid_to_32(query);
+        let query = query.to_lowercase();
+        self.players
+            .iter()
+            .filter(|p| match &steam_id {
+                Some(id) => &p.steam_id == id,
+                None => p.name.to_lowercase().contains(&query),
+            })
+            .collect()
+    }
+}
+
+/// All set fields have to match for a demo to be found
+#[derive(Debug, Default, Clone)]
+pub struct Query {
+    /// Steam ID in any format, or part of a name
+    pub player: Option<String>,
+    /// Part of the map name
+    pub map: Option<String>,
+    pub chat: Option<Regex>,
+    /// Part of a weapon name, only kills of `player` are checked if that is set too
+    pub weapon: Option<String>,
+}
+
+impl Query {
+    pub fn is_empty(&self) -> bool {
+        self.player.is_none() && self.map.is_none() && self.chat.is_none() && self.weapon.is_none()
+    }
+}
+
+/// A matching line of a found demo
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    /// `None` for matched players
+    pub tick: Option<u32>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub demo: IndexedDemo,
+    pub matches: Vec<SearchMatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchIndex {
+    version: u32,
+    demos: HashMap<PathBuf, IndexedDemo>,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        Self {
+            version: INDEX_VERSION,
+            demos: HashMap::new(),
+        }
+    }
+}
+
+impl SearchIndex {
+    /// Loads the index file, starts with an empty index if it doesn't exist or is outdated
+    pub fn load() -> Self {
+        let index = (|| {
+            let file = paths::cache_file(INDEX_FILE);
+            if !std::fs::exists(&file)? {
+                return Ok(Self::default());
+            }
+            let index: Self = bitcode::deserialize(&std::fs::read(file)?)?;
+            anyhow::Ok(index)
+        })()
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to load search index {e:?}");
+            Self::default()
+        });
+        if index.version != INDEX_VERSION {
+            log::info!("Search index is outdated, discarding it");
+            return Self::default();
+        }
+        index
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::write(paths::cache_file(INDEX_FILE), bitcode::serialize(self)?)?;
+        Ok(())
+    }
+
+    pub fn add(&mut self, demo: &Demo, state: &MatchState) {
+        self.demos
+            .insert(demo.path.clone(), IndexedDemo::new(demo, state));
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.demos.remove(path);
+    }
+
+    /// Whether the demo is indexed and hasn't changed since
+    pub fn contains(&self, demo: &Demo) -> bool {
+        self.demos
+            .get(&demo.path)
+            .is_some_and(|d| d.size == demo.size && d.modified == demo.modified)
+    }
+
+    pub fn len(&self) -> usize {
+        self.demos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.demos.is_empty()
+    }
+
+    /// Demos matching the query, sorted by path
+    pub fn search(&self, query: &Query) -> Vec<SearchResult> {
+        let mut results: Vec<SearchResult> = self
+            .demos
+            .values()
+            .filter_map(|demo| Self::search_demo(demo, query))
+            .collect();
+        results.sort_by(|a, b| a.demo.path.cmp(&b.demo.path));
+        results
+    }
+
+    fn search_demo(demo: &IndexedDemo, query: &Query) -> Option<SearchResult> {
+        if let Some(map) = &query.map {
+            if !demo.map.to_lowercase().contains(&map.to_lowercase()) {
+                return None;
+            }
+        }
+
+        let mut matches = Vec::new();
+        let players = match &query.player {
+            Some(player) => {
+                let players = demo.player(player);
+                if players.is_empty() {
+                    return None;
+                }
+                if query.chat.is_none() && query.weapon.is_none() {
+                    matches.extend(players.iter().map(|p| SearchMatch {
+                        tick: None,
+                        text: format!("{} {}", p.name, p.steam_id),
+                    }));
+                }
+                Some(players)
+            }
+            None => None,
+        };
+        let is_player = |name: &str| {
+            players
+                .as_ref()
+                .is_none_or(|p| p.iter().any(|p| p.name == name))
+        };
+
+        if let Some(chat) = &query.chat {
+            let len = matches.len();
+            matches.extend(
+                demo.chat
+                    .iter()
+                    .filter(|c| is_player(&c.from) && chat.is_match(&c.text))
+                    .map(|c| SearchMatch {
+                        tick: Some(c.tick),
+                        text: format!("{}: {}", c.from, c.text),
+                    }),
+            );
+            if matches.len() == len {
+                return None;
+            }
+        }
+
+        if let Some(weapon) = &query.weapon {
+            let len = matches.len();
+            let weapon = weapon.to_lowercase();
+            matches.extend(
+                demo.kills
+                    .iter()
+                    .filter(|k| {
+                        k.killer.as_deref().is_some_and(is_player)
+                            && k.weapon.to_lowercase().contains(&weapon)
+                    })
+                    .map(|k| SearchMatch {
+                        tick: Some(k.tick),
+                        text: format!(
+                            "{} killed {} with {}",
+                            k.killer.as_deref().unwrap_or_default(),
+                            k.victim,
+                            k.weapon
+                        ),
+                    }),
+            );
+            if matches.len() == len {
+                return None;
+            }
+        }
+
+        matches.sort_by_key(|m| m.tick);
+        Some(SearchResult {
+            demo: demo.clone(),
+            matches,
+        })
+    }
+}
+
+/// Demos to index, those of the library or of `demo_folder` if the library has no folders
+pub fn library_demos(library: &LibraryOptions, demo_folder: Option<&Path>) -> Vec<PathBuf> {
+    if !library.folders.is_empty() {
+        return library.find_demos();
+    }
+    match demo_folder {
+        Some(folder) => LibraryOptions {
+            folders: vec![folder.to_path_buf()],
+            ..Default::default()
+        }
+        .find_demos(),
+        None => Vec::new(),
+    }
+}
+
+/// Analyses the demos that aren't indexed yet or changed since (or all with `force`) and saves
+/// the index afterwards. `progress_cb` is called with (current, total), returns the number of
+/// newly indexed demos.
+///
+/// The index is only locked while adding a demo, so it can still be searched in the meantime.
+pub fn index_demos(
+    index: &Mutex<SearchIndex>,
+    paths: Vec<PathBuf>,
+    force: bool,
+    progress_cb: impl Fn(usize, usize),
+) -> Result<usize> {
+    let mut demos = Vec::new();
+    for path in paths {
+        let mut demo = Demo::new(path);
+        demo.read_data();
+        if force || !index.lock().unwrap().contains(&demo) {
+            demos.push(demo);
+        }
+    }
+
+    let total = demos.len();
+    let mut indexed = 0;
+    for (i, mut demo) in demos.into_iter().enumerate() {
+        progress_cb(i, total);
+        match pollster::block_on(demo.full_analysis()) {
+            Ok(state) => {
+                index.lock().unwrap().add(&demo, &state);
+                indexed += 1;
+            }
+            Err(e) => log::warn!("Failed to analyse {}: {e}", demo.path.display()),
+        }
+    }
+    progress_cb(total, total);
+
+    if indexed > 0 {
+        index.lock().unwrap().save()?;
+    }
+    Ok(indexed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demo(name: &str, map: &str) -> IndexedDemo {
+        IndexedDemo {
+            path: PathBuf::from(format!("/demos/{name}.dem")),
+            filename: format!("{name}.dem"),
+            size: Some(1),
+            modified: None,
+            map: map.to_owned(),
+            tps: 66.667,
+            players: vec![
+                IndexedPlayer {
+                    steam_id: "[U:1:2469]".to_owned(),
+                    name: "Scout Main".to_owned(),
+                },
+                IndexedPlayer {
+                    steam_id: "[U:1:1000]".to_owned(),
+                    name: "Pocket".to_owned(),
+                },
+            ],
+            chat: vec![
+                IndexedChat {
+                    tick: 200,
+                    from: "Pocket".to_owned(),
+                    text: "gg".to_owned(),
+                },
+                IndexedChat {
+                    tick: 100,
+                    from: "Scout Main".to_owned(),
+                    text: "good game".to_owned(),
+                },
+            ],
+            kills: vec![
+                IndexedKill {
+                    tick: 50,
+                    killer: Some("Scout Main".to_owned()),
+                    victim: "Pocket".to_owned(),
+                    weapon: "scattergun".to_owned(),
+                },
+                IndexedKill {
+                    tick: 60,
+                    killer: Some("Pocket".to_owned()),
+                    victim: "Scout Main".to_owned(),
+                    weapon: "tf_projectile_rocket".to_owned(),
+                },
+            ],
+        }
+    }
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::default();
+        for demo in [
+            demo("b", "cp_process_final"),
+            demo("a", "koth_product_final"),
+        ] {
+            index.demos.insert(demo.path.clone(), demo);
+        }
+        index
+    }
+
+    fn found(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.demo.filename.as_str()).collect()
+    }
+
+    #[test]
+    fn map_is_case_insensitive_part() {
+        let results = index().search(&Query {
+            map: Some("PROCESS".to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(found(&results), vec!["b.dem"]);
+    }
+
+    #[test]
+    fn player_by_name_or_any_steam_id_format() {
+        let index = index();
+        for player in ["scout", "[U:1:2469]", "STEAM_0:1:1234", "76561197960268197"] {
+            let results = index.search(&Query {
+                player: Some(player.to_owned()),
+                ..Default::default()
+            });
+            assert_eq!(found(&results), vec!["a.dem", "b.dem"], "{player}");
+            assert_eq!(results[0].matches.len(), 1);
+            assert_eq!(results[0].matches[0].text, "Scout Main [U:1:2469]");
+        }
+        let results = index.search(&Query {
+            player: Some("[U:1:5]".to_owned()),
+            ..Default::default()
+        });
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn chat_of_player_sorted_by_tick() {
+        let index = index();
+        let results = index.search(&Query {
+            chat: Some(Regex::new("^g").unwrap()),
+            ..Default::default()
+        });
+        let ticks: Vec<Option<u32>> = results[0].matches.iter().map(|m| m.tick).collect();
+        assert_eq!(ticks, vec![Some(100), Some(200)]);
+
+        let results = index.search(&Query {
+            player: Some("pocket".to_owned()),
+            chat: Some(Regex::new("game").unwrap()),
+            ..Default::default()
+        });
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn weapon_only_counts_kills_of_player() {
+        let index = index();
+        let results = index.search(&Query {
+            player: Some("scout".to_owned()),
+            weapon: Some("Scatter".to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(found(&results), vec!["a.dem", "b.dem"]);
+        assert_eq!(
+            results[0].matches[0].text,
+            "Scout Main killed Pocket with scattergun"
+        );
+
+        let results = index.search(&Query {
+            player: Some("scout".to_owned()),
+            weapon: Some("rocket".to_owned()),
+            ..Default::default()
+        });
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn changed_demos_are_not_contained() {
+        let index = index();
+        let mut demo = Demo::new("/demos/a.dem");
+        demo.size = Some(1);
+        assert!(index.contains(&demo));
+
+        demo.modified = Some(SystemTime::UNIX_EPOCH);
+        assert!(!index.contains(&demo));
+        demo.modified = None;
+        demo.size = Some(2);
+        assert!(!index.contains(&demo));
+        assert!(!index.contains(&Demo::new("/demos/c.dem")));
+    }
+}
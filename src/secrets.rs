@@ -0,0 +1,30 @@
+//! Passwords kept in the platform keyring, the Secret Service on Linux, the Keychain on macOS and
+//! the Credential Manager on Windows
+
+use anyhow::Result;
+use keyring::{Entry, Error};
+
+const SERVICE: &str = "tf2-demo-player";
+
+/// The stored secret, `None` if there is none under this key
+pub fn get(key: &str) -> Result<Option<String>> {
+    match Entry::new(SERVICE, key)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Stores the secret under this key, an empty secret removes it
+pub fn set(key: &str, secret: &str) -> Result<()> {
+    let entry = Entry::new(SERVICE, key)?;
+    let res = if secret.is_empty() {
+        entry.delete_credential()
+    } else {
+        entry.set_password(secret)
+    };
+    match res {
+        Ok(()) | Err(Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,20 +1,46 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::util;
+use crate::{
+    demo_manager::LibraryOptions, highlights::HighlightOptions, macros::CommandMacro, paths,
+    postprocess::FfmpegSettings, rcon_manager::RconProfile, recorder::RecordOptions, secrets, util,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(default)]
 pub struct Settings {
     pub demo_folder_path: Option<PathBuf>,
     pub tf_folder_path: Option<PathBuf>,
-    pub rcon_pw: String,
-    pub rcon_port: u16,
+    pub rcon_profiles: Vec<RconProfile>,
+    /// Index of the profile in use
+    pub rcon_profile: usize,
+    /// Only read to move settings from before profiles into the first one
+    #[serde(skip_serializing)]
+    rcon_pw: Option<String>,
+    #[serde(skip_serializing)]
+    rcon_port: Option<u16>,
     pub event_skip_predelay: f32,
     pub doubleclick_play: bool,
     pub pause_after_seek: bool,
+    /// Moves the playhead along with the demo playing in TF2
+    pub follow_game: bool,
     pub favorited_folders: Vec<PathBuf>,
+    pub library: LibraryOptions,
+    /// The library is shown instead of `demo_folder_path`
+    pub library_open: bool,
+    /// Ticks between player position samples when inspecting a demo, 0 disables them
+    pub position_interval: u32,
+    /// Spectate the victim instead of the killer when jumping to a kill from the inspector
+    pub spectate_victim: bool,
+    pub highlights: HighlightOptions,
+    pub recording: RecordOptions,
+    pub ffmpeg: FfmpegSettings,
+    pub shortcuts: Shortcuts,
+    pub macros: Vec<CommandMacro>,
 
     #[serde(skip)]
     pub first_launch: bool,
@@ -27,24 +53,188 @@ impl Default for Settings {
         Self {
             demo_folder_path: demos_folder.clone(),
             tf_folder_path: tf_folder.clone(),
-            rcon_pw: Default::default(),
-            rcon_port: 27015,
+            rcon_profiles: vec![RconProfile::default()],
+            rcon_profile: 0,
+            rcon_pw: None,
+            rcon_port: None,
             event_skip_predelay: 30.0,
             doubleclick_play: false,
             pause_after_seek: true,
-            favorited_folders: demos_folder.map_or_else(|| Vec::new(), |f| vec![f]),
+            follow_game: false,
+            favorited_folders: demos_folder.clone().into_iter().collect(),
+            library: LibraryOptions {
+                folders: demos_folder.into_iter().collect(),
+                ..Default::default()
+            },
+            library_open: false,
+            position_interval: 0,
+            spectate_victim: false,
+            highlights: HighlightOptions::default(),
+            recording: RecordOptions::default(),
+            ffmpeg: FfmpegSettings::default(),
+            shortcuts: Shortcuts::default(),
+            macros: vec![CommandMacro {
+                name: "Spectate recorder".to_owned(),
+                commands: "spec_player \"{player}\"".to_owned(),
+                ..Default::default()
+            }],
 
             first_launch: false,
         }
     }
 }
 
+/// Playback controls that can be bound to a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackAction {
+    Play,
+    TogglePause,
+    Stop,
+    SpeedUp,
+    SpeedDown,
+    StepForward,
+    StepBackward,
+    ToggleLoop,
+}
+
+impl PlaybackAction {
+    pub const ALL: [PlaybackAction; 8] = [
+        PlaybackAction::Play,
+        PlaybackAction::TogglePause,
+        PlaybackAction::Stop,
+        PlaybackAction::SpeedUp,
+        PlaybackAction::SpeedDown,
+        PlaybackAction::StepForward,
+        PlaybackAction::StepBackward,
+        PlaybackAction::ToggleLoop,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlaybackAction::Play => "Play demo",
+            PlaybackAction::TogglePause => "Pause/resume",
+            PlaybackAction::Stop => "Stop playback",
+            PlaybackAction::SpeedUp => "Increase speed",
+            PlaybackAction::SpeedDown => "Decrease speed",
+            PlaybackAction::StepForward => "Step one tick forward",
+            PlaybackAction::StepBackward => "Step one tick back",
+            PlaybackAction::ToggleLoop => "Toggle A-B loop",
+        }
+    }
+}
+
+/// Key bindings for the playback controls, in the format of `gtk::accelerator_parse`
+/// (e.g. `<Control>space`), empty ones are disabled
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Shortcuts {
+    pub play: String,
+    pub toggle_pause: String,
+    pub stop: String,
+    pub speed_up: String,
+    pub speed_down: String,
+    pub step_forward: String,
+    pub step_backward: String,
+    pub toggle_loop: String,
+}
+
+impl Default for Shortcuts {
+    fn default() -> Self {
+        Self {
+            play: "<Control>Return".to_owned(),
+            toggle_pause: "<Control>space".to_owned(),
+            stop: "<Control>BackSpace".to_owned(),
+            speed_up: "<Control>bracketright".to_owned(),
+            speed_down: "<Control>bracketleft".to_owned(),
+            step_forward: "<Control>period".to_owned(),
+            step_backward: "<Control>comma".to_owned(),
+            toggle_loop: "<Control>l".to_owned(),
+        }
+    }
+}
+
+impl Shortcuts {
+    pub fn get(&self, action: PlaybackAction) -> &str {
+        match action {
+            PlaybackAction::Play => &self.play,
+            PlaybackAction::TogglePause => &self.toggle_pause,
+            PlaybackAction::Stop => &self.stop,
+            PlaybackAction::SpeedUp => &self.speed_up,
+            PlaybackAction::SpeedDown => &self.speed_down,
+            PlaybackAction::StepForward => &self.step_forward,
+            PlaybackAction::StepBackward => &self.step_backward,
+            PlaybackAction::ToggleLoop => &self.toggle_loop,
+        }
+    }
+
+    pub fn set(&mut self, action: PlaybackAction, accel: String) {
+        *match action {
+            PlaybackAction::Play => &mut self.play,
+            PlaybackAction::TogglePause => &mut self.toggle_pause,
+            PlaybackAction::Stop => &mut self.stop,
+            PlaybackAction::SpeedUp => &mut self.speed_up,
+            PlaybackAction::SpeedDown => &mut self.speed_down,
+            PlaybackAction::StepForward => &mut self.step_forward,
+            PlaybackAction::StepBackward => &mut self.step_backward,
+            PlaybackAction::ToggleLoop => &mut self.toggle_loop,
+        } = accel;
+    }
+}
+
 const SETTINGS_PATH: &str = "settings.json";
 
 impl Settings {
+    /// Whether the file looks like settings written by this app, which always contain the demo
+    /// folder, even if it isn't set
+    pub fn is_settings_file(path: &Path) -> bool {
+        fs::read(path)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<serde_json::Value>(&content).ok())
+            .is_some_and(|value| {
+                value.get("demo_folder_path").is_some()
+                    && serde_json::from_value::<Settings>(value).is_ok()
+            })
+    }
+
     pub fn load() -> Self {
-        match fs::read(SETTINGS_PATH) {
-            Ok(content) => serde_json::from_slice::<Settings>(&content).unwrap_or_default(),
+        match fs::read(paths::config_file(SETTINGS_PATH)) {
+            Ok(content) => {
+                let mut settings = serde_json::from_slice::<Settings>(&content).unwrap_or_default();
+                if settings.rcon_profiles.is_empty() {
+                    settings.rcon_profiles.push(RconProfile::default());
+                }
+                let local = &mut settings.rcon_profiles[0];
+                if let Some(password) = settings.rcon_pw.take() {
+                    local.password = password;
+                }
+                if let Some(port) = settings.rcon_port.take() {
+                    local.port = port;
+                }
+                for profile in &mut settings.rcon_profiles {
+                    if !profile.password_in_keyring {
+                        continue;
+                    }
+                    match secrets::get(&profile.keyring_key()) {
+                        Ok(Some(password)) => {
+                            profile.password = password;
+                            profile.password_loaded = true;
+                        }
+                        Ok(None) => profile.password_in_keyring = false,
+                        // Stays in the keyring, `store_passwords` leaves it alone unless it is changed
+                        Err(e) => log::warn!("Couldn't read rcon password from keyring, {}", e),
+                    }
+                }
+                // Moves passwords saved in plain text into the keyring
+                if settings
+                    .rcon_profiles
+                    .iter()
+                    .any(|p| !p.password_in_keyring && !p.password.is_empty())
+                {
+                    settings.store_passwords();
+                    settings.save();
+                }
+                settings
+            }
             Err(e) => {
                 log::warn!("Couldn't load settings file, {}; Creating default", e);
                 let mut s = Settings::default();
@@ -55,13 +245,23 @@ impl Settings {
     }
 
     pub fn save(&self) {
-        if let Err(e) = fs::write(SETTINGS_PATH, serde_json::to_string(self).unwrap()) {
+        let mut settings = self.clone();
+        for profile in &mut settings.rcon_profiles {
+            if profile.password_in_keyring {
+                profile.password.clear();
+            }
+        }
+        if let Err(e) = fs::write(
+            paths::config_file(SETTINGS_PATH),
+            serde_json::to_string(&settings).unwrap(),
+        ) {
             log::warn!("Couldn't save settings file, {}", e);
         }
     }
 
     pub fn folder_opened(&mut self, path: &PathBuf) {
         self.demo_folder_path = Some(path.into());
+        self.library_open = false;
     }
 
     pub fn toggle_favorite(&mut self) {
@@ -83,9 +283,88 @@ impl Settings {
         }
     }
 
+    /// Puts the rcon passwords into the keyring, they are kept in the settings file if that fails.
+    /// Passwords are only deleted from the keyring if they were loaded and then cleared
+    pub fn store_passwords(&mut self) {
+        for profile in &mut self.rcon_profiles {
+            if !password_changed(profile) {
+                continue;
+            }
+            let key = profile.keyring_key();
+            if profile.password_in_keyring
+                && secrets::get(&key).is_ok_and(|p| p.as_ref() == Some(&profile.password))
+            {
+                continue;
+            }
+            match secrets::set(&key, &profile.password) {
+                Ok(()) => {
+                    profile.password_in_keyring = !profile.password.is_empty();
+                    profile.password_loaded = true;
+                }
+                Err(e) => {
+                    log::warn!("Couldn't store rcon password in keyring, {}", e);
+                    // Keeps the entry if deleting it failed
+                    if !profile.password.is_empty() {
+                        profile.password_in_keyring = false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The rcon profile in use
+    pub fn rcon(&self) -> RconProfile {
+        self.rcon_profiles
+            .get(self.rcon_profile)
+            .cloned()
+            .unwrap_or_default()
+    }
+
     pub fn replays_folder(&self) -> Option<PathBuf> {
         self.tf_folder_path
             .as_ref()
             .map(|p| p.join("tf/replay/client/replays"))
     }
 }
+
+/// Whether the password has to be written to the keyring, or removed from it if it is empty. An
+/// empty password that couldn't be read from the keyring is just unknown, not cleared
+fn password_changed(profile: &RconProfile) -> bool {
+    if profile.password.is_empty() {
+        profile.password_in_keyring && profile.password_loaded
+    } else {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(password: &str, in_keyring: bool, loaded: bool) -> RconProfile {
+        RconProfile {
+            password: password.to_owned(),
+            password_in_keyring: in_keyring,
+            password_loaded: loaded,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unreadable_keyring_passwords_are_kept() {
+        assert!(!password_changed(&profile("", true, false)));
+    }
+
+    #[test]
+    fn cleared_passwords_are_deleted() {
+        assert!(password_changed(&profile("", true, true)));
+        assert!(!password_changed(&profile("", false, false)));
+    }
+
+    #[test]
+    fn entered_passwords_are_stored() {
+        assert!(password_changed(&profile("secret", false, false)));
+        assert!(password_changed(&profile("secret", true, false)));
+        assert!(password_changed(&profile("secret", true, true)));
+    }
+}
@@ -0,0 +1,399 @@
+//! Per player statistics aggregated from the events of a [`MatchState`]
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tf_demo_parser::demo::{
+    data::DemoTick,
+    parser::analyser::{Class, Team},
+};
+
+use crate::analyser::{
+    ConnectionEventType, CritType, FlagEventType, MatchEventType, MatchState, StableUserId,
+    UserInfo,
+};
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Stats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    pub dominations: u32,
+    pub revenges: u32,
+    /// Kills with crits or mini-crits
+    pub crits: u32,
+    /// Deaths faked with the dead ringer, these don't count as kills or deaths
+    pub feigns: u32,
+    /// Seconds spent on red or blu
+    pub time_played: f32,
+    /// Damage dealt to other players
+    pub damage: u32,
+    pub damage_taken: u32,
+    /// Healing done to other players
+    pub healing: u32,
+    pub ubers: u32,
+    /// Deaths as medic with a full charge
+    pub drops: u32,
+    /// Points and flags captured
+    pub captures: u32,
+    /// Blocked captures and defended flags
+    pub defenses: u32,
+}
+
+impl Stats {
+    pub fn kd(&self) -> f32 {
+        self.kills as f32 / self.deaths.max(1) as f32
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerStats {
+    pub user: StableUserId,
+    pub total: Stats,
+    pub classes: HashMap<Class, Stats>,
+}
+
+impl PlayerStats {
+    fn new(user: StableUserId) -> Self {
+        Self {
+            user,
+            total: Stats::default(),
+            classes: HashMap::new(),
+        }
+    }
+
+    /// Stats for one class, `None` gives the totals
+    pub fn get(&self, class: Option<Class>) -> Option<&Stats> {
+        match class {
+            Some(c) => self.classes.get(&c),
+            None => Some(&self.total),
+        }
+    }
+
+    fn update(&mut self, class: Option<Class>, f: impl Fn(&mut Stats)) {
+        f(&mut self.total);
+        if let Some(class) = class {
+            f(self.classes.entry(class).or_default());
+        }
+    }
+}
+
+/// Class a player was playing at the given tick
+pub fn class_at(user: &UserInfo, tick: DemoTick) -> Option<Class> {
+    user.class_switches
+        .iter()
+        .take_while(|(t, _)| *t <= tick)
+        .last()
+        .map(|(_, c)| *c)
+}
+
+/// Calculates the stats of every user, indexed the same as [`MatchState::users`]
+pub fn scoreboard(state: &MatchState) -> Vec<PlayerStats> {
+    let mut stats: Vec<PlayerStats> = (0..state.users.len())
+        .map(|i| PlayerStats::new(i.into()))
+        .collect();
+
+    for event in &state.events {
+        let class_of = |u: &StableUserId| class_at(&state.users[u], event.tick);
+        let death = match &event.value {
+            MatchEventType::Kill(death) => death,
+            MatchEventType::UberDeployed(uber) => {
+                stats[&uber.medic].update(class_of(&uber.medic), |s| s.ubers += 1);
+                continue;
+            }
+            MatchEventType::MedicDeath(death) if death.charged => {
+                stats[&death.medic].update(class_of(&death.medic), |s| s.drops += 1);
+                continue;
+            }
+            MatchEventType::PointCaptured(cap) => {
+                for capper in &cap.cappers {
+                    stats[capper].update(class_of(capper), |s| s.captures += 1);
+                }
+                continue;
+            }
+            MatchEventType::CaptureBlocked(block) => {
+                if let Some(blocker) = &block.blocker {
+                    stats[blocker].update(class_of(blocker), |s| s.defenses += 1);
+                }
+                continue;
+            }
+            MatchEventType::Flag(flag) => {
+                if let Some(player) = &flag.player {
+                    match flag.kind {
+                        FlagEventType::Captured => {
+                            stats[player].update(class_of(player), |s| s.captures += 1)
+                        }
+                        FlagEventType::Defended => {
+                            stats[player].update(class_of(player), |s| s.defenses += 1)
+                        }
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        if death.deadringer {
+            stats[&death.victim].update(class_of(&death.victim), |s| s.feigns += 1);
+            continue;
+        }
+
+        stats[&death.victim].update(class_of(&death.victim), |s| s.deaths += 1);
+
+        if let Some(killer) = death.killer.filter(|k| *k != death.victim) {
+            stats[&killer].update(class_of(&killer), |s| {
+                s.kills += 1;
+                s.dominations += death.domination as u32;
+                s.revenges += death.revenge as u32;
+                s.crits += matches!(death.crit_type, CritType::Mini | CritType::Full) as u32;
+            });
+        }
+
+        if let Some(assister) = &death.assister {
+            stats[assister].update(class_of(assister), |s| {
+                s.assists += 1;
+                s.dominations += death.assist_dom as u32;
+                s.revenges += death.assist_revg as u32;
+            });
+        }
+    }
+
+    for (player, user) in stats.iter_mut().zip(&state.users) {
+        for combat in &user.combat {
+            player.update(combat.class, |s| {
+                s.damage += combat.damage;
+                s.damage_taken += combat.damage_taken;
+                s.healing += combat.healing;
+            });
+        }
+    }
+
+    let tickrate = if state.server_info.interval_per_tick > 0.0 {
+        1.0 / state.server_info.interval_per_tick
+    } else {
+        crate::demo_manager::Demo::TICKRATE
+    };
+    for (i, player) in stats.iter_mut().enumerate() {
+        for (class, ticks) in ticks_played(state, i.into()) {
+            let secs = crate::util::ticks_to_sec(ticks, tickrate);
+            player.update(class, |s| s.time_played += secs);
+        }
+    }
+
+    stats
+}
+
+enum Change {
+    Team(Team),
+    Class(Class),
+    Connected(bool),
+}
+
+/// Ticks a user spent on red or blu, split by the class they were playing
+fn ticks_played(state: &MatchState, user: StableUserId) -> Vec<(Option<Class>, u32)> {
+    let info = &state.users[&user];
+    let mut changes: Vec<(DemoTick, Change)> = state
+        .events
+        .iter()
+        .filter_map(|e| match &e.value {
+            MatchEventType::TeamSwitch(u, team) if *u == user => {
+                Some((e.tick, Change::Team(*team)))
+            }
+            _ => None,
+        })
+        .chain(
+            info.class_switches
+                .iter()
+                .map(|(t, c)| (*t, Change::Class(*c))),
+        )
+        .chain(info.connection_events.iter().map(|(t, c)| {
+            (
+                *t,
+                Change::Connected(matches!(c, ConnectionEventType::Join)),
+            )
+        }))
+        .collect();
+    changes.sort_by_key(|c| c.0);
+
+    let mut played = Vec::new();
+    let mut team = None;
+    let mut class = None;
+    let mut connected = true;
+    let mut since = DemoTick::default();
+    let mut credit = |tick: DemoTick, team: Option<Team>, class: Option<Class>, connected| {
+        if connected && team.is_some_and(|t: Team| t.is_player()) && tick > since {
+            played.push((class, u32::from(tick) - u32::from(since)));
+        }
+        since = tick;
+    };
+    for (tick, change) in changes {
+        credit(tick, team, class, connected);
+        match change {
+            Change::Team(t) => team = Some(t),
+            Change::Class(c) => class = Some(c),
+            Change::Connected(c) => connected = c,
+        }
+    }
+    credit(state.end_tick, team, class, connected);
+    played
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::{CombatTotals, Death, MatchEvent, UberDeployed};
+
+    fn user(name: &str, switches: &[(u32, Class)]) -> UserInfo {
+        UserInfo {
+            name: Some(name.to_owned()),
+            class_switches: switches.iter().map(|(t, c)| ((*t).into(), *c)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn event(tick: u32, value: MatchEventType) -> MatchEvent {
+        MatchEvent {
+            tick: tick.into(),
+            value,
+        }
+    }
+
+    fn kill(tick: u32, killer: usize, victim: usize) -> MatchEvent {
+        event(
+            tick,
+            MatchEventType::Kill(Death {
+                weapon: "scattergun".to_owned(),
+                victim: victim.into(),
+                assister: None,
+                killer: Some(killer.into()),
+                crit_type: CritType::None,
+                domination: false,
+                revenge: false,
+                assist_dom: false,
+                assist_revg: false,
+                deadringer: false,
+            }),
+        )
+    }
+
+    fn state(users: Vec<UserInfo>, events: Vec<MatchEvent>) -> MatchState {
+        MatchState {
+            users,
+            events,
+            end_tick: 1000.into(),
+            server_info: crate::analyser::ServerInfo {
+                interval_per_tick: 0.01,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn kills_are_split_by_class() {
+        let mut crit = kill(300, 0, 1);
+        if let MatchEventType::Kill(death) = &mut crit.value {
+            death.crit_type = CritType::Full;
+            death.domination = true;
+            death.assister = Some(2.into());
+        }
+        let state = state(
+            vec![
+                user("a", &[(0, Class::Scout), (200, Class::Soldier)]),
+                user("b", &[(0, Class::Heavy)]),
+                user("c", &[(0, Class::Medic)]),
+            ],
+            vec![kill(100, 0, 1), crit, kill(400, 1, 0)],
+        );
+        let stats = scoreboard(&state);
+
+        let a = &stats[0];
+        assert_eq!(a.total.kills, 2);
+        assert_eq!(a.total.deaths, 1);
+        assert_eq!(a.total.crits, 1);
+        assert_eq!(a.total.dominations, 1);
+        assert_eq!(a.get(Some(Class::Scout)).unwrap().kills, 1);
+        assert_eq!(a.get(Some(Class::Soldier)).unwrap().kills, 1);
+        assert_eq!(a.get(Some(Class::Soldier)).unwrap().deaths, 1);
+        assert!(a.get(Some(Class::Medic)).is_none());
+
+        assert_eq!(stats[1].total.kills, 1);
+        assert_eq!(stats[1].total.deaths, 2);
+        assert_eq!(stats[2].total.assists, 1);
+        assert_eq!(stats[0].total.kd(), 2.0);
+    }
+
+    #[test]
+    fn feigns_and_suicides_are_no_kills() {
+        let mut feign = kill(100, 0, 1);
+        if let MatchEventType::Kill(death) = &mut feign.value {
+            death.deadringer = true;
+        }
+        let state = state(
+            vec![user("a", &[]), user("b", &[])],
+            vec![feign, kill(200, 1, 1)],
+        );
+        let stats = scoreboard(&state);
+
+        assert_eq!(stats[0].total.kills, 0);
+        assert_eq!(stats[1].total.feigns, 1);
+        assert_eq!(stats[1].total.deaths, 1);
+        assert_eq!(stats[1].total.kills, 0);
+    }
+
+    #[test]
+    fn combat_totals_and_ubers() {
+        let mut medic = user("medic", &[(0, Class::Medic)]);
+        medic.combat = vec![
+            CombatTotals {
+                class: Some(Class::Medic),
+                damage: 50,
+                damage_taken: 120,
+                healing: 2000,
+            },
+            CombatTotals {
+                class: None,
+                damage: 10,
+                ..Default::default()
+            },
+        ];
+        let state = state(
+            vec![medic],
+            vec![event(
+                100,
+                MatchEventType::UberDeployed(UberDeployed {
+                    medic: 0.into(),
+                    target: None,
+                }),
+            )],
+        );
+        let stats = scoreboard(&state);
+
+        assert_eq!(stats[0].total.damage, 60);
+        assert_eq!(stats[0].total.damage_taken, 120);
+        assert_eq!(stats[0].total.healing, 2000);
+        assert_eq!(stats[0].total.ubers, 1);
+        assert_eq!(stats[0].get(Some(Class::Medic)).unwrap().damage, 50);
+    }
+
+    #[test]
+    fn time_played_only_counts_playing_teams() {
+        let mut a = user("a", &[(0, Class::Scout), (500, Class::Pyro)]);
+        a.connection_events = vec![(800.into(), ConnectionEventType::Leave("left".to_owned()))];
+        let state = state(
+            vec![a],
+            vec![
+                event(0, MatchEventType::TeamSwitch(0.into(), Team::Spectator)),
+                event(100, MatchEventType::TeamSwitch(0.into(), Team::Red)),
+            ],
+        );
+        let stats = scoreboard(&state);
+
+        // Red from tick 100 until leaving at 800, at 100 ticks per second
+        assert!((stats[0].total.time_played - 7.0).abs() < 0.001);
+        assert!((stats[0].get(Some(Class::Scout)).unwrap().time_played - 4.0).abs() < 0.001);
+        assert!((stats[0].get(Some(Class::Pyro)).unwrap().time_played - 3.0).abs() < 0.001);
+        assert_eq!(class_at(&state.users[0], 600.into()), Some(Class::Pyro));
+    }
+}
@@ -78,6 +78,8 @@ pub enum EventListFilterChange {
     Votes,
     Team,
     Class,
+    Medic,
+    Objectives,
 }
 
 #[derive(Debug, Clone)]
@@ -89,6 +91,8 @@ pub struct EventListFilter {
     show_votes: bool,
     show_team: bool,
     show_class: bool,
+    show_medic: bool,
+    show_objectives: bool,
 }
 
 impl EventListFilter {
@@ -100,6 +104,8 @@ impl EventListFilter {
         self.show_votes = false;
         self.show_team = false;
         self.show_class = false;
+        self.show_medic = false;
+        self.show_objectives = false;
     }
 
     fn matches(&self, ev: &MatchEventType) -> bool {
@@ -107,18 +113,47 @@ impl EventListFilter {
             || (self.show_class && matches!(ev, MatchEventType::ClassSwitch(_, _)))
             || (self.show_connections && matches!(ev, MatchEventType::Connection(_)))
             || (self.show_deaths && matches!(ev, MatchEventType::Kill(_)))
-            || (self.show_rounds && matches!(ev, MatchEventType::RoundEnd(_)))
+            || (self.show_rounds
+                && matches!(
+                    ev,
+                    MatchEventType::RoundEnd(_)
+                        | MatchEventType::RoundStart { .. }
+                        | MatchEventType::SetupFinished
+                        | MatchEventType::Overtime
+                        | MatchEventType::Stalemate(_)
+                ))
             || (self.show_team && matches!(ev, MatchEventType::TeamSwitch(_, _)))
             || (self.show_votes && matches!(ev, MatchEventType::VoteStarted(_)))
+            || (self.show_medic
+                && matches!(
+                    ev,
+                    MatchEventType::UberDeployed(_) | MatchEventType::MedicDeath(_)
+                ))
+            || (self.show_objectives
+                && matches!(
+                    ev,
+                    MatchEventType::PointCaptured(_)
+                        | MatchEventType::CaptureBlocked(_)
+                        | MatchEventType::Flag(_)
+                ))
     }
 }
 
+#[derive(Debug)]
+pub enum EventViewOut {
+    GotoTick(u32),
+    /// Tick of a kill and the names of the killer and the victim
+    GotoKill(u32, Option<String>, String),
+}
+
 #[derive(Debug)]
 pub enum EventViewMsg {
     Filter(EventListFilterChange),
     Show(Option<Arc<MatchState>>, f32),
     Selected(DynamicIndex),
     SaveEvents,
+    /// Selects the last shown event before the tick
+    GameTick(u32),
 }
 pub struct EventViewModel {
     inspection: Option<Arc<MatchState>>,
@@ -134,7 +169,7 @@ pub struct EventViewModel {
 impl AsyncComponent for EventViewModel {
     type Init = Option<(Arc<MatchState>, StableUserId)>;
     type Input = EventViewMsg;
-    type Output = u32;
+    type Output = EventViewOut;
     type CommandOutput = ();
 
     view! {
@@ -176,6 +211,13 @@ impl AsyncComponent for EventViewModel {
                         connect_clicked => EventViewMsg::Filter(EventListFilterChange::Rounds),
                         set_icon_name: relm4_icons::icon_names::FLAG_FILLED,
                     },
+                    gtk::ToggleButton {
+                        #[watch]
+                        set_active: model.filter.show_objectives,
+                        set_tooltip_text: Some("Objectives"),
+                        connect_clicked => EventViewMsg::Filter(EventListFilterChange::Objectives),
+                        set_icon_name: relm4_icons::icon_names::MAP_MARKER,
+                    },
                     gtk::ToggleButton {
                         #[watch]
                         set_active: model.filter.show_connections,
@@ -204,6 +246,13 @@ impl AsyncComponent for EventViewModel {
                         connect_clicked => EventViewMsg::Filter(EventListFilterChange::Class),
                         set_icon_name: relm4_icons::icon_names::DISCOVER,
                     },
+                    gtk::ToggleButton {
+                        #[watch]
+                        set_active: model.filter.show_medic,
+                        set_tooltip_text: Some("Ubers and medic deaths"),
+                        connect_clicked => EventViewMsg::Filter(EventListFilterChange::Medic),
+                        set_icon_name: relm4_icons::icon_names::HEALTH_EMERGENCY,
+                    },
                 }
             },
             gtk::ScrolledWindow{
@@ -238,6 +287,8 @@ impl AsyncComponent for EventViewModel {
                 show_votes: false,
                 show_team: false,
                 show_class: false,
+                show_medic: false,
+                show_objectives: false,
             },
         };
 
@@ -272,6 +323,12 @@ impl AsyncComponent for EventViewModel {
                     EventListFilterChange::Class => {
                         self.filter.show_class = !self.filter.show_class
                     }
+                    EventListFilterChange::Medic => {
+                        self.filter.show_medic = !self.filter.show_medic
+                    }
+                    EventListFilterChange::Objectives => {
+                        self.filter.show_objectives = !self.filter.show_objectives
+                    }
                 };
                 self.list_model
                     .broadcast(EventRowMsg::Filter(self.filter.clone()));
@@ -294,6 +351,25 @@ impl AsyncComponent for EventViewModel {
                 ind.current_index(),
                 self.tps,
             )))),
+            EventViewMsg::GameTick(tick) => {
+                let Some(state) = &self.inspection else {
+                    return;
+                };
+                let current = state
+                    .events
+                    .iter()
+                    .rposition(|ev| u32::from(ev.tick) <= tick && self.filter.matches(&ev.value));
+                let list = self.list_model.widget();
+                match current.and_then(|i| list.row_at_index(i as i32)) {
+                    Some(row) => {
+                        if list.selected_row().as_ref() != Some(&row) {
+                            list.select_row(Some(&row));
+                            scroll_to(&row);
+                        }
+                    }
+                    None => list.unselect_all(),
+                }
+            }
             EventViewMsg::SaveEvents => {
                 let picker = gtk::FileDialog::builder()
                     .initial_name("events.txt")
@@ -319,6 +395,26 @@ impl AsyncComponent for EventViewModel {
     }
 }
 
+/// Scrolls the surrounding scrolled window so the row is visible
+fn scroll_to(row: &gtk::ListBoxRow) {
+    let Some(scroll) = row
+        .ancestor(gtk::ScrolledWindow::static_type())
+        .and_downcast::<gtk::ScrolledWindow>()
+    else {
+        return;
+    };
+    let Some(bounds) = row.compute_bounds(&scroll) else {
+        return;
+    };
+    let adj = scroll.vadjustment();
+    let (top, bottom) = (bounds.y() as f64, (bounds.y() + bounds.height()) as f64);
+    if top < 0.0 {
+        adj.set_value(adj.value() + top);
+    } else if bottom > adj.page_size() {
+        adj.set_value(adj.value() + bottom - adj.page_size());
+    }
+}
+
 #[derive(Debug)]
 struct EventDialogModel {
     inspection: Option<Arc<MatchState>>,
@@ -339,7 +435,7 @@ enum EventDialogMsg {
 impl Component for EventDialogModel {
     type Init = gtk::Box;
     type Input = EventDialogMsg;
-    type Output = u32;
+    type Output = EventViewOut;
     type CommandOutput = ();
 
     view! {
@@ -368,7 +464,7 @@ impl Component for EventDialogModel {
                             #[wrap(Some)]
                             set_end_widget = &gtk::Button{
                                 set_icon_name: "find-location-symbolic",
-                                set_tooltip_text: Some("Set playbar to event"),
+                                set_tooltip_text: Some("Set playbar to event, kills are also played in-game from the killer's view"),
                                 connect_clicked => EventDialogMsg::Goto,
                             }
                         },
@@ -574,6 +670,21 @@ impl Component for EventDialogModel {
                                 #[watch]
                                 attach: (&vote_table(vote), 0, 5, 2, 1),
                             }
+                            MatchEventType::UberDeployed(_)
+                            | MatchEventType::MedicDeath(_)
+                            | MatchEventType::RoundStart { .. }
+                            | MatchEventType::SetupFinished
+                            | MatchEventType::Overtime
+                            | MatchEventType::Stalemate(_)
+                            | MatchEventType::PointCaptured(_)
+                            | MatchEventType::CaptureBlocked(_)
+                            | MatchEventType::Flag(_) => gtk::Label{
+                                set_selectable: true,
+                                #[watch]
+                                set_label: &model.inspection.as_ref()
+                                    .map(|i|ev.value.to_string(&i.users))
+                                    .unwrap_or_default(),
+                            }
                         },
                     },
                     None => gtk::Label{
@@ -638,13 +749,21 @@ impl Component for EventDialogModel {
                 }
             }
             EventDialogMsg::Goto => {
-                if let Some(ev) = self
+                if let Some((state, ev)) = self
                     .inspection
                     .as_ref()
                     .zip(self.selected_event)
-                    .and_then(|(i, s)| i.events.get(s))
+                    .and_then(|(i, s)| Some((i, i.events.get(s)?)))
                 {
-                    let _ = sender.output(ev.tick.into());
+                    let name = |id: &StableUserId| state.users[id].name.clone();
+                    let _ = sender.output(match &ev.value {
+                        MatchEventType::Kill(kill) => EventViewOut::GotoKill(
+                            ev.tick.into(),
+                            kill.killer.as_ref().and_then(name),
+                            name(&kill.victim).unwrap_or_default(),
+                        ),
+                        _ => EventViewOut::GotoTick(ev.tick.into()),
+                    });
                 }
             }
         }
@@ -835,6 +954,63 @@ impl FactoryComponent for EventRowModel {
                         .into();
                 subtitle = class.to_string();
             }
+            MatchEventType::UberDeployed(uber) => {
+                icon = relm4_icons::icon_names::HEALTH_EMERGENCY;
+                title = markup_escape_text(&format!(
+                    "{} deployed uber",
+                    state.users[&uber.medic]
+                        .name
+                        .as_ref()
+                        .map_or("unknown", |v| v)
+                ))
+                .into();
+                subtitle = uber
+                    .target
+                    .and_then(|t| state.users[&t].name.as_ref())
+                    .map_or_else(String::new, |n| markup_escape_text(n).into());
+            }
+            MatchEventType::MedicDeath(death) => {
+                icon = relm4_icons::icon_names::HEALTH_EMERGENCY;
+                title = markup_escape_text(&format!(
+                    "{} died{}",
+                    state.users[&death.medic]
+                        .name
+                        .as_ref()
+                        .map_or("unknown", |v| v),
+                    if death.charged { " with full uber" } else { "" }
+                ))
+                .into();
+                subtitle = format!("Healed {}", death.healing);
+            }
+            MatchEventType::RoundStart { .. }
+            | MatchEventType::SetupFinished
+            | MatchEventType::Overtime
+            | MatchEventType::Stalemate(_) => {
+                icon = relm4_icons::icon_names::FLAG_FILLED;
+                title = ev.value.to_string(&state.users);
+                subtitle = "".into();
+            }
+            MatchEventType::PointCaptured(cap) => {
+                icon = relm4_icons::icon_names::MAP_MARKER;
+                title = format!(
+                    "<span foreground=\"{}\">{}</span> captured {}",
+                    get_team_color_string(Some(&cap.team)),
+                    cap.team,
+                    markup_escape_text(&cap.name)
+                );
+                subtitle = markup_escape_text(
+                    &cap.cappers
+                        .iter()
+                        .map(|c| state.users[c].name.as_deref().unwrap_or("unknown"))
+                        .join(", "),
+                )
+                .into();
+            }
+            MatchEventType::CaptureBlocked(_) | MatchEventType::Flag(_) => {
+                icon = relm4_icons::icon_names::MAP_MARKER;
+                title = markup_escape_text(&ev.value.to_string(&state.users)).into();
+                subtitle = "".into();
+            }
         }
         Self {
             icon: icon.to_string(),
@@ -850,19 +1026,7 @@ impl FactoryComponent for EventRowModel {
     fn update(&mut self, message: Self::Input, _sender: FactorySender<Self>) {
         match message {
             EventRowMsg::Filter(filter) => {
-                self.matches_filter = (filter.show_chat
-                    && matches!(self.event.value, MatchEventType::Chat(_)))
-                    || (filter.show_class
-                        && matches!(self.event.value, MatchEventType::ClassSwitch(_, _)))
-                    || (filter.show_connections
-                        && matches!(self.event.value, MatchEventType::Connection(_)))
-                    || (filter.show_deaths && matches!(self.event.value, MatchEventType::Kill(_)))
-                    || (filter.show_rounds
-                        && matches!(self.event.value, MatchEventType::RoundEnd(_)))
-                    || (filter.show_team
-                        && matches!(self.event.value, MatchEventType::TeamSwitch(_, _)))
-                    || (filter.show_votes
-                        && matches!(self.event.value, MatchEventType::VoteStarted(_)));
+                self.matches_filter = filter.matches(&self.event.value);
             }
         }
     }
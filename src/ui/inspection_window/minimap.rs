@@ -0,0 +1,327 @@
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+use adw::prelude::*;
+use gtk::cairo;
+use gtk::gdk::prelude::GdkCairoContextExt;
+use relm4::prelude::*;
+use tf_demo_parser::demo::parser::analyser::Team;
+
+use crate::analyser::{MatchState, PositionSample};
+
+/// Folder with optional map overview images: `<map>.png` together with a `<map>.txt`
+/// in the source engine overview format (`pos_x`, `pos_y` and `scale`)
+const OVERVIEW_FOLDER: &str = "overviews";
+/// Distance between grid lines in hammer units
+const GRID_SIZE: f64 = 512.0;
+
+struct Overview {
+    image: gtk::gdk_pixbuf::Pixbuf,
+    pos_x: f64,
+    pos_y: f64,
+    scale: f64,
+}
+
+impl Overview {
+    fn load(map: &str) -> Option<Self> {
+        let folder = crate::paths::config_file(OVERVIEW_FOLDER);
+        let txt = std::fs::read_to_string(folder.join(format!("{map}.txt"))).ok()?;
+        let tokens: Vec<&str> = txt.split('"').skip(1).step_by(2).collect();
+        let value = |key: &str| -> Option<f64> {
+            let i = tokens.iter().position(|t| t.eq_ignore_ascii_case(key))?;
+            tokens.get(i + 1)?.parse().ok()
+        };
+        let (pos_x, pos_y, scale) = (value("pos_x")?, value("pos_y")?, value("scale")?);
+        match gtk::gdk_pixbuf::Pixbuf::from_file(folder.join(format!("{map}.png"))) {
+            Ok(image) => Some(Self {
+                image,
+                pos_x,
+                pos_y,
+                scale,
+            }),
+            Err(e) => {
+                log::warn!("Failed to load overview image for {map}: {e}");
+                None
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct MinimapData {
+    state: Option<Arc<MatchState>>,
+    overview: Option<Overview>,
+    /// Shown part of the map in world coordinates: left, top, width, height
+    bounds: (f64, f64, f64, f64),
+    tick: u32,
+    tps: f32,
+}
+
+impl MinimapData {
+    fn update_bounds(&mut self) {
+        if let Some(ov) = &self.overview {
+            self.bounds = (
+                ov.pos_x,
+                ov.pos_y,
+                ov.image.width() as f64 * ov.scale,
+                ov.image.height() as f64 * ov.scale,
+            );
+            return;
+        }
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for sample in self
+            .state
+            .iter()
+            .flat_map(|s| &s.users)
+            .flat_map(|u| &u.positions)
+        {
+            min_x = min_x.min(sample.origin[0] as f64);
+            max_x = max_x.max(sample.origin[0] as f64);
+            min_y = min_y.min(sample.origin[1] as f64);
+            max_y = max_y.max(sample.origin[1] as f64);
+        }
+        if min_x > max_x {
+            self.bounds = (-GRID_SIZE, GRID_SIZE, 2.0 * GRID_SIZE, 2.0 * GRID_SIZE);
+            return;
+        }
+        let pad = ((max_x - min_x).max(max_y - min_y) * 0.05).max(GRID_SIZE / 4.0);
+        self.bounds = (
+            min_x - pad,
+            max_y + pad,
+            max_x - min_x + 2.0 * pad,
+            max_y - min_y + 2.0 * pad,
+        );
+    }
+
+    fn draw(&self, cr: &cairo::Context, width: i32, height: i32) {
+        let (left, top, w, h) = self.bounds;
+        let factor = (width as f64 / w).min(height as f64 / h);
+        let (off_x, off_y) = (
+            (width as f64 - w * factor) / 2.0,
+            (height as f64 - h * factor) / 2.0,
+        );
+        let to_screen = |x: f64, y: f64| (off_x + (x - left) * factor, off_y + (top - y) * factor);
+
+        match &self.overview {
+            Some(ov) => {
+                cr.save().ok();
+                cr.translate(off_x, off_y);
+                cr.scale(factor * ov.scale, factor * ov.scale);
+                cr.set_source_pixbuf(&ov.image, 0.0, 0.0);
+                cr.paint().ok();
+                cr.restore().ok();
+            }
+            None => {
+                cr.set_source_rgb(0.15, 0.15, 0.15);
+                cr.rectangle(off_x, off_y, w * factor, h * factor);
+                cr.fill().ok();
+
+                cr.set_source_rgb(0.25, 0.25, 0.25);
+                cr.set_line_width(1.0);
+                let mut x = (left / GRID_SIZE).ceil() * GRID_SIZE;
+                while x < left + w {
+                    let (sx, _) = to_screen(x, 0.0);
+                    cr.move_to(sx, off_y);
+                    cr.line_to(sx, off_y + h * factor);
+                    x += GRID_SIZE;
+                }
+                let mut y = (top / GRID_SIZE).floor() * GRID_SIZE;
+                while y > top - h {
+                    let (_, sy) = to_screen(0.0, y);
+                    cr.move_to(off_x, sy);
+                    cr.line_to(off_x + w * factor, sy);
+                    y -= GRID_SIZE;
+                }
+                cr.stroke().ok();
+            }
+        }
+
+        let Some(state) = &self.state else {
+            return;
+        };
+        let max_gap = (self.tps * 2.0) as u32;
+        cr.set_font_size(11.0);
+        for user in &state.users {
+            let Some((pos, sample)) = position_at(&user.positions, self.tick, max_gap) else {
+                continue;
+            };
+            if !sample.alive {
+                continue;
+            }
+            let (sx, sy) = to_screen(pos[0], pos[1]);
+            let (r, g, b) = team_color(sample.team);
+            let yaw = (sample.yaw as f64).to_radians();
+
+            cr.set_source_rgb(r, g, b);
+            cr.arc(sx, sy, 6.0, 0.0, std::f64::consts::TAU);
+            cr.fill().ok();
+            cr.set_line_width(2.0);
+            cr.move_to(sx, sy);
+            cr.line_to(sx + yaw.cos() * 14.0, sy - yaw.sin() * 14.0);
+            cr.stroke().ok();
+
+            cr.set_source_rgb(1.0, 1.0, 1.0);
+            cr.move_to(sx + 8.0, sy - 8.0);
+            cr.show_text(user.name.as_deref().unwrap_or("unknown")).ok();
+        }
+    }
+}
+
+/// Position of a player at `tick`, interpolated between the surrounding samples
+fn position_at(
+    samples: &[PositionSample],
+    tick: u32,
+    max_gap: u32,
+) -> Option<([f64; 2], &PositionSample)> {
+    let i = samples.partition_point(|s| s.tick <= tick);
+    let prev = samples.get(i.checked_sub(1)?)?;
+    let since = tick - u32::from(prev.tick);
+    if since > max_gap {
+        return None;
+    }
+    let pos = match samples.get(i) {
+        Some(next) if u32::from(next.tick - prev.tick) <= max_gap && next.alive == prev.alive => {
+            let t = since as f64 / u32::from(next.tick - prev.tick) as f64;
+            [
+                prev.origin[0] as f64 + (next.origin[0] - prev.origin[0]) as f64 * t,
+                prev.origin[1] as f64 + (next.origin[1] - prev.origin[1]) as f64 * t,
+            ]
+        }
+        _ => [prev.origin[0] as f64, prev.origin[1] as f64],
+    };
+    Some((pos, prev))
+}
+
+fn team_color(team: Team) -> (f64, f64, f64) {
+    match team {
+        Team::Red => (0.88, 0.29, 0.29),
+        Team::Blue => (0.42, 0.68, 0.97),
+        Team::Spectator | Team::Other => (0.52, 0.52, 0.52),
+    }
+}
+
+pub struct MinimapModel {
+    data: Rc<RefCell<MinimapData>>,
+    area: gtk::DrawingArea,
+
+    tick: u32,
+    end_tick: u32,
+    has_positions: bool,
+}
+
+#[derive(Debug)]
+pub enum MinimapMsg {
+    /// Match state, map name and tickrate
+    Show(Option<Arc<MatchState>>, Option<String>, f32),
+    Tick(f64),
+    Goto,
+}
+
+#[relm4::component(pub)]
+impl Component for MinimapModel {
+    type Init = ();
+    type Input = MinimapMsg;
+    type Output = u32;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Stack {
+            #[watch]
+            set_visible_child_name: if model.has_positions { "map" } else { "empty" },
+
+            add_named[Some("empty")] = &adw::StatusPage {
+                set_icon_name: Some(relm4_icons::icon_names::MAP),
+                set_title: "No position data",
+                set_description: Some("Set a position sample interval in the preferences and inspect the demo again"),
+            },
+
+            add_named[Some("map")] = &gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+
+                #[local_ref]
+                area -> gtk::DrawingArea {
+                    set_vexpand: true,
+                    set_hexpand: true,
+                },
+
+                gtk::Box {
+                    set_spacing: 10,
+                    set_margin_all: 10,
+
+                    gtk::Scale {
+                        set_hexpand: true,
+                        set_increments: (1.0, 66.0),
+                        #[watch]
+                        set_range: (0.0, model.end_tick as f64),
+                        #[watch]
+                        #[block_signal(tick_handler)]
+                        set_value: model.tick as f64,
+                        connect_value_changed[sender] => move |s| {
+                            sender.input(MinimapMsg::Tick(s.value()));
+                        } @tick_handler,
+                    },
+                    gtk::Label {
+                        #[watch]
+                        set_label: &format!("{} ({})",
+                            crate::util::ticks_to_timestamp(model.tick, model.data.borrow().tps), model.tick
+                        ),
+                    },
+                    gtk::Button {
+                        set_icon_name: "find-location-symbolic",
+                        set_tooltip_text: Some("Set playbar to tick"),
+                        connect_clicked => MinimapMsg::Goto,
+                    },
+                },
+            },
+        }
+    }
+
+    fn init(
+        _: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = MinimapModel {
+            data: Rc::default(),
+            area: gtk::DrawingArea::new(),
+            tick: 0,
+            end_tick: 0,
+            has_positions: false,
+        };
+
+        let data = model.data.clone();
+        model
+            .area
+            .set_draw_func(move |_, cr, w, h| data.borrow().draw(cr, w, h));
+
+        let area = &model.area;
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match message {
+            MinimapMsg::Show(state, map, tps) => {
+                let mut data = self.data.borrow_mut();
+                self.has_positions = state
+                    .as_ref()
+                    .is_some_and(|s| s.users.iter().any(|u| !u.positions.is_empty()));
+                self.end_tick = state.as_ref().map_or(0, |s| s.end_tick.into());
+                self.tick = 0;
+                data.overview = map.as_deref().and_then(Overview::load);
+                data.state = state;
+                data.tps = tps;
+                data.tick = 0;
+                data.update_bounds();
+            }
+            MinimapMsg::Tick(tick) => {
+                self.tick = tick as u32;
+                self.data.borrow_mut().tick = self.tick;
+            }
+            MinimapMsg::Goto => {
+                let _ = sender.output(self.tick);
+            }
+        }
+        self.area.queue_draw();
+    }
+}
@@ -1,35 +1,53 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
 
 use crate::analyser::MatchState;
 use crate::demo_manager::Demo;
+use crate::export::DemoExport;
+use crate::settings::Settings;
 use adw::prelude::*;
 use anyhow::Result;
 use async_std::path::Path;
-use event_list::{EventViewModel, EventViewMsg};
+use event_list::{EventViewModel, EventViewMsg, EventViewOut};
 use itertools::Itertools;
+use minimap::{MinimapModel, MinimapMsg};
 use relm4::{gtk::glib::markup_escape_text, prelude::*};
+use scoreboard::{ScoreboardModel, ScoreboardMsg};
 use tf_demo_parser::demo::parser::analyser::Team;
 
 use super::util;
 
 mod event_list;
+mod minimap;
+mod scoreboard;
+mod scoreboard_object;
 
 pub struct InspectionModel {
     demo: Demo,
+    settings: Rc<RefCell<Settings>>,
 
     player_factories: HashMap<Team, FactoryVecDeque<PlayerRowModel>>,
     event_view: AsyncController<EventViewModel>,
+    scoreboard: Controller<ScoreboardModel>,
+    minimap: Controller<MinimapModel>,
 }
 
 #[derive(Debug)]
 pub enum InspectionMsg {
     Inspect(Demo),
     SearchChanged(String),
+    ExportJson,
+    /// Playback position of the game and the path of the demo it is playing
+    GameTick(std::path::PathBuf, u32),
+    Goto(u32),
+    /// Tick of a kill and the names of the killer and the victim
+    GotoKill(u32, Option<String>, String),
 }
 
 #[derive(Debug)]
 pub enum InspectionOut {
     GotoTick(u32),
+    /// Makes the game spectate a player, after seeking to the tick if there is one
+    Spectate(Option<u32>, String),
     DemoProcessed(Demo),
 }
 
@@ -39,7 +57,7 @@ lazy_static::lazy_static! {
 
 #[relm4::component(pub)]
 impl Component for InspectionModel {
-    type Init = ();
+    type Init = Rc<RefCell<Settings>>;
     type Input = InspectionMsg;
     type Output = InspectionOut;
     type CommandOutput = Result<Arc<MatchState>>;
@@ -62,7 +80,15 @@ impl Component for InspectionModel {
                     pack_start = &gtk::Spinner{
                         #[watch]
                         set_spinning: model.demo.inspection.is_none(),
-                    }
+                    },
+
+                    pack_end = &gtk::Button{
+                        set_icon_name: "document-save-as-symbolic",
+                        set_tooltip_text: Some("Export analysis as json"),
+                        #[watch]
+                        set_sensitive: model.demo.inspection.is_some(),
+                        connect_clicked => InspectionMsg::ExportJson,
+                    },
                 },
 
                 add_top_bar = &adw::Clamp{
@@ -277,18 +303,21 @@ impl Component for InspectionModel {
                         set_icon_name: Some(relm4_icons::icon_names::INFO_OUTLINE),
                     },
                     add_titled_with_icon: (model.event_view.widget(), None, "Events", relm4_icons::icon_names::LIST_LARGE),
+                    add_titled_with_icon: (model.scoreboard.widget(), None, "Scoreboard", relm4_icons::icon_names::PEOPLE),
+                    add_titled_with_icon: (model.minimap.widget(), None, "Minimap", relm4_icons::icon_names::MAP),
                 }
             }
         }
     }
 
     fn init(
-        _init: Self::Init,
+        settings: Self::Init,
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
         let model = InspectionModel {
             demo: Demo::new(Path::new("empty")),
+            settings,
             player_factories: HashMap::from_iter(
                 vec![Team::Red, Team::Blue, Team::Spectator, Team::Other]
                     .iter()
@@ -299,14 +328,27 @@ impl Component for InspectionModel {
                                 sender.output_sender(),
                                 |m| match m {
                                     PlayerRowOut::GotoTick(t) => InspectionOut::GotoTick(t),
+                                    PlayerRowOut::Spectate(name) => {
+                                        InspectionOut::Spectate(None, name)
+                                    }
                                 },
                             ),
                         )
                     }),
             ),
-            event_view: EventViewModel::builder()
-                .launch(None)
-                .forward(sender.output_sender(), |t| InspectionOut::GotoTick(t)),
+            event_view: EventViewModel::builder().launch(None).forward(
+                sender.input_sender(),
+                |out| match out {
+                    EventViewOut::GotoTick(tick) => InspectionMsg::Goto(tick),
+                    EventViewOut::GotoKill(tick, killer, victim) => {
+                        InspectionMsg::GotoKill(tick, killer, victim)
+                    }
+                },
+            ),
+            scoreboard: ScoreboardModel::builder().launch(()).detach(),
+            minimap: MinimapModel::builder()
+                .launch(())
+                .forward(sender.output_sender(), InspectionOut::GotoTick),
         };
 
         let widgets = view_output!();
@@ -328,12 +370,36 @@ impl Component for InspectionModel {
                 }
                 if self.demo.inspection.is_none() {
                     let mut dem = self.demo.clone();
-                    sender.oneshot_command(async move { dem.full_analysis().await });
+                    let interval = self.settings.borrow().position_interval;
+                    sender.oneshot_command(async move {
+                        if interval > 0 {
+                            dem.full_analysis_with_positions(interval).await
+                        } else {
+                            dem.full_analysis().await
+                        }
+                    });
                 } else {
                     self.update_display();
                 }
                 root.present();
             }
+            InspectionMsg::Goto(tick) => {
+                let _ = sender.output(InspectionOut::GotoTick(tick));
+            }
+            InspectionMsg::GotoKill(tick, killer, victim) => {
+                let _ = sender.output(InspectionOut::GotoTick(tick));
+                let player = match killer {
+                    Some(killer) if !self.settings.borrow().spectate_victim => killer,
+                    _ => victim,
+                };
+                let _ = sender.output(InspectionOut::Spectate(Some(tick), player));
+            }
+            InspectionMsg::GameTick(demo, tick) => {
+                if demo == self.demo.path {
+                    self.event_view.emit(EventViewMsg::GameTick(tick));
+                    self.minimap.emit(MinimapMsg::Tick(tick.into()));
+                }
+            }
             InspectionMsg::SearchChanged(txt) => {
                 let txt = txt.to_lowercase();
                 for (_, fac) in &mut self.player_factories {
@@ -341,6 +407,33 @@ impl Component for InspectionModel {
                     fac.broadcast(PlayerRowMsg::SearchChanged(txt));
                 }
             }
+            InspectionMsg::ExportJson => {
+                if let Some(insp) = &self.demo.inspection {
+                    let json = match DemoExport::new(Some(&self.demo), insp).to_json() {
+                        Ok(json) => json,
+                        Err(e) => {
+                            util::notice_dialog(root, "Failed to export analysis", &e.to_string());
+                            return;
+                        }
+                    };
+                    let name = self
+                        .demo
+                        .path
+                        .file_stem()
+                        .map_or("demo".into(), |s| s.to_string_lossy());
+                    let dia = gtk::FileDialog::builder()
+                        .initial_name(format!("{name}_analysis.json"))
+                        .build();
+                    dia.save(Some(root), None::<&gtk::gio::Cancellable>, move |res| {
+                        if let Ok(file) = res {
+                            let path = file.path().unwrap();
+                            if let Err(e) = std::fs::write(&path, &json) {
+                                log::warn!("Failed to write to file {}: {e}", path.display());
+                            }
+                        }
+                    });
+                }
+            }
         }
     }
 
@@ -380,6 +473,13 @@ impl InspectionModel {
             self.demo.inspection.clone(),
             self.demo.tps(),
         ));
+        self.scoreboard
+            .emit(ScoreboardMsg::Show(self.demo.inspection.clone()));
+        self.minimap.emit(MinimapMsg::Show(
+            self.demo.inspection.clone(),
+            self.demo.header.as_ref().map(|h| h.map.clone()),
+            self.demo.tps(),
+        ));
     }
 }
 
@@ -394,6 +494,7 @@ struct PlayerRowModel {
 enum PlayerRowMsg {
     OpenProfile,
     OpenSteamhistory,
+    Spectate,
 
     SearchChanged(String),
 }
@@ -401,6 +502,7 @@ enum PlayerRowMsg {
 #[derive(Debug)]
 enum PlayerRowOut {
     GotoTick(u32),
+    Spectate(String),
 }
 
 #[relm4::factory]
@@ -436,6 +538,13 @@ impl FactoryComponent for PlayerRowModel {
                             connect_clicked => PlayerRowMsg::OpenSteamhistory,
                         },
                     },
+                    gtk::Button {
+                        set_label: "Follow this player",
+                        set_has_frame: false,
+                        set_tooltip_text: Some("Spectate this player in-game"),
+                        set_sensitive: self.player.name.is_some(),
+                        connect_clicked => PlayerRowMsg::Spectate,
+                    },
                     //gtk::Button { // TODO
                     //    set_label: "Show events",
                     //    set_has_frame: false,
@@ -511,8 +620,13 @@ impl FactoryComponent for PlayerRowModel {
         }
     }
 
-    fn update(&mut self, message: Self::Input, _sender: FactorySender<Self>) {
+    fn update(&mut self, message: Self::Input, sender: FactorySender<Self>) {
         match message {
+            PlayerRowMsg::Spectate => {
+                if let Some(name) = &self.player.name {
+                    let _ = sender.output(PlayerRowOut::Spectate(name.clone()));
+                }
+            }
             PlayerRowMsg::OpenProfile => {
                 if let Err(e) = opener::open_browser(format!(
                     "https://steamcommunity.com/profiles/{}",
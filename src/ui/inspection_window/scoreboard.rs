@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use adw::prelude::*;
+use gtk::gio;
+use relm4::prelude::*;
+use tf_demo_parser::demo::parser::analyser::Class;
+
+use super::scoreboard_object::ScoreboardObject;
+use crate::{
+    analyser::MatchState,
+    stats::{self, PlayerStats},
+};
+
+const CLASSES: [(&str, Option<Class>); 10] = [
+    ("All classes", None),
+    ("Scout", Some(Class::Scout)),
+    ("Soldier", Some(Class::Soldier)),
+    ("Pyro", Some(Class::Pyro)),
+    ("Demoman", Some(Class::Demoman)),
+    ("Heavy", Some(Class::Heavy)),
+    ("Engineer", Some(Class::Engineer)),
+    ("Medic", Some(Class::Medic)),
+    ("Sniper", Some(Class::Sniper)),
+    ("Spy", Some(Class::Spy)),
+];
+
+pub struct ScoreboardModel {
+    list_model: gio::ListStore,
+    list_selection: gtk::NoSelection,
+
+    state: Option<Arc<MatchState>>,
+    stats: Vec<PlayerStats>,
+    class: Option<Class>,
+}
+
+#[derive(Debug)]
+pub enum ScoreboardMsg {
+    Show(Option<Arc<MatchState>>),
+    ClassSelected(u32),
+}
+
+#[relm4::component(pub)]
+impl Component for ScoreboardModel {
+    type Init = ();
+    type Input = ScoreboardMsg;
+    type Output = ();
+    type CommandOutput = ();
+
+    view! {
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+
+            gtk::DropDown::from_strings(&CLASSES.map(|c| c.0)) {
+                set_halign: gtk::Align::End,
+                set_margin_all: 5,
+                connect_selected_notify[sender] => move |dd| {
+                    sender.input(ScoreboardMsg::ClassSelected(dd.selected()));
+                },
+            },
+
+            gtk::ScrolledWindow {
+                set_vexpand: true,
+                set_hscrollbar_policy: gtk::PolicyType::Automatic,
+
+                #[name="scoreboard"]
+                gtk::ColumnView {
+                    set_model: Some(&model.list_selection),
+                    set_show_column_separators: true,
+                }
+            }
+        }
+    }
+
+    fn init(
+        _: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let liststore = gio::ListStore::new::<ScoreboardObject>();
+        let sorted_model = gtk::SortListModel::builder().model(&liststore).build();
+
+        let model = ScoreboardModel {
+            list_model: liststore,
+            list_selection: gtk::NoSelection::new(Some(sorted_model.clone())),
+            state: None,
+            stats: vec![],
+            class: None,
+        };
+
+        let widgets = view_output!();
+
+        sorted_model.set_sorter(widgets.scoreboard.sorter().as_ref());
+
+        let view = &widgets.scoreboard;
+        add_column(view, "Name", "name", false, None);
+        add_column(view, "Team", "team", false, None);
+        let kills_column = add_column(view, "Kills", "kills", true, None);
+        add_column(view, "Deaths", "deaths", true, None);
+        add_column(view, "Assists", "assists", true, None);
+        add_column(
+            view,
+            "K/D",
+            "kd",
+            true,
+            Some(|v| format!("{:.2}", v.get::<f32>().unwrap())),
+        );
+        add_column(view, "Dominations", "dominations", true, None);
+        add_column(view, "Revenges", "revenges", true, None);
+        add_column(view, "Crits", "crits", true, None);
+        add_column(view, "Feigns", "feigns", true, None);
+        add_column(view, "Damage", "damage", true, None);
+        add_column(view, "Damage taken", "damage-taken", true, None);
+        add_column(view, "Healing", "healing", true, None);
+        add_column(view, "Ubers", "ubers", true, None);
+        add_column(view, "Drops", "drops", true, None);
+        add_column(view, "Captures", "captures", true, None);
+        add_column(view, "Defenses", "defenses", true, None);
+        add_column(
+            view,
+            "Time played",
+            "time-played",
+            true,
+            Some(|v| crate::util::sec_to_timestamp(v.get::<f32>().unwrap())),
+        );
+
+        view.sort_by_column(Some(&kills_column), gtk::SortType::Descending);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, _sender: ComponentSender<Self>, _root: &Self::Root) {
+        match message {
+            ScoreboardMsg::Show(state) => {
+                self.stats = state.as_deref().map(stats::scoreboard).unwrap_or_default();
+                self.state = state;
+            }
+            ScoreboardMsg::ClassSelected(ind) => {
+                self.class = CLASSES.get(ind as usize).and_then(|c| c.1);
+            }
+        }
+        self.fill();
+    }
+}
+
+impl ScoreboardModel {
+    fn fill(&self) {
+        self.list_model.remove_all();
+        let Some(state) = &self.state else {
+            return;
+        };
+        for player in &self.stats {
+            let Some(stats) = player.get(self.class) else {
+                continue;
+            };
+            if stats.time_played <= 0.0 && stats.kills + stats.deaths + stats.assists == 0 {
+                continue;
+            }
+            self.list_model
+                .append(&ScoreboardObject::new(&state.users[&player.user], stats));
+        }
+    }
+}
+
+fn add_column(
+    view: &gtk::ColumnView,
+    title: &str,
+    property: &'static str,
+    numeric: bool,
+    format: Option<fn(&gtk::glib::Value) -> String>,
+) -> gtk::ColumnViewColumn {
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(move |_, li| {
+        let listitem = li.downcast_ref::<gtk::ListItem>().unwrap();
+        let label = gtk::Label::builder()
+            .halign(if numeric {
+                gtk::Align::End
+            } else {
+                gtk::Align::Start
+            })
+            .build();
+        listitem.set_child(Some(&label));
+        let expr = listitem
+            .property_expression("item")
+            .chain_property::<ScoreboardObject>(property);
+        match format {
+            Some(format) => expr
+                .chain_closure_with_callback(move |v| format(&v[1]))
+                .bind(&label, "label", gtk::Widget::NONE),
+            None => expr.bind(&label, "label", gtk::Widget::NONE),
+        };
+    });
+
+    let expr = gtk::PropertyExpression::new(
+        ScoreboardObject::static_type(),
+        None::<gtk::Expression>,
+        property,
+    );
+    let column = gtk::ColumnViewColumn::builder()
+        .title(title)
+        .resizable(true)
+        .factory(&factory)
+        .expand(!numeric);
+    let column = if numeric {
+        column.sorter(&gtk::NumericSorter::new(Some(&expr)))
+    } else {
+        column.sorter(&gtk::StringSorter::new(Some(&expr)))
+    }
+    .build();
+    view.append_column(&column);
+    column
+}
@@ -0,0 +1,99 @@
+use gtk::glib;
+use gtk::glib::Object;
+use relm4::gtk;
+
+use crate::analyser::UserInfo;
+use crate::stats::Stats;
+
+glib::wrapper! {
+    pub struct ScoreboardObject(ObjectSubclass<imp::ScoreboardObject>);
+}
+
+impl ScoreboardObject {
+    pub fn new(user: &UserInfo, stats: &Stats) -> Self {
+        Object::builder()
+            .property("name", user.name.clone().unwrap_or_default())
+            .property(
+                "team",
+                user.last_team.map_or("".to_owned(), |t| t.to_string()),
+            )
+            .property("kills", stats.kills)
+            .property("deaths", stats.deaths)
+            .property("assists", stats.assists)
+            .property("kd", stats.kd())
+            .property("dominations", stats.dominations)
+            .property("revenges", stats.revenges)
+            .property("crits", stats.crits)
+            .property("feigns", stats.feigns)
+            .property("time-played", stats.time_played)
+            .property("damage", stats.damage)
+            .property("damage-taken", stats.damage_taken)
+            .property("healing", stats.healing)
+            .property("ubers", stats.ubers)
+            .property("drops", stats.drops)
+            .property("captures", stats.captures)
+            .property("defenses", stats.defenses)
+            .build()
+    }
+}
+
+mod imp {
+    use std::cell::Cell;
+    use std::cell::RefCell;
+
+    use glib::Properties;
+    use gtk::glib;
+    use gtk::prelude::*;
+    use gtk::subclass::prelude::*;
+    use relm4::gtk;
+
+    #[derive(Properties, Default)]
+    #[properties(wrapper_type = super::ScoreboardObject)]
+    pub struct ScoreboardObject {
+        #[property(get, set)]
+        name: RefCell<String>,
+        #[property(get, set)]
+        team: RefCell<String>,
+        #[property(get, set)]
+        kills: Cell<u32>,
+        #[property(get, set)]
+        deaths: Cell<u32>,
+        #[property(get, set)]
+        assists: Cell<u32>,
+        #[property(get, set)]
+        kd: Cell<f32>,
+        #[property(get, set)]
+        dominations: Cell<u32>,
+        #[property(get, set)]
+        revenges: Cell<u32>,
+        #[property(get, set)]
+        crits: Cell<u32>,
+        #[property(get, set)]
+        feigns: Cell<u32>,
+        #[property(get, set)]
+        time_played: Cell<f32>,
+        #[property(get, set)]
+        damage: Cell<u32>,
+        #[property(get, set)]
+        damage_taken: Cell<u32>,
+        #[property(get, set)]
+        healing: Cell<u32>,
+        #[property(get, set)]
+        ubers: Cell<u32>,
+        #[property(get, set)]
+        drops: Cell<u32>,
+        #[property(get, set)]
+        captures: Cell<u32>,
+        #[property(get, set)]
+        defenses: Cell<u32>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ScoreboardObject {
+        const NAME: &'static str = "ScoreboardObject";
+        type Type = super::ScoreboardObject;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for ScoreboardObject {}
+}
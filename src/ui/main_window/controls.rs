@@ -6,7 +6,8 @@ use relm4::prelude::*;
 use relm4_icons::icon_names;
 
 use crate::demo_manager::Demo;
-use crate::settings::Settings;
+use crate::recorder::{Clip, RecordOptions};
+use crate::settings::{PlaybackAction, Settings};
 use crate::ui::inspection_window::InspectionMsg;
 use crate::util::sec_to_timestamp;
 use crate::util::ticks_to_sec;
@@ -14,8 +15,14 @@ use crate::util::ticks_to_sec;
 use super::super::inspection_window::InspectionModel;
 use super::super::inspection_window::InspectionOut;
 use super::super::main_window::RconAction;
+use super::record_dialog::{RecordDialogModel, RecordDialogMsg, RecordDialogOut};
 use super::util;
 
+/// Playback speeds selectable in the speed dropdown
+const SPEEDS: [f32; 7] = [0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 4.0];
+const SPEED_LABELS: [&str; 7] = ["0.25x", "0.5x", "0.75x", "1x", "1.5x", "2x", "4x"];
+const NORMAL_SPEED: u32 = 3;
+
 #[derive(Debug)]
 pub enum ControlsOut {
     Rcon(RconAction),
@@ -24,6 +31,7 @@ pub enum ControlsOut {
     SaveChanges,
     DiscardChanges,
     PlayheadMoved(u32),
+    Record(Clip, RecordOptions),
 }
 
 #[derive(Debug)]
@@ -32,14 +40,28 @@ pub enum ControlsMsg {
     SetDirty(bool),
 
     PlayheadMoved(f64),
+    GameTick(u32),
+    FollowGame(bool),
     Play,
     Stop,
+    TogglePause,
+    Speed(u32),
+    StepForward,
+    StepBackward,
+    Loop(bool),
+    Shortcut(PlaybackAction),
+    RunMacro(usize),
+    Spectate(Option<u32>, String),
     GotoPlayhead,
     SeekForward,
     SeekBackward,
     ConvertReplay,
     InspectDemo,
     DemoInspected(Demo),
+    SetInPoint,
+    SetOutPoint,
+    Record,
+    RecordClip(Clip, RecordOptions),
 
     SaveChanges,
     DiscardChanges,
@@ -49,11 +71,17 @@ pub struct ControlsModel {
     dirty: bool,
     demo: Option<Demo>,
     playhead_time: f64,
+    in_point: Option<u32>,
+    out_point: Option<u32>,
+    /// Index into [`SPEEDS`]
+    speed: u32,
+    looping: bool,
 
     window: adw::Window,
     settings: Rc<RefCell<Settings>>,
 
     inspection_wnd: Controller<InspectionModel>,
+    record_dialog: Controller<RecordDialogModel>,
 }
 
 #[relm4::component(async pub)]
@@ -137,6 +165,12 @@ impl AsyncComponent for ControlsModel {
                         connect_clicked => ControlsMsg::Play,
                     },
 
+                    gtk::Button{
+                        set_icon_name: icon_names::PAUSE,
+                        set_tooltip_text: Some("Pause/resume playback"),
+                        connect_clicked => ControlsMsg::TogglePause,
+                    },
+
                     gtk::Button{
                         set_icon_name: "find-location-symbolic",
                         set_tooltip_text: Some("Skip to tick"),
@@ -149,6 +183,42 @@ impl AsyncComponent for ControlsModel {
                         connect_clicked => ControlsMsg::Stop,
                     },
 
+                    gtk::ToggleButton{
+                        set_icon_name: icon_names::CHAIN_LINK,
+                        set_tooltip_text: Some("Follow the playback position of the game"),
+                        set_active: model.settings.borrow().follow_game,
+                        connect_toggled[sender] => move |btn| {
+                            sender.input(ControlsMsg::FollowGame(btn.is_active()));
+                        },
+                    },
+
+                    gtk::Separator{
+                        set_orientation: gtk::Orientation::Vertical,
+                        add_css_class: "spacer",
+                    },
+
+                    gtk::Button{
+                        set_icon_name: icon_names::ARROW_POINTING_AT_LINE_LEFT,
+                        set_tooltip_text: Some("Step one tick back"),
+                        connect_clicked => ControlsMsg::StepBackward,
+                    },
+
+                    gtk::Button{
+                        set_icon_name: icon_names::ARROW_POINTING_AT_LINE_RIGHT,
+                        set_tooltip_text: Some("Step one tick forward"),
+                        connect_clicked => ControlsMsg::StepForward,
+                    },
+
+                    gtk::DropDown::from_strings(&SPEED_LABELS) {
+                        set_tooltip_text: Some("Playback speed"),
+                        #[watch]
+                        #[block_signal(speed_handler)]
+                        set_selected: model.speed,
+                        connect_selected_notify[sender] => move |dd| {
+                            sender.input(ControlsMsg::Speed(dd.selected()));
+                        } @speed_handler,
+                    },
+
                     gtk::Separator{
                         set_orientation: gtk::Orientation::Vertical,
                         add_css_class: "spacer",
@@ -181,7 +251,56 @@ impl AsyncComponent for ControlsModel {
                         set_icon_name: icon_names::LIST_COMPACT,
                         set_tooltip_text: Some("Inspect demo"),
                         connect_clicked => ControlsMsg::InspectDemo,
-                    }
+                    },
+
+                    gtk::Separator{
+                        set_orientation: gtk::Orientation::Vertical,
+                        add_css_class: "spacer",
+                    },
+
+                    gtk::Button{
+                        set_icon_name: icon_names::EXECUTE_FROM,
+                        set_tooltip_text: Some("Set in point"),
+                        connect_clicked => ControlsMsg::SetInPoint,
+                    },
+
+                    gtk::Button{
+                        set_icon_name: icon_names::EXECUTE_TO,
+                        set_tooltip_text: Some("Set out point"),
+                        connect_clicked => ControlsMsg::SetOutPoint,
+                    },
+
+                    gtk::ToggleButton{
+                        set_icon_name: icon_names::PLAYLIST_REPEAT,
+                        set_tooltip_text: Some("Loop playback between in and out point"),
+                        #[watch]
+                        set_sensitive: model.loop_range().is_some(),
+                        #[watch]
+                        #[block_signal(loop_handler)]
+                        set_active: model.looping,
+                        connect_toggled[sender] => move |btn| {
+                            sender.input(ControlsMsg::Loop(btn.is_active()));
+                        } @loop_handler,
+                    },
+
+                    gtk::Button{
+                        set_icon_name: icon_names::FILM_CAMERA,
+                        set_tooltip_text: Some("Record clip between in and out point"),
+                        #[watch]
+                        set_sensitive: model.loop_range().is_some(),
+                        connect_clicked => ControlsMsg::Record,
+                    },
+
+                    gtk::Separator{
+                        set_orientation: gtk::Orientation::Vertical,
+                        add_css_class: "spacer",
+                    },
+
+                    #[name = "macro_button"]
+                    gtk::MenuButton{
+                        set_icon_name: icon_names::TERMINAL,
+                        set_tooltip_text: Some("Run command macro"),
+                    },
                 },
 
                 #[wrap(Some)]
@@ -217,12 +336,25 @@ impl AsyncComponent for ControlsModel {
             demo: None,
             dirty: false,
             playhead_time: 0.0,
+            in_point: None,
+            out_point: None,
+            speed: NORMAL_SPEED,
+            looping: false,
+            record_dialog: RecordDialogModel::builder().launch(init.0.clone()).forward(
+                sender.input_sender(),
+                |msg| match msg {
+                    RecordDialogOut::Record(clip, options) => {
+                        ControlsMsg::RecordClip(clip, options)
+                    }
+                },
+            ),
             window: init.0,
-            settings: init.1,
-            inspection_wnd: InspectionModel::builder().launch(()).forward(
+            settings: init.1.clone(),
+            inspection_wnd: InspectionModel::builder().launch(init.1.clone()).forward(
                 sender.input_sender(),
                 |msg| match msg {
                     InspectionOut::GotoTick(tick) => ControlsMsg::PlayheadMoved(tick.into()),
+                    InspectionOut::Spectate(tick, player) => ControlsMsg::Spectate(tick, player),
                     InspectionOut::DemoProcessed(dem) => ControlsMsg::DemoInspected(dem),
                 },
             ),
@@ -230,6 +362,74 @@ impl AsyncComponent for ControlsModel {
 
         let widgets = view_output!();
 
+        // Built on every click, so macros edited in the settings show up right away
+        let settings = model.settings.clone();
+        let macro_sender = sender.input_sender().clone();
+        widgets.macro_button.set_create_popup_func(move |btn| {
+            let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            let popover = gtk::Popover::builder().child(&list).build();
+            for (i, command_macro) in settings.borrow().macros.iter().enumerate() {
+                let button = gtk::Button::builder()
+                    .label(&command_macro.name)
+                    .tooltip_text(&command_macro.commands)
+                    .css_classes(["flat"])
+                    .build();
+                let popover = popover.clone();
+                let sender = macro_sender.clone();
+                button.connect_clicked(move |_| {
+                    popover.popdown();
+                    sender.emit(ControlsMsg::RunMacro(i));
+                });
+                list.append(&button);
+            }
+            if settings.borrow().macros.is_empty() {
+                list.append(&gtk::Label::new(Some(
+                    "No macros, add them in the settings",
+                )));
+            }
+            btn.set_popover(Some(&popover));
+        });
+
+        let keys = gtk::EventControllerKey::new();
+        let settings = model.settings.clone();
+        let key_sender = sender.input_sender().clone();
+        keys.connect_key_pressed(move |keys, key, _, state| {
+            // Keys typed into text fields aren't shortcuts
+            let typing = keys
+                .widget()
+                .and_then(|w| w.root())
+                .and_then(|r| r.focus())
+                .is_some_and(|f| f.is::<gtk::Text>() || f.is::<gtk::TextView>());
+            if typing {
+                return gtk::glib::Propagation::Proceed;
+            }
+            let state = state & gtk::accelerator_get_default_mod_mask();
+            let matches = |accel: &str| {
+                gtk::accelerator_parse(accel)
+                    .is_some_and(|(k, m)| k.to_lower() == key.to_lower() && m == state)
+            };
+            let settings = settings.borrow();
+            let msg = PlaybackAction::ALL
+                .into_iter()
+                .find(|a| matches(settings.shortcuts.get(*a)))
+                .map(ControlsMsg::Shortcut)
+                .or_else(|| {
+                    settings
+                        .macros
+                        .iter()
+                        .position(|m| matches(&m.shortcut))
+                        .map(ControlsMsg::RunMacro)
+                });
+            match msg {
+                Some(msg) => {
+                    key_sender.emit(msg);
+                    gtk::glib::Propagation::Stop
+                }
+                None => gtk::glib::Propagation::Proceed,
+            }
+        });
+        model.window.add_controller(keys);
+
         AsyncComponentParts { model, widgets }
     }
 
@@ -246,30 +446,125 @@ impl AsyncComponent for ControlsModel {
                 self.playhead_time = val;
                 let _ = sender.output(ControlsOut::PlayheadMoved(val as u32));
             }
+            ControlsMsg::GameTick(tick) => {
+                self.playhead_time = (tick as f64).min(widgets.playhead.adjustment().upper());
+                let _ = sender.output(ControlsOut::PlayheadMoved(self.playhead_time as u32));
+                if let Some(demo) = &self.demo {
+                    self.inspection_wnd
+                        .emit(InspectionMsg::GameTick(demo.path.clone(), tick));
+                }
+            }
+            ControlsMsg::FollowGame(follow) => {
+                let mut settings = self.settings.borrow_mut();
+                settings.follow_game = follow;
+                settings.save();
+            }
             ControlsMsg::SetDemo(dem, keep_playhead) => {
                 if !keep_playhead {
                     self.playhead_time = 0.0;
-                }
-                widgets.playhead.clear_marks();
-                for event in dem.as_ref().map_or(&vec![], |d| &d.events) {
-                    widgets
-                        .playhead
-                        .add_mark(event.tick as f64, gtk::PositionType::Bottom, None);
+                    self.in_point = None;
+                    self.out_point = None;
+                    if self.looping {
+                        sender.input(ControlsMsg::Loop(false));
+                    }
                 }
                 self.demo = dem;
                 self.dirty = false;
+                self.update_marks(&widgets.playhead);
+            }
+            ControlsMsg::SetInPoint => {
+                self.in_point = Some(self.playhead_time as u32);
+                self.update_marks(&widgets.playhead);
+                if self.looping {
+                    sender.input(ControlsMsg::Loop(true));
+                }
+            }
+            ControlsMsg::SetOutPoint => {
+                self.out_point = Some(self.playhead_time as u32);
+                self.update_marks(&widgets.playhead);
+                if self.looping {
+                    sender.input(ControlsMsg::Loop(true));
+                }
+            }
+            ControlsMsg::Record => {
+                if let (Some(demo), Some(start_tick), Some(end_tick)) =
+                    (&self.demo, self.in_point, self.out_point)
+                {
+                    let clip = Clip {
+                        demo: demo.clone(),
+                        start_tick,
+                        end_tick,
+                    };
+                    let settings = self.settings.borrow();
+                    let presets = settings.ffmpeg.presets.iter().map(|p| p.name.clone());
+                    self.record_dialog.emit(RecordDialogMsg::Show(
+                        Box::new(clip),
+                        settings.recording.clone(),
+                        presets.collect(),
+                    ));
+                }
+            }
+            ControlsMsg::RecordClip(clip, options) => {
+                let _ = sender.output(ControlsOut::Record(clip, options));
             }
             ControlsMsg::Play => {
+                self.looping = false;
                 let _ = sender.output(ControlsOut::Rcon(RconAction::Play(
-                    self.demo.as_ref().unwrap().filename.clone(),
+                    self.demo.as_ref().unwrap().path.clone(),
+                )));
+            }
+            ControlsMsg::TogglePause => {
+                let _ = sender.output(ControlsOut::Rcon(RconAction::TogglePause));
+            }
+            ControlsMsg::Speed(index) => {
+                self.speed = index.min(SPEEDS.len() as u32 - 1);
+                let _ = sender.output(ControlsOut::Rcon(RconAction::Speed(
+                    SPEEDS[self.speed as usize],
                 )));
             }
+            ControlsMsg::StepForward => {
+                let _ = sender.output(ControlsOut::Rcon(RconAction::Step(1)));
+            }
+            ControlsMsg::StepBackward => {
+                let _ = sender.output(ControlsOut::Rcon(RconAction::Step(-1)));
+            }
+            ControlsMsg::Loop(active) => {
+                let range = self.loop_range().filter(|_| active);
+                self.looping = range.is_some();
+                let _ = sender.output(ControlsOut::Rcon(RconAction::Loop(range)));
+            }
+            ControlsMsg::Spectate(tick, player) => {
+                let _ = sender.output(ControlsOut::Rcon(RconAction::Spectate(tick, player)));
+            }
+            ControlsMsg::RunMacro(index) => {
+                if let Some(command_macro) = self.settings.borrow().macros.get(index) {
+                    let commands =
+                        command_macro.expand(self.playhead_time as u32, self.demo.as_ref());
+                    let _ = sender.output(ControlsOut::Rcon(RconAction::Macro(commands)));
+                }
+            }
+            ControlsMsg::Shortcut(action) => {
+                if self.demo.is_none() {
+                    return;
+                }
+                sender.input(match action {
+                    PlaybackAction::Play => ControlsMsg::Play,
+                    PlaybackAction::TogglePause => ControlsMsg::TogglePause,
+                    PlaybackAction::Stop => ControlsMsg::Stop,
+                    PlaybackAction::SpeedUp => ControlsMsg::Speed(self.speed + 1),
+                    PlaybackAction::SpeedDown => ControlsMsg::Speed(self.speed.saturating_sub(1)),
+                    PlaybackAction::StepForward => ControlsMsg::StepForward,
+                    PlaybackAction::StepBackward => ControlsMsg::StepBackward,
+                    PlaybackAction::ToggleLoop => ControlsMsg::Loop(!self.looping),
+                });
+            }
             ControlsMsg::GotoPlayhead => {
                 let _ = sender.output(ControlsOut::Rcon(RconAction::GotoTick(
                     self.playhead_time as u32,
                 )));
             }
             ControlsMsg::Stop => {
+                self.looping = false;
                 let _ = sender.output(ControlsOut::Rcon(RconAction::Stop));
             }
             ControlsMsg::SeekBackward => {
@@ -376,11 +671,7 @@ impl AsyncComponent for ControlsModel {
             }
             ControlsMsg::SetDirty(state) => self.dirty = state,
             ControlsMsg::DemoInspected(dem) => {
-                if self
-                    .demo
-                    .as_ref()
-                    .map_or(false, |d| d.filename == dem.filename)
-                {
+                if self.demo.as_ref().map_or(false, |d| d.path == dem.path) {
                     self.demo = Some(dem.clone());
                 }
                 let _ = sender.output(ControlsOut::DemoInspected(dem));
@@ -389,3 +680,22 @@ impl AsyncComponent for ControlsModel {
         self.update_view(widgets, sender);
     }
 }
+
+impl ControlsModel {
+    fn loop_range(&self) -> Option<(u32, u32)> {
+        self.in_point.zip(self.out_point).filter(|(i, o)| i < o)
+    }
+
+    fn update_marks(&self, playhead: &gtk::Scale) {
+        playhead.clear_marks();
+        for event in self.demo.as_ref().map_or(&vec![], |d| &d.events) {
+            playhead.add_mark(event.tick as f64, gtk::PositionType::Bottom, None);
+        }
+        if let Some(tick) = self.in_point {
+            playhead.add_mark(tick as f64, gtk::PositionType::Top, Some("In"));
+        }
+        if let Some(tick) = self.out_point {
+            playhead.add_mark(tick as f64, gtk::PositionType::Top, Some("Out"));
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use adw::prelude::*;
 use relm4::prelude::*;
 
-use crate::demo_manager::Demo;
+use crate::demo_manager::{self, Demo};
 
 #[derive(Debug)]
 pub enum DemoInfoboxOut {
@@ -13,12 +13,28 @@ pub enum DemoInfoboxMsg {
     Display(Option<Demo>),
 
     NotesChanged(String),
+    /// Comma separated tags
+    TagsChanged(String),
+    /// Clicked star, clicking the current rating removes it
+    RatingClicked(u8),
     OpenFolder,
 }
 
 pub struct DemoInfoboxModel {
     demo: Option<Demo>,
     pub notes: Option<String>,
+    pub tags: Vec<String>,
+    pub rating: Option<u8>,
+}
+
+impl DemoInfoboxModel {
+    /// Whether notes, tags or rating differ from the displayed demo
+    fn dirty(&self) -> bool {
+        let Some(demo) = &self.demo else {
+            return false;
+        };
+        self.notes != demo.notes || self.tags != demo.tags || self.rating != demo.rating
+    }
 }
 
 #[relm4::component(pub)]
@@ -124,12 +140,44 @@ impl Component for DemoInfoboxModel {
                 },
 
                 attach[0,5,1,1] = &gtk::Label{
+                    set_label: "Tags:",
+                    set_halign: gtk::Align::Start,
+                },
+
+                #[name="tags"]
+                attach[1,5,1,1] = &gtk::Entry{
+                    set_halign: gtk::Align::Fill,
+                    set_valign: gtk::Align::Center,
+                    set_hexpand: true,
+                    set_placeholder_text: Some("scrim, frag movie, ..."),
+                    set_primary_icon_name: Some(relm4_icons::icon_names::TAG_OUTLINE),
+                    set_tooltip_text: Some("Comma separated"),
+                    #[watch]
+                    set_sensitive: model.demo.is_some(),
+                    connect_changed[sender] => move |entry|{
+                        sender.input(DemoInfoboxMsg::TagsChanged(entry.text().to_string()));
+                    },
+                },
+
+                attach[0,6,1,1] = &gtk::Label{
+                    set_label: "Rating:",
+                    set_halign: gtk::Align::Start,
+                },
+
+                #[name="stars"]
+                attach[1,6,1,1] = &gtk::Box{
+                    set_halign: gtk::Align::Start,
+                    #[watch]
+                    set_sensitive: model.demo.is_some(),
+                },
+
+                attach[0,7,1,1] = &gtk::Label{
                     set_label: "Notes:",
                     set_halign: gtk::Align::Start,
                 },
 
                 #[name="notes"]
-                attach[0,6,2,1] = &gtk::TextView{
+                attach[0,8,2,1] = &gtk::TextView{
                     set_vexpand: true,
                     #[wrap(Some)]
                     set_buffer = &gtk::TextBuffer{
@@ -153,10 +201,23 @@ impl Component for DemoInfoboxModel {
         let model = DemoInfoboxModel {
             demo: None,
             notes: None,
+            tags: Vec::new(),
+            rating: None,
         };
 
         let widgets = view_output!();
 
+        for star in 1..=demo_manager::MAX_RATING {
+            let button = gtk::Button::builder()
+                .icon_name(relm4_icons::icon_names::STAR_OUTLINE_ROUNDED)
+                .tooltip_text(format!("{star}/{}", demo_manager::MAX_RATING))
+                .css_classes(["flat", "circular"])
+                .build();
+            let sender = sender.clone();
+            button.connect_clicked(move |_| sender.input(DemoInfoboxMsg::RatingClicked(star)));
+            widgets.stars.append(&button);
+        }
+
         ComponentParts { model, widgets }
     }
 
@@ -171,12 +232,15 @@ impl Component for DemoInfoboxModel {
         match message {
             DemoInfoboxMsg::Display(demo) => {
                 self.demo = demo;
+                self.tags = self.demo.as_ref().map_or(Vec::new(), |d| d.tags.clone());
+                self.rating = self.demo.as_ref().and_then(|d| d.rating);
                 widgets.notes.buffer().set_text(
                     self.demo
                         .as_ref()
                         .and_then(|d| d.notes.as_ref())
                         .unwrap_or(&"".to_owned()),
-                )
+                );
+                widgets.tags.set_text(&self.tags.join(", "));
             }
             DemoInfoboxMsg::NotesChanged(notes) => {
                 let new_notes: Option<String>;
@@ -187,11 +251,20 @@ impl Component for DemoInfoboxModel {
                     new_notes = Some(notes);
                 }
 
-                let _ = sender.output(DemoInfoboxOut::Dirty(
-                    new_notes.as_ref() != self.demo.as_ref().and_then(|d| d.notes.as_ref()),
-                ));
-
                 self.notes = new_notes;
+                let _ = sender.output(DemoInfoboxOut::Dirty(self.dirty()));
+            }
+            DemoInfoboxMsg::TagsChanged(tags) => {
+                self.tags = demo_manager::parse_tags(tags.split(','));
+                let _ = sender.output(DemoInfoboxOut::Dirty(self.dirty()));
+            }
+            DemoInfoboxMsg::RatingClicked(star) => {
+                self.rating = if self.rating == Some(star) {
+                    None
+                } else {
+                    Some(star)
+                };
+                let _ = sender.output(DemoInfoboxOut::Dirty(self.dirty()));
             }
             DemoInfoboxMsg::OpenFolder => {
                 let path = self.demo.as_ref().unwrap().path.as_path();
@@ -200,6 +273,17 @@ impl Component for DemoInfoboxModel {
                 }
             }
         }
+        let mut star = widgets.stars.first_child();
+        let mut value = 1;
+        while let Some(button) = star.and_downcast::<gtk::Button>() {
+            button.set_icon_name(if self.rating.is_some_and(|r| r >= value) {
+                relm4_icons::icon_names::STAR_LARGE
+            } else {
+                relm4_icons::icon_names::STAR_OUTLINE_ROUNDED
+            });
+            star = button.next_sibling();
+            value += 1;
+        }
         self.update_view(widgets, sender);
     }
 }
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::hash::RandomState;
+use std::path::PathBuf;
 
 use adw::prelude::*;
 use chrono::TimeZone;
@@ -8,27 +9,42 @@ use gtk::gio;
 use relm4::prelude::*;
 
 use super::demo_object::DemoObject;
-use crate::demo_manager::Demo;
+use crate::demo_manager::{self, Demo};
 
 pub struct DemoListModel {
     list_model: gio::ListStore,
     list_selection: gtk::MultiSelection,
+    /// Hides demos without the tags picked in the sidebar
+    tag_filter: gtk::CustomFilter,
+    /// Folders the listed demos were loaded from, for the folder column
+    roots: Vec<PathBuf>,
 }
 
 #[derive(Debug)]
 pub enum DemoListMsg {
-    Update(HashMap<String, Demo>, bool),
+    /// The demos and the folders they were loaded from
+    Update(HashMap<PathBuf, Demo>, Vec<PathBuf>, bool),
     SelectionChanged,
+    /// Empties the list before loading demos from these folders
+    Clear(Vec<PathBuf>),
+    /// Demos read while loading
+    Append(Vec<Demo>),
+    /// Demos that changed on disk, `None` for removed ones
+    Refresh(Vec<(PathBuf, Option<Demo>)>),
+    /// Selects the demo with this path and scrolls to it
+    Select(PathBuf),
+    /// Only shows demos that have all of these tags
+    FilterTags(Vec<String>),
 }
 
 #[derive(Debug)]
 pub enum DemoListOut {
-    SelectionChanged(Option<String>),
-    DemoActivated(String),
+    SelectionChanged(Option<PathBuf>),
+    DemoActivated(PathBuf),
 }
 
 impl DemoListModel {
-    pub fn get_selected_demos(&self) -> Vec<String> {
+    pub fn get_selected_demos(&self) -> Vec<PathBuf> {
         let selected = self.list_selection.selection();
         if selected.is_empty() {
             return vec![];
@@ -42,7 +58,8 @@ impl DemoListModel {
                     .item(selected.nth(i))
                     .and_downcast_ref::<DemoObject>()
                     .unwrap()
-                    .name()
+                    .path()
+                    .into()
             })
             .collect()
     }
@@ -64,8 +81,8 @@ impl Component for DemoListModel {
             gtk::ColumnView{
                 set_model: Some(&model.list_selection),
                 connect_activate[sender] => move |view,ind| {
-                    let demo_name = view.model().unwrap().item(ind).and_downcast_ref::<DemoObject>().unwrap().name();
-                    let _ = sender.output(DemoListOut::DemoActivated(demo_name));
+                    let demo_path = view.model().unwrap().item(ind).and_downcast_ref::<DemoObject>().unwrap().path();
+                    let _ = sender.output(DemoListOut::DemoActivated(demo_path.into()));
                 }
             }
         }
@@ -77,11 +94,16 @@ impl Component for DemoListModel {
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
         let liststore = gio::ListStore::new::<DemoObject>();
-        let sorted_model = gtk::SortListModel::builder().model(&liststore).build();
+        let tag_filter = gtk::CustomFilter::new(|_| true);
+        let filtered_model =
+            gtk::FilterListModel::new(Some(liststore.clone()), Some(tag_filter.clone()));
+        let sorted_model = gtk::SortListModel::builder().model(&filtered_model).build();
 
         let model = DemoListModel {
             list_model: liststore.clone(),
             list_selection: gtk::MultiSelection::new(Some(sorted_model.clone())),
+            tag_filter,
+            roots: Vec::new(),
         };
 
         {
@@ -101,11 +123,23 @@ impl Component for DemoListModel {
         name_factory.connect_setup(|_, li| {
             let listitem = li.downcast_ref::<gtk::ListItem>().unwrap();
             let label = gtk::Label::builder().halign(gtk::Align::Start).build();
-            listitem.set_child(Some(&label));
+            let recording = gtk::Label::builder()
+                .label("recording")
+                .tooltip_text("The demo is still being written")
+                .css_classes(["caption", "accent"])
+                .build();
+            let cell = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            cell.append(&label);
+            cell.append(&recording);
+            listitem.set_child(Some(&cell));
             listitem
                 .property_expression("item")
                 .chain_property::<DemoObject>("name")
                 .bind(&label, "label", gtk::Widget::NONE);
+            listitem
+                .property_expression("item")
+                .chain_property::<DemoObject>("recording")
+                .bind(&recording, "visible", gtk::Widget::NONE);
         });
         widgets.demo_list.append_column(
             &gtk::ColumnViewColumn::builder()
@@ -123,6 +157,39 @@ impl Component for DemoListModel {
                 .build(),
         );
 
+        let folder_factory = gtk::SignalListItemFactory::new();
+        folder_factory.connect_setup(|_, li| {
+            let listitem = li.downcast_ref::<gtk::ListItem>().unwrap();
+            let label = gtk::Label::builder()
+                .halign(gtk::Align::Start)
+                .ellipsize(gtk::pango::EllipsizeMode::Start)
+                .build();
+            listitem.set_child(Some(&label));
+            listitem
+                .property_expression("item")
+                .chain_property::<DemoObject>("folder")
+                .bind(&label, "label", gtk::Widget::NONE);
+            listitem
+                .property_expression("item")
+                .chain_property::<DemoObject>("path")
+                .bind(&label, "tooltip-text", gtk::Widget::NONE);
+        });
+        widgets.demo_list.append_column(
+            &gtk::ColumnViewColumn::builder()
+                .title("Folder")
+                .resizable(true)
+                .factory(&folder_factory)
+                .expand(true)
+                .sorter(&gtk::StringSorter::new(Some(
+                    &gtk::PropertyExpression::new(
+                        DemoObject::static_type(),
+                        None::<gtk::Expression>,
+                        "folder",
+                    ),
+                )))
+                .build(),
+        );
+
         let map_factory = gtk::SignalListItemFactory::new();
         map_factory.connect_setup(|_, li| {
             let listitem = li.downcast_ref::<gtk::ListItem>().unwrap();
@@ -298,6 +365,66 @@ impl Component for DemoListModel {
                 .build(),
         );
 
+        let tags_factory = gtk::SignalListItemFactory::new();
+        tags_factory.connect_setup(|_, li| {
+            let listitem = li.downcast_ref::<gtk::ListItem>().unwrap();
+            let label = gtk::Label::builder()
+                .halign(gtk::Align::Start)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build();
+            listitem.set_child(Some(&label));
+            listitem
+                .property_expression("item")
+                .chain_property::<DemoObject>("tags")
+                .bind(&label, "label", gtk::Widget::NONE);
+            listitem
+                .property_expression("item")
+                .chain_property::<DemoObject>("tags")
+                .bind(&label, "tooltip-text", gtk::Widget::NONE);
+        });
+        widgets.demo_list.append_column(
+            &gtk::ColumnViewColumn::builder()
+                .title("Tags")
+                .resizable(true)
+                .factory(&tags_factory)
+                .expand(true)
+                .sorter(&gtk::StringSorter::new(Some(
+                    &gtk::PropertyExpression::new(
+                        DemoObject::static_type(),
+                        None::<gtk::Expression>,
+                        "tags",
+                    ),
+                )))
+                .build(),
+        );
+
+        let rating_factory = gtk::SignalListItemFactory::new();
+        rating_factory.connect_setup(|_, li| {
+            let listitem = li.downcast_ref::<gtk::ListItem>().unwrap();
+            let label = gtk::Label::builder().halign(gtk::Align::Start).build();
+            listitem.set_child(Some(&label));
+            listitem
+                .property_expression("item")
+                .chain_property::<DemoObject>("rating")
+                .chain_closure_with_callback(|v| "★".repeat(v[1].get::<u32>().unwrap() as usize))
+                .bind(&label, "label", gtk::Widget::NONE);
+        });
+        widgets.demo_list.append_column(
+            &gtk::ColumnViewColumn::builder()
+                .title("Rating")
+                .resizable(true)
+                .factory(&rating_factory)
+                .expand(true)
+                .sorter(&gtk::NumericSorter::new(Some(
+                    &gtk::PropertyExpression::new(
+                        DemoObject::static_type(),
+                        None::<gtk::Expression>,
+                        "rating",
+                    ),
+                )))
+                .build(),
+        );
+
         widgets
             .demo_list
             .sort_by_column(Some(date_column), gtk::SortType::Descending);
@@ -307,34 +434,93 @@ impl Component for DemoListModel {
 
     fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, root: &Self::Root) {
         match message {
-            DemoListMsg::Update(demos, scroll) => {
-                let model_set: HashSet<(String, u64), RandomState> =
+            DemoListMsg::Update(demos, roots, scroll) => {
+                self.roots = roots;
+                let model_set: HashSet<(PathBuf, u64), RandomState> =
                     HashSet::from_iter(self.list_model.into_iter().map(|d| {
                         let demo = d.unwrap().downcast::<DemoObject>().unwrap();
-                        (demo.name(), demo.size())
+                        (demo.path().into(), demo.size())
                     }));
 
-                let data_set: HashSet<(String, u64), RandomState> =
-                    HashSet::from_iter(demos.iter().map(|t| {
-                        let demo = t.1;
-                        (demo.filename.to_owned(), demo.size.unwrap_or(0))
-                    }));
+                let data_set: HashSet<(PathBuf, u64), RandomState> = HashSet::from_iter(
+                    demos
+                        .iter()
+                        .map(|(path, demo)| (path.clone(), demo.size.unwrap_or(0))),
+                );
 
                 self.list_model.retain(|d| {
                     let d = d.downcast_ref::<DemoObject>().unwrap();
-                    data_set.contains(&(d.name(), d.size()))
+                    data_set.contains(&(d.path().into(), d.size()))
                 });
 
                 let added = data_set.difference(&model_set);
-                for dn in added {
+                for (path, _) in added {
+                    let folder = demo_manager::folder_label(path, &self.roots);
                     self.list_model
-                        .append(&DemoObject::new(demos.get(&dn.0).unwrap()));
+                        .append(&DemoObject::new(demos.get(path).unwrap(), &folder));
                 }
 
                 if scroll {
                     root.vadjustment().set_value(0.0);
                 }
             }
+            DemoListMsg::Clear(roots) => {
+                self.roots = roots;
+                self.list_model.remove_all();
+            }
+            DemoListMsg::Append(demos) => {
+                let objects: Vec<DemoObject> = demos
+                    .iter()
+                    .map(|d| DemoObject::new(d, &demo_manager::folder_label(&d.path, &self.roots)))
+                    .collect();
+                self.list_model.extend_from_slice(&objects);
+            }
+            DemoListMsg::Refresh(changes) => {
+                for (path, demo) in changes {
+                    let pos = self.list_model.find_with_equal_func(|d| {
+                        d.downcast_ref::<DemoObject>()
+                            .is_some_and(|d| path == d.path())
+                    });
+                    match (pos, demo) {
+                        (Some(pos), Some(demo)) => self
+                            .list_model
+                            .item(pos)
+                            .and_downcast::<DemoObject>()
+                            .unwrap()
+                            .update(&demo),
+                        (Some(pos), None) => self.list_model.remove(pos),
+                        (None, Some(demo)) => {
+                            let folder = demo_manager::folder_label(&path, &self.roots);
+                            self.list_model.append(&DemoObject::new(&demo, &folder));
+                        }
+                        (None, None) => {}
+                    }
+                }
+                // Tags of the updated demos might not match the filter anymore
+                self.tag_filter.changed(gtk::FilterChange::Different);
+            }
+            DemoListMsg::Select(path) => {
+                let model = self.list_selection.model().unwrap();
+                let pos = (0..model.n_items()).find(|i| {
+                    model
+                        .item(*i)
+                        .and_downcast_ref::<DemoObject>()
+                        .is_some_and(|d| path == d.path())
+                });
+                if let (Some(pos), Some(view)) =
+                    (pos, root.child().and_downcast::<gtk::ColumnView>())
+                {
+                    view.scroll_to(pos, None, gtk::ListScrollFlags::SELECT, None);
+                }
+            }
+            DemoListMsg::FilterTags(tags) => {
+                self.tag_filter.set_filter_func(move |item| {
+                    // Tags never contain commas, see `demo_manager::parse_tags`
+                    let demo_tags = item.downcast_ref::<DemoObject>().unwrap().tags();
+                    tags.iter()
+                        .all(|tag| demo_tags.split(", ").any(|t| t == tag))
+                });
+            }
             DemoListMsg::SelectionChanged => {
                 let selected = self.list_selection.selection();
                 if selected.is_empty() {
@@ -343,13 +529,13 @@ impl Component for DemoListModel {
                 }
 
                 let model = self.list_selection.model().unwrap();
-                let dem_name = model
+                let dem_path = model
                     .item(selected.nth(0))
                     .and_downcast_ref::<DemoObject>()
                     .unwrap()
-                    .name();
+                    .path();
 
-                let _ = sender.output(DemoListOut::SelectionChanged(Some(dem_name)));
+                let _ = sender.output(DemoListOut::SelectionChanged(Some(dem_path.into())));
             }
         }
     }
@@ -11,30 +11,33 @@ glib::wrapper! {
 }
 
 impl DemoObject {
-    pub fn new(demo: &Demo) -> Self {
-        let mut b = Object::builder()
-            .property("name", demo.filename.to_owned())
-            .property("bookmarks", demo.events.len() as u32);
+    pub fn new(demo: &Demo, folder: &str) -> Self {
+        let obj: Self = Object::builder()
+            .property("path", demo.get_path())
+            .property("folder", folder)
+            .build();
+        obj.update(demo);
+        obj
+    }
 
+    /// Takes over the properties of the reread demo
+    pub fn update(&self, demo: &Demo) {
+        self.set_name(demo.filename.to_owned());
+        self.set_bookmarks(demo.events.len() as u32);
+        self.set_tags(demo.tags.join(", "));
+        self.set_rating(demo.rating.unwrap_or(0) as u32);
         if let Some(header) = &demo.header {
-            b = b
-                .property("map", header.map.to_owned())
-                .property("username", header.nick.to_owned())
-                .property("duration", header.duration);
+            self.set_map(header.map.to_owned());
+            self.set_username(header.nick.to_owned());
+            self.set_duration(header.duration);
         }
-
-        b = b
-            .property(
-                "created",
-                demo.created.map_or(0, |t| {
-                    t.duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as i64
-                }),
-            )
-            .property("size", demo.size.unwrap_or(0));
-
-        b.build()
+        self.set_created(demo.created.map_or(0, |t| {
+            t.duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as i64
+        }));
+        self.set_size(demo.size.unwrap_or(0));
+        self.set_recording(demo.recording);
     }
 }
 
@@ -54,6 +57,10 @@ mod imp {
         #[property(get, set)]
         name: RefCell<String>,
         #[property(get, set)]
+        path: RefCell<String>,
+        #[property(get, set)]
+        folder: RefCell<String>,
+        #[property(get, set)]
         map: RefCell<String>,
         #[property(get, set)]
         username: RefCell<String>,
@@ -61,12 +68,20 @@ mod imp {
         duration: Cell<f32>,
         #[property(get, set)]
         bookmarks: Cell<u32>,
+        /// Comma separated
+        #[property(get, set)]
+        tags: RefCell<String>,
+        /// 0 if the demo isn't rated
+        #[property(get, set)]
+        rating: Cell<u32>,
         #[property(get, set)]
         size: Cell<u64>,
         #[property(get, set)]
         created: Cell<i64>,
         #[property(get, set)]
         has_replay: Cell<bool>,
+        #[property(get, set)]
+        recording: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -12,6 +12,8 @@ pub enum EventListOut {
 
     AddEvent,
     EditEvent(Event),
+    DetectHighlights,
+    Enqueue(Event),
     Dirty,
 }
 
@@ -22,6 +24,9 @@ pub enum EventListMsg {
 
     Edit,
     Delete,
+    Enqueue,
+    /// Selects the last event before the tick
+    GameTick(u32),
 }
 
 pub struct EventListModel {
@@ -41,10 +46,11 @@ impl EventListModel {
 }
 
 #[relm4::component(pub)]
-impl SimpleComponent for EventListModel {
+impl Component for EventListModel {
     type Init = adw::Window;
     type Input = EventListMsg;
     type Output = EventListOut;
+    type CommandOutput = ();
 
     view! {
         adw::ToolbarView {
@@ -122,6 +128,24 @@ impl SimpleComponent for EventListModel {
                     set_sensitive: model.selection_model.selected_item().is_some(),
                     connect_clicked => EventListMsg::Edit,
                 },
+
+                pack_end = &gtk::Button{
+                    set_icon_name: relm4_icons::icon_names::FILM_CAMERA,
+                    set_tooltip_text: Some("Add selected event to the render queue"),
+                    #[watch]
+                    set_sensitive: model.selection_model.selected_item().is_some(),
+                    connect_clicked => EventListMsg::Enqueue,
+                },
+
+                pack_end = &gtk::Button{
+                    set_icon_name: relm4_icons::icon_names::STAR_LARGE,
+                    set_tooltip_text: Some("Detect highlights"),
+                    #[watch]
+                    set_sensitive: model.demo.is_some(),
+                    connect_clicked[sender] => move|_|{
+                        let _ = sender.output(EventListOut::DetectHighlights);
+                    },
+                },
             }
         }
     }
@@ -144,7 +168,39 @@ impl SimpleComponent for EventListModel {
         ComponentParts { model, widgets }
     }
 
-    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>) {
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        if let EventListMsg::GameTick(tick) = message {
+            let current = self
+                .list_model
+                .iter::<EventObject>()
+                .map_while(Result::ok)
+                .take_while(|ev| ev.tick() <= tick)
+                .count();
+            match current.checked_sub(1) {
+                Some(pos) if pos as u32 != self.selection_model.selected() => {
+                    self.selection_model.set_selected(pos as u32);
+                    widgets
+                        .list_view
+                        .scroll_to(pos as u32, gtk::ListScrollFlags::NONE, None);
+                }
+                Some(_) => {}
+                None => self
+                    .selection_model
+                    .set_selected(gtk::INVALID_LIST_POSITION),
+            }
+        } else {
+            self.update(message, sender.clone(), root);
+        }
+        self.update_view(widgets, sender);
+    }
+
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, _: &Self::Root) {
         //log::debug!("{:?}", message);
         match message {
             EventListMsg::Display(dem) => {
@@ -172,6 +228,16 @@ impl SimpleComponent for EventListModel {
                         .into(),
                 ));
             }
+            EventListMsg::Enqueue => {
+                if let Some(event) = self
+                    .selection_model
+                    .selected_item()
+                    .and_downcast_ref::<EventObject>()
+                {
+                    let _ = sender.output(EventListOut::Enqueue(event.into()));
+                }
+            }
+            EventListMsg::GameTick(_) => {}
             EventListMsg::Event(event, edit) => {
                 if edit {
                     self.list_model.remove(self.selection_model.selected());
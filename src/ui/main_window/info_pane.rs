@@ -7,7 +7,10 @@ use relm4::prelude::*;
 use super::event_list::EventListModel;
 use crate::demo_manager::Demo;
 use crate::demo_manager::Event;
+use crate::highlights;
+use crate::recorder::{Clip, RecordOptions};
 use crate::settings::Settings;
+use crate::ui::util;
 
 use super::controls::ControlsModel;
 use super::controls::ControlsMsg;
@@ -29,6 +32,8 @@ pub enum InfoPaneOut {
     Save(Demo),
 
     Update(Demo),
+    Record(Clip, RecordOptions),
+    Enqueue(Box<Demo>, Event),
 }
 
 #[derive(Debug)]
@@ -42,10 +47,23 @@ pub enum InfoPaneMsg {
     Rcon(RconAction),
 
     PlayheadMoved(u32),
+    /// Playback position of the game, while following it
+    GameTick(u32),
     AddEvent,
     EditEvent(Event),
+    DetectHighlights,
+    AddHighlights(Vec<Event>),
 
     DemoInspected(Demo),
+    Record(Clip, RecordOptions),
+    Enqueue(Event),
+}
+
+#[derive(Debug)]
+pub enum InfoPaneCmd {
+    /// The analysed demo and the highlights found, `None` if there is no recording player
+    Highlights(Box<Demo>, Option<Vec<Event>>),
+    Error(String),
 }
 
 pub struct InfoPaneModel {
@@ -54,6 +72,8 @@ pub struct InfoPaneModel {
     event_list: Controller<EventListModel>,
     event_dialog: Controller<EventDialogModel>,
 
+    window: adw::Window,
+    settings: Rc<RefCell<Settings>>,
     demo: Option<Demo>,
     playhead_tick: u32,
 }
@@ -63,7 +83,7 @@ impl Component for InfoPaneModel {
     type Init = (adw::Window, Rc<RefCell<Settings>>);
     type Input = InfoPaneMsg;
     type Output = InfoPaneOut;
-    type CommandOutput = ();
+    type CommandOutput = InfoPaneCmd;
 
     view! {
         gtk::Box{
@@ -105,6 +125,7 @@ impl Component for InfoPaneModel {
                     ControlsOut::SaveChanges => InfoPaneMsg::SaveChanges,
                     ControlsOut::DiscardChanges => InfoPaneMsg::DiscardChanges,
                     ControlsOut::PlayheadMoved(tick) => InfoPaneMsg::PlayheadMoved(tick),
+                    ControlsOut::Record(clip, options) => InfoPaneMsg::Record(clip, options),
                 });
 
         let infobox = DemoInfoboxModel::builder().launch(()).forward(
@@ -121,11 +142,13 @@ impl Component for InfoPaneModel {
                 EventListOut::PlayheadTo(tick) => InfoPaneMsg::PlayheadTo(tick),
                 EventListOut::AddEvent => InfoPaneMsg::AddEvent,
                 EventListOut::EditEvent(event) => InfoPaneMsg::EditEvent(event),
+                EventListOut::DetectHighlights => InfoPaneMsg::DetectHighlights,
+                EventListOut::Enqueue(event) => InfoPaneMsg::Enqueue(event),
                 EventListOut::Dirty => InfoPaneMsg::Edited(true),
             },
         );
 
-        let event_dialog = EventDialogModel::builder().launch(init.0).forward(
+        let event_dialog = EventDialogModel::builder().launch(init.0.clone()).forward(
             event_list.sender(),
             |msg| match msg {
                 EventDialogOut::Save(event, edit) => EventListMsg::Event(event, edit),
@@ -133,6 +156,8 @@ impl Component for InfoPaneModel {
         );
 
         let model = InfoPaneModel {
+            window: init.0,
+            settings: init.1,
             demo: None,
             controls,
             infobox,
@@ -167,6 +192,8 @@ impl Component for InfoPaneModel {
             InfoPaneMsg::SaveChanges => {
                 let mut demo = self.demo.clone().unwrap();
                 demo.notes = self.infobox.model().notes.clone();
+                demo.tags = self.infobox.model().tags.clone();
+                demo.rating = self.infobox.model().rating;
                 demo.events = self.event_list.model().events();
                 let _ = sender.output(InfoPaneOut::Save(demo));
             }
@@ -174,6 +201,10 @@ impl Component for InfoPaneModel {
                 sender.input(InfoPaneMsg::Display(self.demo.clone(), true));
             }
             InfoPaneMsg::PlayheadMoved(tick) => self.playhead_tick = tick,
+            InfoPaneMsg::GameTick(tick) => {
+                self.controls.emit(ControlsMsg::GameTick(tick));
+                self.event_list.emit(EventListMsg::GameTick(tick));
+            }
             InfoPaneMsg::AddEvent => {
                 let mut event = Event::default();
                 event.ev_type = "Bookmark".to_owned();
@@ -200,16 +231,104 @@ impl Component for InfoPaneModel {
                     .map_or(u32::MAX, |h| h.ticks);
                 self.event_dialog.emit(EventDialogMsg::Show(params))
             }
+            InfoPaneMsg::Record(clip, options) => {
+                let _ = sender.output(InfoPaneOut::Record(clip, options));
+            }
+            InfoPaneMsg::Enqueue(event) => {
+                if let Some(demo) = &self.demo {
+                    let _ = sender.output(InfoPaneOut::Enqueue(Box::new(demo.clone()), event));
+                }
+            }
+            InfoPaneMsg::DetectHighlights => {
+                let Some(mut demo) = self.demo.clone() else {
+                    return;
+                };
+                let options = self.settings.borrow().highlights.clone();
+                sender.oneshot_command(async move {
+                    let state = match demo.inspection.clone() {
+                        Some(state) => state,
+                        None => match demo.full_analysis().await {
+                            Ok(state) => state,
+                            Err(e) => return InfoPaneCmd::Error(e.to_string()),
+                        },
+                    };
+                    let found = highlights::recording_user(&demo, &state)
+                        .map(|user| highlights::detect(&state, user, demo.tps(), &options));
+                    InfoPaneCmd::Highlights(Box::new(demo), found)
+                });
+            }
+            InfoPaneMsg::AddHighlights(events) => {
+                for event in events {
+                    self.event_list.emit(EventListMsg::Event(event, false));
+                }
+            }
             InfoPaneMsg::DemoInspected(dem) => {
-                if self
-                    .demo
-                    .as_ref()
-                    .map_or(false, |d| d.filename == dem.filename)
-                {
+                if self.demo.as_ref().is_some_and(|d| d.path == dem.path) {
                     self.demo = Some(dem.clone());
                 }
                 let _ = sender.output(InfoPaneOut::Update(dem));
             }
         }
     }
+
+    fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        sender: ComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            InfoPaneCmd::Error(e) => {
+                util::notice_dialog(&self.window, "Failed to analyse demo", &e);
+            }
+            InfoPaneCmd::Highlights(_, None) => {
+                util::notice_dialog(
+                    &self.window,
+                    "No recording player",
+                    "Highlights can only be detected in POV demos",
+                );
+            }
+            InfoPaneCmd::Highlights(demo, Some(found)) => {
+                let tps = demo.tps();
+                sender.input(InfoPaneMsg::DemoInspected(*demo));
+                let existing = self.event_list.model().events();
+                let found: Vec<Event> = found
+                    .into_iter()
+                    .filter(|e| !existing.contains(e))
+                    .collect();
+                if found.is_empty() {
+                    util::notice_dialog(&self.window, "No new highlights found", "");
+                    return;
+                }
+
+                let mut body: Vec<String> = found
+                    .iter()
+                    .take(15)
+                    .map(|e| {
+                        format!(
+                            "{} {}",
+                            crate::util::ticks_to_timestamp(e.tick, tps),
+                            e.title
+                        )
+                    })
+                    .collect();
+                if found.len() > 15 {
+                    body.push(format!("and {} more", found.len() - 15));
+                }
+                let window = self.window.clone();
+                relm4::spawn_local(async move {
+                    if util::confirm_dialog(
+                        &window,
+                        &format!("Add {} highlights as bookmarks?", found.len()),
+                        &body.join("\n"),
+                        "Add",
+                    )
+                    .await
+                    {
+                        sender.input(InfoPaneMsg::AddHighlights(found));
+                    }
+                });
+            }
+        }
+    }
 }
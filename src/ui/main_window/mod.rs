@@ -1,4 +1,6 @@
 use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
@@ -8,12 +10,17 @@ use relm4::actions::RelmActionGroup;
 use relm4::prelude::*;
 
 use crate::demo_manager::Event;
+use crate::recorder::{Clip, RecordOptions, RecordStatus, Recorder};
+use crate::search::SearchIndex;
 use crate::ui::about_window::AboutMsg;
+use crate::ui::render_queue_window::{RenderQueueModel, RenderQueueMsg, RenderQueueOut};
+use crate::ui::search_window::{SearchModel, SearchMsg, SearchOut};
 use crate::ui::settings_window::*;
 use crate::ui::util;
+use crate::watcher::DemoWatcher;
 use crate::{
     demo_manager::{Demo, DemoManager},
-    rcon_manager::RconManager,
+    rcon_manager::{Command, ConnectionState, RconManager},
     settings::Settings,
 };
 use demo_list::*;
@@ -31,39 +38,67 @@ mod event_dialog;
 mod event_list;
 mod event_object;
 mod info_pane;
+mod record_dialog;
+
+/// How often the playback position is read from the game while following it
+const GAME_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// Changes on disk are collected this long before the list is updated, also how often demos that
+/// are being recorded are reread
+const FILE_CHANGE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum RconAction {
-    Play(String),
+    Play(PathBuf),
     GotoTick(u32),
     GotoEvent(Event),
     Stop,
+    TogglePause,
+    Speed(f32),
+    /// Skips the amount of ticks and pauses
+    Step(i32),
+    /// Repeats playback between the two ticks, `None` to stop looping
+    Loop(Option<(u32, u32)>),
+    /// Console commands of an expanded macro
+    Macro(String),
+    /// Spectates the player, after jumping to the tick like to an event if there is one
+    Spectate(Option<u32>, String),
 }
 
 #[derive(Debug)]
 pub enum DemoPlayerMsg {
     OpenSettings,
-    SettingsClosed(Settings),
+    SettingsClosed(Box<Settings>),
     ShowAbout,
+    OpenSearch,
+    OpenRenderQueue,
+    SelectRconProfile(usize),
+    /// Opens the folder of a demo and selects it, unless it is in the open library
+    ShowDemo(PathBuf),
 
     DeleteSelected,
     DeleteUnfinished,
     DeleteUnmarked,
     CleanReplays,
 
-    OpenFolder(Option<std::path::PathBuf>, bool),
+    OpenFolder(Option<PathBuf>, bool),
+    OpenLibrary(bool),
     SelectFolder,
     ReloadFolder,
     ShowSidebar,
     FavoriteFolder,
+    /// Adds the tag to the filter of the demo list or removes it
+    ToggleTag(String),
 
     DemosChanged(bool),
 
     Rcon(RconAction),
-    PlayDemoDblclck(String),
-    DemoSelected(Option<String>, bool),
+    PlayDemoDblclck(PathBuf),
+    DemoSelected(Option<PathBuf>, bool),
     DemoSave(Demo),
     DemoUpdate(Demo),
+    RecordClip(Clip, RecordOptions),
+    Enqueue(Box<Demo>, Event),
+    QueueStatus(Option<RecordStatus>),
 }
 
 relm4::new_action_group!(AppMenu, "app-menu");
@@ -73,20 +108,53 @@ relm4::new_stateless_action!(CleanReplaysAction, AppMenu, "clean-replays");
 
 #[derive(Debug)]
 pub enum DemoPlayerCmd {
-    Progress(usize, usize),
-    Done(std::path::PathBuf, bool),
+    /// Number of read demos, the total and the demos read since the last progress update
+    Progress(usize, usize, Vec<Demo>),
+    /// The folder that was opened, `None` for the library
+    Done(Option<PathBuf>, bool),
+    RecordStatus(RecordStatus),
+    Recorded(Result<Vec<std::path::PathBuf>, String>),
+    RconState(ConnectionState),
+    PollGame,
+    /// Current tick of the demo playing in the game, `None` if it couldn't be read
+    GameTick(Option<u32>),
+    /// A file or folder in the open demo folders changed
+    FileChanged(PathBuf),
+    ApplyFileChanges,
 }
 
 pub struct DemoPlayerModel {
     demo_manager: Arc<Mutex<DemoManager>>,
     rcon_manager: RconManager,
+    rcon_state: ConnectionState,
     settings: Rc<RefCell<Settings>>,
+    /// Demo last started in the game, its position is only followed while it is selected
+    playing_demo: Option<PathBuf>,
+    game_tick: Option<u32>,
+    polling_game: bool,
+    loop_range: Option<(u32, u32)>,
 
     selected_demo: Option<Demo>,
+    /// Demo to select once the folder finished loading
+    pending_selection: Option<PathBuf>,
     loading: Option<(usize, usize)>,
+    /// Demos are added to the list while loading, instead of updating it once done
+    streaming: bool,
+    /// Watches the open folders while they aren't loading
+    watcher: Option<DemoWatcher>,
+    changed_files: HashSet<PathBuf>,
+    changes_scheduled: bool,
+    recording: Option<RecordStatus>,
+    search_index: Arc<Mutex<SearchIndex>>,
+    /// Tags of the listed demos and how many demos have them
+    tags: BTreeMap<String, usize>,
+    /// Tags a demo needs to be listed
+    tag_filter: BTreeSet<String>,
 
     preferences_wnd: Option<Controller<PreferencesModel>>,
     about_wnd: Controller<AboutModel>,
+    search_wnd: Controller<SearchModel>,
+    render_queue_wnd: Controller<RenderQueueModel>,
 
     demo_list: Controller<DemoListModel>,
     demo_details: Controller<InfoPaneModel>,
@@ -113,7 +181,11 @@ impl AsyncComponent for DemoPlayerModel {
                     set_title_widget = &adw::WindowTitle{
                         set_title: "Demo Player",
                         #[watch]
-                        set_subtitle: model.settings.borrow().demo_folder_path.as_ref().map_or("(unset)", |p|p.to_str().unwrap()),
+                        set_subtitle: &if model.settings.borrow().library_open {
+                            "Library".to_owned()
+                        } else {
+                            model.settings.borrow().demo_folder_path.as_ref().map_or("(unset)".to_owned(), |p| p.display().to_string())
+                        },
                     },
 
                     pack_start = &gtk::Button{
@@ -121,6 +193,27 @@ impl AsyncComponent for DemoPlayerModel {
                         connect_clicked => DemoPlayerMsg::ShowSidebar,
                     },
 
+                    pack_start = &gtk::Image{
+                        #[watch]
+                        set_icon_name: Some(match model.rcon_state {
+                            ConnectionState::Connected => "network-transmit-receive-symbolic",
+                            ConnectionState::Connecting => "network-wired-acquiring-symbolic",
+                            ConnectionState::Disconnected => "network-offline-symbolic",
+                            ConnectionState::AuthFailed => "dialog-password-symbolic",
+                        }),
+                        #[watch]
+                        set_tooltip_text: Some(&format!("TF2 RCon: {}", model.rcon_state)),
+                    },
+
+                    #[name = "profile_button"]
+                    pack_start = &gtk::MenuButton{
+                        set_tooltip_text: Some("RCon profile"),
+                        #[watch]
+                        set_label: &model.settings.borrow().rcon().name,
+                        #[watch]
+                        set_visible: model.settings.borrow().rcon_profiles.len() > 1,
+                    },
+
                     pack_end = &adw::SplitButton{
                         #[watch]
                         set_sensitive: model.loading.is_none(),
@@ -134,8 +227,35 @@ impl AsyncComponent for DemoPlayerModel {
                         #[watch]
                         set_sensitive: model.loading.is_none(),
                         set_icon_name: "view-refresh-symbolic",
-                        set_tooltip_text: Some("Reload demo folder"),
+                        set_tooltip_text: Some("Reload demos"),
                         connect_clicked => DemoPlayerMsg::ReloadFolder,
+                    },
+
+                    pack_end = &gtk::Spinner{
+                        #[watch]
+                        set_visible: model.recording.is_some(),
+                        #[watch]
+                        set_spinning: model.recording.is_some(),
+                        #[watch]
+                        set_tooltip_text: Some(&match model.recording {
+                            Some(RecordStatus::Loading) => "Loading demo".to_owned(),
+                            Some(RecordStatus::Recording(size)) => format!("Recording clip ({} MB)", size / 1_000_000),
+                            Some(RecordStatus::Collecting) => "Collecting recorded files".to_owned(),
+                            Some(RecordStatus::Processing(progress)) => format!("Processing with ffmpeg ({:.0}%)", progress * 100.0),
+                            None => String::new(),
+                        }),
+                    },
+
+                    pack_end = &gtk::Button{
+                        set_icon_name: "view-list-symbolic",
+                        set_tooltip_text: Some("Render queue"),
+                        connect_clicked => DemoPlayerMsg::OpenRenderQueue,
+                    },
+
+                    pack_end = &gtk::Button{
+                        set_icon_name: "system-search-symbolic",
+                        set_tooltip_text: Some("Search analysed demos"),
+                        connect_clicked => DemoPlayerMsg::OpenSearch,
                     }
                 },
                 #[wrap(Some)]
@@ -158,6 +278,17 @@ impl AsyncComponent for DemoPlayerModel {
                                 set_tooltip_text: Some("Open folder"),
                             },
                             gtk::Button{
+                                #[watch]
+                                set_sensitive: model.loading.is_none(),
+                                add_css_class: "flat",
+                                add_css_class: "circular",
+                                set_icon_name: relm4_icons::icon_names::LIBRARY,
+                                connect_clicked => DemoPlayerMsg::OpenLibrary(true),
+                                set_tooltip_text: Some("Open library, its folders are set up in the settings"),
+                            },
+                            gtk::Button{
+                                #[watch]
+                                set_sensitive: !model.settings.borrow().library_open,
                                 #[watch]
                                 set_icon_name: if model.settings.borrow().favorited() {relm4_icons::icon_names::STAR_LARGE} else {relm4_icons::icon_names::STAR_OUTLINE_ROUNDED},
                                 add_css_class: "flat",
@@ -193,6 +324,45 @@ impl AsyncComponent for DemoPlayerModel {
                                 b
                             }),
                         },
+                        gtk::Separator{
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_margin_top: 5,
+                            set_margin_bottom: 5,
+                            #[watch]
+                            set_visible: !model.tags.is_empty(),
+                        },
+                        gtk::ScrolledWindow{
+                            #[watch]
+                            set_visible: !model.tags.is_empty(),
+                            set_hscrollbar_policy: gtk::PolicyType::Never,
+                            set_propagate_natural_height: true,
+                            set_max_content_height: 200,
+                            #[watch]
+                            set_child: Some(&{
+                                let b = gtk::FlowBox::builder()
+                                    .selection_mode(gtk::SelectionMode::None)
+                                    .column_spacing(5)
+                                    .row_spacing(5)
+                                    .build();
+
+                                for (tag, count) in &model.tags {
+                                    let bu = gtk::ToggleButton::builder()
+                                        .label(format!("{tag} ({count})"))
+                                        .active(model.tag_filter.contains(tag))
+                                        .tooltip_text("Only list demos with this tag")
+                                        .css_classes(["flat", "caption"])
+                                        .build();
+                                    let tag = tag.clone();
+                                    let sender = sender.clone();
+                                    bu.connect_clicked(move |_|{
+                                        sender.input(DemoPlayerMsg::ToggleTag(tag.clone()));
+                                    });
+                                    b.append(&bu);
+                                }
+
+                                b
+                            }),
+                        },
                         gtk::Separator{
                             set_orientation: gtk::Orientation::Horizontal,
                             set_margin_top: 5,
@@ -230,27 +400,20 @@ impl AsyncComponent for DemoPlayerModel {
                             #[wrap(Some)]
                             set_child = model.demo_list.widget(),
                             add_overlay = &gtk::Box{
-                                set_hexpand: true,
-                                set_vexpand: true,
-                                add_css_class: "view",
+                                set_halign: gtk::Align::Center,
+                                set_valign: gtk::Align::End,
+                                set_margin_all: 12,
+                                set_spacing: 6,
+                                add_css_class: "osd",
+                                add_css_class: "toolbar",
                                 #[watch]
                                 set_visible: model.loading.is_some(),
-                                gtk::Box{
-                                    set_halign: gtk::Align::Center,
-                                    set_valign: gtk::Align::Center,
-                                    set_hexpand: true,
-                                    set_vexpand: true,
-                                    set_orientation: gtk::Orientation::Vertical,
-                                    gtk::Spinner{
-                                       set_spinning: true,
-                                    },
-                                    gtk::Label{
-                                        set_label: "Loading demos",
-                                    },
-                                    gtk::Label{
-                                        #[watch]
-                                        set_label: &format!("{}/{}", model.loading.map_or(0, |l|l.0), model.loading.map_or(0, |l|l.1))
-                                    }
+                                gtk::Spinner{
+                                   set_spinning: true,
+                                },
+                                gtk::Label{
+                                    #[watch]
+                                    set_label: &format!("Loading demos {}/{}", model.loading.map_or(0, |l|l.0), model.loading.map_or(0, |l|l.1))
                                 }
                             }
                         },
@@ -266,7 +429,7 @@ impl AsyncComponent for DemoPlayerModel {
     menu! {
         delete_menu: {
             "Delete 0s demos" => DeleteUnfinishedAction,
-            "Delete demos without bookmarks" => DeleteUnmarkedAction,
+            "Delete demos without bookmarks, notes, tags or rating" => DeleteUnmarkedAction,
             "Clean replays" => CleanReplaysAction,
         }
     }
@@ -291,26 +454,101 @@ impl AsyncComponent for DemoPlayerModel {
                 InfoPaneOut::Rcon(act) => DemoPlayerMsg::Rcon(act),
                 InfoPaneOut::Save(demo) => DemoPlayerMsg::DemoSave(demo),
                 InfoPaneOut::Update(demo) => DemoPlayerMsg::DemoUpdate(demo),
+                InfoPaneOut::Record(clip, options) => DemoPlayerMsg::RecordClip(clip, options),
+                InfoPaneOut::Enqueue(demo, event) => DemoPlayerMsg::Enqueue(demo, event),
             });
 
         let about_wnd = AboutModel::builder().launch(root.clone()).detach();
 
+        let search_index = Arc::new(Mutex::new(SearchIndex::load()));
+        let search_wnd = SearchModel::builder()
+            .launch((root.clone(), search_index.clone(), settings.clone()))
+            .forward(sender.input_sender(), |msg| match msg {
+                SearchOut::Open(path) => DemoPlayerMsg::ShowDemo(path),
+            });
+
+        let render_queue_wnd = RenderQueueModel::builder()
+            .launch((root.clone(), settings.clone()))
+            .forward(sender.input_sender(), |msg| match msg {
+                RenderQueueOut::Status(status) => DemoPlayerMsg::QueueStatus(status),
+            });
+
         let model = {
             let settings_clone = settings.borrow().clone();
             Self {
                 demo_manager: Arc::new(Mutex::new(DemoManager::new())),
-                rcon_manager: RconManager::new(&settings_clone.rcon_pw, settings_clone.rcon_port),
+                rcon_manager: RconManager::new(&settings_clone.rcon()),
+                rcon_state: ConnectionState::Disconnected,
                 settings,
+                playing_demo: None,
+                game_tick: None,
+                polling_game: false,
+                loop_range: None,
                 preferences_wnd: None,
                 about_wnd,
+                search_wnd,
+                render_queue_wnd,
+                search_index,
                 demo_list,
                 demo_details,
                 selected_demo: None,
+                pending_selection: None,
                 loading: None,
+                streaming: false,
+                watcher: None,
+                changed_files: HashSet::new(),
+                changes_scheduled: false,
+                recording: None,
+                tags: BTreeMap::new(),
+                tag_filter: BTreeSet::new(),
             }
         };
 
         let widgets = view_output!();
+        model.watch_rcon(&sender);
+
+        // Built on every click, so it always lists the profiles from the current settings
+        let settings = model.settings.clone();
+        let profile_sender = sender.input_sender().clone();
+        widgets.profile_button.set_create_popup_func(move |btn| {
+            let list = gtk::Box::new(gtk::Orientation::Vertical, 5);
+            let popover = gtk::Popover::builder().child(&list).build();
+            let settings = settings.borrow();
+            let mut group = None::<gtk::CheckButton>;
+            for (i, profile) in settings.rcon_profiles.iter().enumerate() {
+                let check = gtk::CheckButton::builder()
+                    .label(format!(
+                        "{} ({}:{})",
+                        profile.name, profile.host, profile.port
+                    ))
+                    .active(i == settings.rcon_profile)
+                    .build();
+                check.set_group(group.as_ref());
+                group.get_or_insert(check.clone());
+                let (popover, sender) = (popover.clone(), profile_sender.clone());
+                check.connect_toggled(move |c| {
+                    if c.is_active() {
+                        popover.popdown();
+                        sender.emit(DemoPlayerMsg::SelectRconProfile(i));
+                    }
+                });
+                list.append(&check);
+            }
+            btn.set_popover(Some(&popover));
+        });
+
+        sender.command(|out, shutdown| {
+            shutdown
+                .register(async move {
+                    loop {
+                        async_std::task::sleep(GAME_POLL_INTERVAL).await;
+                        if out.send(DemoPlayerCmd::PollGame).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .drop_on_shutdown()
+        });
 
         #[cfg(debug_assertions)]
         widgets.main_window.add_css_class("devel");
@@ -356,10 +594,14 @@ impl AsyncComponent for DemoPlayerModel {
             );
         }
 
-        sender.input(DemoPlayerMsg::OpenFolder(
-            model.settings.borrow().demo_folder_path.clone(),
-            true,
-        ));
+        if model.settings.borrow().library_open {
+            sender.input(DemoPlayerMsg::OpenLibrary(true));
+        } else {
+            sender.input(DemoPlayerMsg::OpenFolder(
+                model.settings.borrow().demo_folder_path.clone(),
+                true,
+            ));
+        }
 
         AsyncComponentParts { model, widgets }
     }
@@ -420,7 +662,7 @@ impl AsyncComponent for DemoPlayerModel {
                     PreferencesModel::builder()
                         .launch((self.settings.borrow().clone(), root.clone()))
                         .forward(sender.input_sender(), |po| match po {
-                            PreferencesOut::Save(s) => DemoPlayerMsg::SettingsClosed(s),
+                            PreferencesOut::Save(s) => DemoPlayerMsg::SettingsClosed(Box::new(s)),
                         }),
                 );
                 self.preferences_wnd
@@ -429,11 +671,26 @@ impl AsyncComponent for DemoPlayerModel {
                     .emit(PreferencesMsg::Show);
             }
             DemoPlayerMsg::SettingsClosed(settings) => {
-                self.settings.replace(settings);
-                let settings_ref = self.settings.borrow();
-                self.rcon_manager = RconManager::new(&settings_ref.rcon_pw, settings_ref.rcon_port);
+                let old = self.settings.replace(*settings);
+                if old.library != self.settings.borrow().library && old.library_open {
+                    sender.input(DemoPlayerMsg::OpenLibrary(false));
+                }
+                self.rcon_manager = RconManager::new(&self.settings.borrow().rcon());
+                self.watch_rcon(&sender);
                 self.preferences_wnd.take();
             }
+            DemoPlayerMsg::SelectRconProfile(index) => {
+                if index != self.settings.borrow().rcon_profile {
+                    let mut settings = self.settings.borrow_mut();
+                    settings.rcon_profile = index;
+                    settings.save();
+                    self.rcon_manager = RconManager::new(&settings.rcon());
+                    self.playing_demo = None;
+                    self.loop_range = None;
+                    drop(settings);
+                    self.watch_rcon(&sender);
+                }
+            }
             DemoPlayerMsg::ShowSidebar => {
                 widgets
                     .sidebar
@@ -448,44 +705,71 @@ impl AsyncComponent for DemoPlayerModel {
                 }
             }
             DemoPlayerMsg::OpenFolder(path, scroll_up) => match path {
-                None => self.demo_manager.lock().unwrap().clear(),
+                None => {
+                    self.watcher = None;
+                    self.demo_manager.lock().unwrap().clear();
+                }
                 Some(path) => {
                     let dm = self.demo_manager.clone();
-                    self.loading = Some((0, 0));
+                    self.start_loading(std::path::absolute(&path).into_iter().collect(), scroll_up);
                     sender.spawn_command(move |s| {
                         if path.exists() {
-                            dm.lock().unwrap().load_demos(&path, |current, total| {
-                                s.emit(DemoPlayerCmd::Progress(current, total))
-                            });
+                            let res =
+                                DemoManager::load_demos(&dm, &path, |current, total, demos| {
+                                    s.emit(DemoPlayerCmd::Progress(current, total, demos))
+                                });
+                            if let Err(e) = res {
+                                log::warn!("Couldn't load {}, {e:?}", path.display());
+                            }
                         } else {
                             dm.lock().unwrap().clear();
                         }
-                        s.emit(DemoPlayerCmd::Done(path, scroll_up))
+                        s.emit(DemoPlayerCmd::Done(Some(path), scroll_up))
                     });
                 }
             },
+            DemoPlayerMsg::OpenLibrary(scroll_up) => {
+                let dm = self.demo_manager.clone();
+                let library = self.settings.borrow().library.clone();
+                self.start_loading(library.roots(), scroll_up);
+                sender.spawn_command(move |s| {
+                    DemoManager::load_library(&dm, &library, |current, total, demos| {
+                        s.emit(DemoPlayerCmd::Progress(current, total, demos))
+                    });
+                    s.emit(DemoPlayerCmd::Done(None, scroll_up))
+                });
+            }
             DemoPlayerMsg::ReloadFolder => {
-                sender.input(DemoPlayerMsg::OpenFolder(
-                    self.settings.borrow().demo_folder_path.clone(),
-                    false,
-                ));
+                if self.settings.borrow().library_open {
+                    sender.input(DemoPlayerMsg::OpenLibrary(false));
+                } else {
+                    sender.input(DemoPlayerMsg::OpenFolder(
+                        self.settings.borrow().demo_folder_path.clone(),
+                        false,
+                    ));
+                }
             }
-            DemoPlayerMsg::DemoSelected(opt_name, reselected) => {
+            DemoPlayerMsg::DemoSelected(opt_path, reselected) => {
                 let mut demo = None::<Demo>;
-                if let Some(name) = opt_name {
-                    demo = self.demo_manager.lock().unwrap().get_demo(&name).cloned();
+                if let Some(path) = opt_path {
+                    demo = self.demo_manager.lock().unwrap().get_demo(&path).cloned();
                 }
                 self.demo_details
                     .emit(InfoPaneMsg::Display(demo.clone(), reselected));
                 self.selected_demo = demo;
+                self.game_tick = None;
             }
             DemoPlayerMsg::Rcon(act) => {
                 // TODO: show status in UI
                 match act {
-                    RconAction::Play(name) => {
-                        let dm = self.demo_manager.lock().unwrap();
-                        let demo = dm.get_demo(&name).unwrap();
-                        let _ = self.rcon_manager.play_demo(demo).await;
+                    RconAction::Play(path) => {
+                        let demo = self.demo_manager.lock().unwrap().get_demo(&path).cloned();
+                        if let Some(demo) = demo {
+                            let _ = self.rcon_manager.play_demo(&demo).await;
+                            self.playing_demo = Some(path);
+                            self.game_tick = None;
+                            self.loop_range = None;
+                        }
                     }
                     RconAction::GotoTick(tick) => {
                         let _ = self
@@ -496,32 +780,81 @@ impl AsyncComponent for DemoPlayerModel {
                     RconAction::GotoEvent(ev) => {
                         let _ = self
                             .rcon_manager
-                            .skip_to_tick(
-                                (ev.tick
-                                    - (self.settings.borrow().event_skip_predelay
-                                        * self.selected_demo.as_ref().unwrap().tps())
-                                    .round() as u32)
-                                    .clamp(
-                                        0,
-                                        self.selected_demo
-                                            .as_ref()
-                                            .unwrap()
-                                            .header
-                                            .as_ref()
-                                            .map_or(0, |h| h.ticks),
-                                    ),
-                                true,
-                            )
+                            .skip_to_tick(self.event_seek_tick(ev.tick), true)
                             .await;
+                        let after_seek: Vec<String> = self
+                            .settings
+                            .borrow()
+                            .macros
+                            .iter()
+                            .filter(|m| m.after_seek)
+                            .map(|m| m.expand(ev.tick, self.selected_demo.as_ref()))
+                            .collect();
+                        for commands in after_seek {
+                            let _ = self
+                                .rcon_manager
+                                .send_command(Command::Raw(&commands))
+                                .await;
+                        }
                     }
                     RconAction::Stop => {
                         let _ = self.rcon_manager.stop_playback().await;
+                        self.playing_demo = None;
+                        self.loop_range = None;
+                    }
+                    RconAction::Spectate(tick, player) => {
+                        if let Some(tick) = tick {
+                            let _ = self
+                                .rcon_manager
+                                .skip_to_tick(self.event_seek_tick(tick), true)
+                                .await;
+                        }
+                        let _ = self
+                            .rcon_manager
+                            .send_command(Command::SpectatePlayer(&player))
+                            .await;
+                    }
+                    RconAction::Macro(commands) => {
+                        let _ = self
+                            .rcon_manager
+                            .send_command(Command::Raw(&commands))
+                            .await;
+                    }
+                    RconAction::TogglePause => {
+                        let _ = self
+                            .rcon_manager
+                            .send_command(Command::TogglePlayback())
+                            .await;
+                    }
+                    RconAction::Speed(speed) => {
+                        let _ = self
+                            .rcon_manager
+                            .send_command(Command::SetPlaybackSpeed(speed))
+                            .await;
+                    }
+                    RconAction::Step(ticks) => {
+                        let _ = self
+                            .rcon_manager
+                            .send_command(Command::SkipRelative(ticks, true))
+                            .await;
+                    }
+                    RconAction::Loop(range) => {
+                        self.loop_range = range;
+                        // Playback pauses at the end tick, polling jumps back to the start from there
+                        let end = range.map_or(i32::MAX as u32, |(_, end)| end);
+                        let _ = self
+                            .rcon_manager
+                            .send_command(Command::SetEndTick(end))
+                            .await;
+                        if let Some((start, _)) = range {
+                            let _ = self.rcon_manager.skip_to_tick(start, false).await;
+                        }
                     }
                 }
             }
-            DemoPlayerMsg::PlayDemoDblclck(name) => {
+            DemoPlayerMsg::PlayDemoDblclck(path) => {
                 if self.settings.borrow().doubleclick_play {
-                    sender.input(DemoPlayerMsg::Rcon(RconAction::Play(name)));
+                    sender.input(DemoPlayerMsg::Rcon(RconAction::Play(path)));
                 }
             }
             DemoPlayerMsg::DeleteSelected => {
@@ -534,19 +867,43 @@ impl AsyncComponent for DemoPlayerModel {
                 }
             }
             DemoPlayerMsg::DemosChanged(scroll) => {
+                let dm = self.demo_manager.lock().unwrap();
                 self.demo_list.emit(DemoListMsg::Update(
-                    self.demo_manager.lock().unwrap().get_demos().clone(),
+                    dm.get_demos().clone(),
+                    dm.roots().to_vec(),
                     scroll,
                 ));
+                drop(dm);
+                self.update_tags();
+            }
+            DemoPlayerMsg::ToggleTag(tag) => {
+                if !self.tag_filter.remove(&tag) {
+                    self.tag_filter.insert(tag);
+                }
+                self.demo_list.emit(DemoListMsg::FilterTags(
+                    self.tag_filter.iter().cloned().collect(),
+                ));
             }
             DemoPlayerMsg::DemoSave(demo) => {
-                let name = demo.filename.clone();
+                let path = demo.path.clone();
                 demo.save_json().await;
+                // Notes, tags and rating don't change the file, so the list has to be told
+                self.demo_list.emit(DemoListMsg::Refresh(vec![(
+                    path.clone(),
+                    Some(demo.clone()),
+                )]));
                 self.demo_manager.lock().unwrap().insert(demo).await;
-                sender.input(DemoPlayerMsg::DemoSelected(Some(name), true));
+                sender.input(DemoPlayerMsg::DemoSelected(Some(path), true));
                 sender.input(DemoPlayerMsg::DemosChanged(false));
             }
             DemoPlayerMsg::DemoUpdate(demo) => {
+                if let Some(state) = &demo.inspection {
+                    let mut index = self.search_index.lock().unwrap();
+                    index.add(&demo, state);
+                    if let Err(e) = index.save() {
+                        log::warn!("Failed to save search index: {e:?}");
+                    }
+                }
                 self.demo_manager.lock().unwrap().insert(demo).await;
             }
             DemoPlayerMsg::FavoriteFolder => {
@@ -555,6 +912,66 @@ impl AsyncComponent for DemoPlayerModel {
             DemoPlayerMsg::ShowAbout => {
                 self.about_wnd.emit(AboutMsg::Open);
             }
+            DemoPlayerMsg::RecordClip(clip, options) => {
+                if self.recording.is_some() {
+                    util::notice_dialog(root, "Already recording a clip", "");
+                    return;
+                }
+                let Some(game_folder) = self.settings.borrow().tf_folder_path.clone() else {
+                    util::notice_dialog(
+                        root,
+                        "TF2 folder path not set up",
+                        "Please check your TF2 folder setting",
+                    );
+                    return;
+                };
+                self.settings.borrow_mut().recording = options.clone();
+                self.settings.borrow().save();
+
+                let settings = self.settings.borrow();
+                let rcon = self.rcon_manager.clone();
+                let mut recorder = Recorder::new(&game_folder, options);
+                recorder.postprocess = settings
+                    .ffmpeg
+                    .processor(recorder.options.postprocess.as_deref());
+                self.recording = Some(RecordStatus::Loading);
+                sender.command(move |out, shutdown| {
+                    shutdown
+                        .register(async move {
+                            let res = recorder
+                                .record(&rcon, &clip, |status| {
+                                    let _ = out.send(DemoPlayerCmd::RecordStatus(status));
+                                })
+                                .await;
+                            let _ =
+                                out.send(DemoPlayerCmd::Recorded(res.map_err(|e| e.to_string())));
+                        })
+                        .drop_on_shutdown()
+                });
+            }
+            DemoPlayerMsg::Enqueue(demo, event) => {
+                self.render_queue_wnd.emit(RenderQueueMsg::Add(demo, event));
+            }
+            DemoPlayerMsg::QueueStatus(status) => self.recording = status,
+            DemoPlayerMsg::OpenRenderQueue => {
+                self.render_queue_wnd.emit(RenderQueueMsg::Show);
+            }
+            DemoPlayerMsg::OpenSearch => {
+                self.search_wnd.emit(SearchMsg::Show);
+            }
+            DemoPlayerMsg::ShowDemo(path) => {
+                let Some(folder) = path.parent() else {
+                    return;
+                };
+                let listed = self.settings.borrow().library_open
+                    && self.demo_manager.lock().unwrap().get_demo(&path).is_some();
+                if listed {
+                    self.demo_list.emit(DemoListMsg::Select(path));
+                } else {
+                    self.pending_selection = Some(path.clone());
+                    sender.input(DemoPlayerMsg::OpenFolder(Some(folder.to_owned()), true));
+                }
+            }
         }
         self.update_view(widgets, sender);
     }
@@ -563,19 +980,198 @@ impl AsyncComponent for DemoPlayerModel {
         &mut self,
         message: Self::CommandOutput,
         sender: AsyncComponentSender<Self>,
-        _root: &Self::Root,
+        root: &Self::Root,
     ) {
         match message {
-            DemoPlayerCmd::Progress(current, total) => {
+            DemoPlayerCmd::Progress(current, total, demos) => {
                 self.loading = Some((current, total));
+                if self.streaming {
+                    self.demo_list.emit(DemoListMsg::Append(demos));
+                }
+            }
+            DemoPlayerCmd::RecordStatus(status) => self.recording = Some(status),
+            DemoPlayerCmd::RconState(state) => self.rcon_state = state,
+            DemoPlayerCmd::PollGame => {
+                if self.polling_needed() && !self.polling_game {
+                    self.polling_game = true;
+                    let rcon = self.rcon_manager.clone();
+                    sender.oneshot_command(async move {
+                        DemoPlayerCmd::GameTick(rcon.demo_tick().await.ok().flatten())
+                    });
+                }
+            }
+            DemoPlayerCmd::GameTick(tick) => {
+                self.polling_game = false;
+                if let Some(tick) = tick.filter(|t| Some(*t) != self.game_tick) {
+                    if self.polling_needed() && self.settings.borrow().follow_game {
+                        self.demo_details.emit(InfoPaneMsg::GameTick(tick));
+                    }
+                }
+                self.game_tick = tick;
+                if let (Some(tick), Some((start, end))) = (tick, self.loop_range) {
+                    if tick + 1 >= end {
+                        let _ = self.rcon_manager.skip_to_tick(start, false).await;
+                        let _ = self
+                            .rcon_manager
+                            .send_command(Command::ResumePlayback())
+                            .await;
+                    }
+                }
+            }
+            DemoPlayerCmd::FileChanged(path) => {
+                if self.watcher.is_some() {
+                    self.changed_files.insert(path);
+                    self.schedule_file_changes(&sender);
+                }
+            }
+            DemoPlayerCmd::ApplyFileChanges => {
+                self.changes_scheduled = false;
+                if self.loading.is_some() {
+                    return;
+                }
+                let changed = std::mem::take(&mut self.changed_files);
+                let (changes, save, recording) = {
+                    let mut dm = self.demo_manager.lock().unwrap();
+                    let changes = dm.apply_changes(changed);
+                    let save = (!changes.is_empty()).then(|| dm.save_cache());
+                    (changes, save, dm.recording())
+                };
+                if let Some(save) = save {
+                    save.await;
+                }
+                if !changes.is_empty() {
+                    self.demo_list.emit(DemoListMsg::Refresh(changes));
+                    self.update_tags();
+                }
+                if recording {
+                    self.schedule_file_changes(&sender);
+                }
+            }
+            DemoPlayerCmd::Recorded(res) => {
+                self.recording = None;
+                match res {
+                    Ok(files) => util::notice_dialog(
+                        root,
+                        "Clip recorded",
+                        &gtk::glib::markup_escape_text(
+                            &files
+                                .iter()
+                                .map(|f| f.display().to_string())
+                                .collect::<Vec<_>>()
+                                .join("\n"),
+                        ),
+                    ),
+                    Err(e) => util::notice_dialog(
+                        root,
+                        "Recording failed",
+                        &gtk::glib::markup_escape_text(&e),
+                    ),
+                }
             }
             DemoPlayerCmd::Done(path, scroll_up) => {
-                self.settings.borrow_mut().folder_opened(&path);
+                match path {
+                    Some(path) => self.settings.borrow_mut().folder_opened(&path),
+                    None => self.settings.borrow_mut().library_open = true,
+                }
                 self.settings.borrow().save();
                 self.loading = None;
-                self.demo_details.emit(InfoPaneMsg::Display(None, false));
+                self.streaming = false;
+                self.changed_files.clear();
+                {
+                    let dm = self.demo_manager.lock().unwrap();
+                    let out = sender.command_sender().clone();
+                    self.watcher = DemoWatcher::new(dm.roots(), dm.recursive(), move |path| {
+                        out.emit(DemoPlayerCmd::FileChanged(path))
+                    })
+                    .inspect_err(|e| log::warn!("Couldn't watch demo folders, {e}"))
+                    .ok();
+                    // A demo selected while loading stays selected if it is still there
+                    if self
+                        .selected_demo
+                        .as_ref()
+                        .is_none_or(|d| dm.get_demo(&d.path).is_none())
+                    {
+                        self.demo_details.emit(InfoPaneMsg::Display(None, false));
+                    }
+                    if dm.recording() {
+                        drop(dm);
+                        self.schedule_file_changes(&sender);
+                    }
+                }
                 sender.input(DemoPlayerMsg::DemosChanged(scroll_up));
+                if let Some(path) = self.pending_selection.take() {
+                    self.demo_list.emit(DemoListMsg::Select(path));
+                }
             }
         }
     }
 }
+
+impl DemoPlayerModel {
+    /// Tick to jump to for an event, `event_skip_predelay` seconds before it
+    fn event_seek_tick(&self, tick: u32) -> u32 {
+        let demo = self.selected_demo.as_ref();
+        let predelay =
+            self.settings.borrow().event_skip_predelay * demo.map_or(Demo::TICKRATE, |d| d.tps());
+        let end = demo.and_then(|d| d.header.as_ref()).map_or(0, |h| h.ticks);
+        (tick as i64 - predelay.round() as i64).clamp(0, end as i64) as u32
+    }
+
+    /// Whether the playback position of the game is needed to follow it or to loop, the game has to
+    /// be playing the selected demo
+    fn polling_needed(&self) -> bool {
+        (self.settings.borrow().follow_game || self.loop_range.is_some())
+            && self.rcon_state == ConnectionState::Connected
+            && self.playing_demo.is_some()
+            && self.selected_demo.as_ref().map(|d| &d.path) == self.playing_demo.as_ref()
+    }
+
+    /// Stops watching while demos are loaded from the folders. A newly opened folder is listed while
+    /// loading, a reload keeps the list and updates it once done to keep the selection
+    fn start_loading(&mut self, roots: Vec<PathBuf>, fresh: bool) {
+        self.loading = Some((0, 0));
+        self.watcher = None;
+        self.streaming = fresh;
+        if fresh {
+            self.demo_list.emit(DemoListMsg::Clear(roots));
+        }
+    }
+
+    /// Rereads the tags of the listed demos, tags no demo has anymore are removed from the filter
+    fn update_tags(&mut self) {
+        self.tags = self.demo_manager.lock().unwrap().tags();
+        let count = self.tag_filter.len();
+        self.tag_filter.retain(|t| self.tags.contains_key(t));
+        if self.tag_filter.len() != count {
+            self.demo_list.emit(DemoListMsg::FilterTags(
+                self.tag_filter.iter().cloned().collect(),
+            ));
+        }
+    }
+
+    /// Applies the collected file changes after [`FILE_CHANGE_DELAY`], unless that already is
+    /// scheduled
+    fn schedule_file_changes(&mut self, sender: &AsyncComponentSender<Self>) {
+        if !self.changes_scheduled {
+            self.changes_scheduled = true;
+            sender.oneshot_command(async {
+                async_std::task::sleep(FILE_CHANGE_DELAY).await;
+                DemoPlayerCmd::ApplyFileChanges
+            });
+        }
+    }
+
+    /// Forwards the connection state of the current rcon manager, stops when it is replaced
+    fn watch_rcon(&self, sender: &AsyncComponentSender<Self>) {
+        let states = self.rcon_manager.subscribe();
+        sender.command(move |out, shutdown| {
+            shutdown
+                .register(async move {
+                    while let Ok(state) = states.recv().await {
+                        let _ = out.send(DemoPlayerCmd::RconState(state));
+                    }
+                })
+                .drop_on_shutdown()
+        });
+    }
+}
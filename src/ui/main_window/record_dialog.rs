@@ -0,0 +1,211 @@
+use adw::prelude::*;
+use relm4::prelude::*;
+
+use crate::recorder::{Clip, Codec, RecordOptions};
+
+#[derive(Debug)]
+pub enum RecordDialogOut {
+    Record(Clip, RecordOptions),
+}
+
+#[derive(Debug)]
+pub enum RecordDialogMsg {
+    /// The clip, the last used options and the names of the ffmpeg presets
+    Show(Box<Clip>, RecordOptions, Vec<String>),
+    Record,
+    Cancel,
+
+    Framerate(u32),
+    Codec(u32),
+    Postprocess(u32),
+    SelectFolder,
+    FolderSelected(std::path::PathBuf),
+}
+
+pub struct RecordDialogModel {
+    clip: Option<Clip>,
+    options: RecordOptions,
+    presets: Vec<String>,
+    changed: bool,
+
+    parent: adw::Window,
+}
+
+#[relm4::component(pub)]
+impl Component for RecordDialogModel {
+    type Init = adw::Window;
+    type Input = RecordDialogMsg;
+    type Output = RecordDialogOut;
+    type CommandOutput = ();
+
+    view! {
+        adw::Dialog{
+            set_content_width: 450,
+            #[wrap(Some)]
+            set_child = &adw::ToolbarView {
+                set_margin_start: 10,
+                set_margin_end: 10,
+
+                #[wrap(Some)]
+                set_content = &adw::PreferencesGroup{
+                    set_title: "Record clip",
+                    #[watch]
+                    set_description: model.clip.as_ref().map(|c| format!("{} ({} - {})",
+                        c.name(),
+                        crate::util::ticks_to_timestamp(c.start_tick, c.demo.tps()),
+                        crate::util::ticks_to_timestamp(c.end_tick, c.demo.tps()),
+                    )).as_deref(),
+
+                    #[name="framerate_row"]
+                    adw::SpinRow{
+                        set_title: "Framerate",
+                        #[wrap(Some)]
+                        set_adjustment = &gtk::Adjustment{
+                            set_lower: 1.0,
+                            set_upper: 1000.0,
+                            set_step_increment: 1.0,
+                            set_page_increment: 30.0,
+                            connect_value_changed[sender] => move |adj| {
+                                sender.input(RecordDialogMsg::Framerate(adj.value() as u32));
+                            },
+                        },
+                    },
+
+                    #[name="codec_row"]
+                    adw::ComboRow{
+                        set_title: "Codec",
+                        set_model: Some(&gtk::StringList::new(&Codec::ALL.map(|c| c.name()))),
+                        connect_selected_notify[sender] => move |row| {
+                            sender.input(RecordDialogMsg::Codec(row.selected()));
+                        },
+                    },
+
+                    #[name="postprocess_row"]
+                    adw::ComboRow{
+                        set_title: "Post-processing",
+                        set_subtitle: "ffmpeg preset the recorded files are processed with",
+                        connect_selected_notify[sender] => move |row| {
+                            sender.input(RecordDialogMsg::Postprocess(row.selected()));
+                        },
+                    },
+
+                    adw::ActionRow{
+                        set_title: "Output folder",
+                        #[watch]
+                        set_subtitle: &model.options.output_folder.as_ref().map_or("TF2 folder".to_owned(), |f| f.display().to_string()),
+                        add_suffix = &gtk::Button{
+                            set_icon_name: relm4_icons::icon_names::SEARCH_FOLDER,
+                            set_valign: gtk::Align::Center,
+                            connect_clicked => RecordDialogMsg::SelectFolder,
+                        },
+                    },
+                },
+
+                add_bottom_bar = &gtk::ActionBar{
+                    pack_end = &gtk::Button{
+                        set_label: "Record",
+                        add_css_class: "suggested-action",
+                        connect_clicked => RecordDialogMsg::Record,
+                    },
+                    pack_end = &gtk::Button{
+                        set_label: "Cancel",
+                        connect_clicked => RecordDialogMsg::Cancel,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = RecordDialogModel {
+            clip: None,
+            options: RecordOptions::default(),
+            presets: vec![],
+            changed: false,
+            parent: init,
+        };
+
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        self.changed = false;
+        match message {
+            RecordDialogMsg::Show(clip, options, presets) => {
+                self.clip = Some(*clip);
+                self.options = options;
+                self.presets = presets;
+                self.changed = true;
+                root.present(Some(&self.parent));
+            }
+            RecordDialogMsg::Record => {
+                if let Some(clip) = self.clip.take() {
+                    let _ = sender.output(RecordDialogOut::Record(clip, self.options.clone()));
+                }
+                root.close();
+            }
+            RecordDialogMsg::Cancel => {
+                root.close();
+            }
+            RecordDialogMsg::Framerate(fps) => self.options.framerate = fps,
+            RecordDialogMsg::Codec(ind) => {
+                self.options.codec = Codec::ALL.get(ind as usize).copied().unwrap_or_default();
+            }
+            RecordDialogMsg::Postprocess(ind) => {
+                // The first entry is "None"
+                self.options.postprocess = ind
+                    .checked_sub(1)
+                    .and_then(|i| self.presets.get(i as usize).cloned());
+            }
+            RecordDialogMsg::SelectFolder => {
+                let dia = gtk::FileDialog::builder().build();
+                let sender = sender.clone();
+                dia.select_folder(
+                    Some(&self.parent),
+                    None::<&gtk::gio::Cancellable>,
+                    move |res| {
+                        if let Some(path) = res.ok().and_then(|f| f.path()) {
+                            sender.input(RecordDialogMsg::FolderSelected(path));
+                        }
+                    },
+                );
+            }
+            RecordDialogMsg::FolderSelected(path) => self.options.output_folder = Some(path),
+        }
+        self.update_view(widgets, sender);
+        if self.changed {
+            widgets
+                .framerate_row
+                .set_value(self.options.framerate as f64);
+            widgets.codec_row.set_selected(
+                Codec::ALL
+                    .iter()
+                    .position(|c| *c == self.options.codec)
+                    .unwrap_or_default() as u32,
+            );
+            let postprocess = self.options.postprocess.clone();
+            let mut names = vec!["None"];
+            names.extend(self.presets.iter().map(String::as_str));
+            widgets
+                .postprocess_row
+                .set_model(Some(&gtk::StringList::new(&names)));
+            widgets.postprocess_row.set_selected(
+                postprocess
+                    .and_then(|p| self.presets.iter().position(|n| *n == p))
+                    .map_or(0, |i| i as u32 + 1),
+            );
+        }
+    }
+}
@@ -1,6 +1,8 @@
 mod about_window;
 mod inspection_window;
 mod main_window;
+mod render_queue_window;
+mod search_window;
 mod settings_window;
 mod util;
 pub use main_window::DemoPlayerModel;
@@ -0,0 +1,455 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{atomic::AtomicBool, atomic::Ordering, Arc, Mutex},
+};
+
+use adw::prelude::*;
+use relm4::prelude::*;
+
+use crate::{
+    demo_manager::{Demo, Event},
+    postprocess,
+    rcon_manager::RconManager,
+    recorder::{RecordStatus, Recorder},
+    render_queue::{self, JobStatus, RenderJob, RenderQueue},
+    settings::Settings,
+};
+
+pub struct RenderQueueModel {
+    parent: adw::Window,
+    settings: Rc<RefCell<Settings>>,
+    queue: Arc<Mutex<RenderQueue>>,
+    stop: Arc<AtomicBool>,
+
+    running: bool,
+    joining: bool,
+    /// Job that is being recorded and its status
+    current: Option<(u64, RecordStatus)>,
+}
+
+#[derive(Debug)]
+pub enum RenderQueueMsg {
+    Show,
+    Add(Box<Demo>, Event),
+    Start,
+    Stop,
+    Remove(u64),
+    Retry(u64),
+    ClearFinished,
+    Join,
+    JoinTo(std::path::PathBuf),
+
+    PaddingBefore(f32),
+    PaddingAfter(f32),
+}
+
+#[derive(Debug)]
+pub enum RenderQueueOut {
+    /// Status of the clip being recorded, `None` when the queue stopped
+    Status(Option<RecordStatus>),
+}
+
+#[derive(Debug)]
+pub enum RenderQueueCmd {
+    Status(u64, Option<RecordStatus>),
+    Done(usize),
+    Joined(Result<std::path::PathBuf, String>),
+}
+
+#[relm4::component(pub)]
+impl Component for RenderQueueModel {
+    type Init = (adw::Window, Rc<RefCell<Settings>>);
+    type Input = RenderQueueMsg;
+    type Output = RenderQueueOut;
+    type CommandOutput = RenderQueueCmd;
+
+    view! {
+        adw::Window {
+            set_hide_on_close: true,
+            set_title: Some("Render queue"),
+            set_default_size: (600, 600),
+
+            #[wrap(Some)]
+            set_content = &adw::ToolbarView {
+                add_top_bar = &adw::HeaderBar {
+                    #[wrap(Some)]
+                    set_title_widget = &adw::WindowTitle {
+                        set_title: "Render queue",
+                        #[watch]
+                        set_subtitle: &format!("{} clips pending", model.queue.lock().unwrap().pending()),
+                    },
+
+                    pack_start = &gtk::Button {
+                        set_icon_name: "media-record-symbolic",
+                        set_tooltip_text: Some("Record all pending clips"),
+                        #[watch]
+                        set_visible: !model.running,
+                        connect_clicked => RenderQueueMsg::Start,
+                    },
+
+                    pack_start = &gtk::Button {
+                        set_icon_name: "media-playback-stop-symbolic",
+                        set_tooltip_text: Some("Stop after the current clip"),
+                        #[watch]
+                        set_visible: model.running,
+                        connect_clicked => RenderQueueMsg::Stop,
+                    },
+
+                    pack_end = &gtk::Button {
+                        set_icon_name: relm4_icons::icon_names::VIDEO_CLIP,
+                        set_tooltip_text: Some("Join the recorded clips into one video"),
+                        #[watch]
+                        set_sensitive: !model.joining,
+                        connect_clicked => RenderQueueMsg::Join,
+                    },
+
+                    pack_end = &gtk::Button {
+                        set_icon_name: "edit-clear-all-symbolic",
+                        set_tooltip_text: Some("Remove finished clips"),
+                        connect_clicked => RenderQueueMsg::ClearFinished,
+                    },
+                },
+
+                add_top_bar = &adw::PreferencesGroup {
+                    set_margin_all: 10,
+                    set_description: Some("Seconds recorded around bookmarks added to the queue"),
+
+                    #[name = "before_row"]
+                    adw::SpinRow {
+                        set_title: "Padding before",
+                        set_digits: 1,
+                        #[wrap(Some)]
+                        set_adjustment = &gtk::Adjustment {
+                            set_lower: 0.0,
+                            set_upper: 600.0,
+                            set_step_increment: 1.0,
+                            set_page_increment: 10.0,
+                        },
+                        connect_value_notify[sender] => move |row| {
+                            sender.input(RenderQueueMsg::PaddingBefore(row.value() as f32));
+                        },
+                    },
+                    #[name = "after_row"]
+                    adw::SpinRow {
+                        set_title: "Padding after",
+                        set_digits: 1,
+                        #[wrap(Some)]
+                        set_adjustment = &gtk::Adjustment {
+                            set_lower: 0.0,
+                            set_upper: 600.0,
+                            set_step_increment: 1.0,
+                            set_page_increment: 10.0,
+                        },
+                        connect_value_notify[sender] => move |row| {
+                            sender.input(RenderQueueMsg::PaddingAfter(row.value() as f32));
+                        },
+                    },
+                },
+
+                #[wrap(Some)]
+                set_content = &gtk::ScrolledWindow {
+                    set_vexpand: true,
+
+                    #[name = "job_list"]
+                    gtk::ListBox {
+                        set_margin_all: 10,
+                        set_valign: gtk::Align::Start,
+                        set_selection_mode: gtk::SelectionMode::None,
+                        add_css_class: "boxed-list",
+                    },
+                },
+            },
+        }
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = RenderQueueModel {
+            parent: init.0,
+            settings: init.1,
+            queue: Arc::new(Mutex::new(RenderQueue::load())),
+            stop: Arc::new(AtomicBool::new(false)),
+            running: false,
+            joining: false,
+            current: None,
+        };
+
+        let widgets = view_output!();
+
+        let options = model.settings.borrow().recording.clone();
+        widgets.before_row.set_value(options.padding_before as f64);
+        widgets.after_row.set_value(options.padding_after as f64);
+        model.fill(&widgets.job_list, &sender);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        match message {
+            RenderQueueMsg::Show => {
+                root.set_transient_for(Some(&self.parent));
+                root.present();
+            }
+            RenderQueueMsg::Add(demo, event) => {
+                let options = self.settings.borrow().recording.clone();
+                let job = RenderJob::from_event(
+                    &demo,
+                    &event,
+                    options.padding_before,
+                    options.padding_after,
+                );
+                let mut queue = self.queue.lock().unwrap();
+                queue.push(job);
+                queue.save();
+            }
+            RenderQueueMsg::Start => 'start: {
+                if self.running {
+                    break 'start;
+                }
+                let settings = self.settings.borrow();
+                let Some(game_folder) = settings.tf_folder_path.clone() else {
+                    super::util::notice_dialog(
+                        root,
+                        "TF2 folder path not set up",
+                        "Please check your TF2 folder setting",
+                    );
+                    break 'start;
+                };
+                let rcon = RconManager::new(&settings.rcon());
+                let mut recorder = Recorder::new(&game_folder, settings.recording.clone());
+                recorder.postprocess = settings
+                    .ffmpeg
+                    .processor(recorder.options.postprocess.as_deref());
+                let queue = self.queue.clone();
+                let stop = self.stop.clone();
+                stop.store(false, Ordering::Relaxed);
+                self.running = true;
+                let _ = sender.output(RenderQueueOut::Status(Some(RecordStatus::Loading)));
+                sender.command(move |out, shutdown| {
+                    shutdown
+                        .register(async move {
+                            let recorded =
+                                render_queue::run(&queue, &rcon, &recorder, &stop, |id, s| {
+                                    let _ = out.send(RenderQueueCmd::Status(id, s));
+                                })
+                                .await;
+                            let _ = out.send(RenderQueueCmd::Done(recorded));
+                        })
+                        .drop_on_shutdown()
+                });
+            }
+            RenderQueueMsg::Stop => self.stop.store(true, Ordering::Relaxed),
+            RenderQueueMsg::Remove(id) => {
+                let mut queue = self.queue.lock().unwrap();
+                queue.remove(id);
+                queue.save();
+            }
+            RenderQueueMsg::Retry(id) => {
+                let mut queue = self.queue.lock().unwrap();
+                queue.retry(id);
+                queue.save();
+            }
+            RenderQueueMsg::ClearFinished => {
+                let mut queue = self.queue.lock().unwrap();
+                queue.clear_finished();
+                queue.save();
+            }
+            RenderQueueMsg::Join => {
+                let dia = gtk::FileDialog::builder().initial_name("clips.mp4").build();
+                let sender = sender.clone();
+                dia.save(Some(root), None::<&gtk::gio::Cancellable>, move |res| {
+                    if let Some(path) = res.ok().and_then(|f| f.path()) {
+                        sender.input(RenderQueueMsg::JoinTo(path));
+                    }
+                });
+            }
+            RenderQueueMsg::JoinTo(output) => {
+                // Raw frames and audio can't be joined without encoding them first
+                let clips: Vec<_> = self
+                    .queue
+                    .lock()
+                    .unwrap()
+                    .jobs
+                    .iter()
+                    .filter_map(|j| match &j.status {
+                        JobStatus::Done(files) => Some(files.clone()),
+                        _ => None,
+                    })
+                    .flatten()
+                    .filter(|f| {
+                        f.extension()
+                            .is_some_and(|e| !["tga", "wav"].contains(&&*e.to_string_lossy()))
+                    })
+                    .collect();
+                if clips.is_empty() {
+                    super::util::notice_dialog(
+                        root,
+                        "Nothing to join",
+                        "Only recorded videos can be joined, record the clips with a video codec or an ffmpeg preset",
+                    );
+                    return;
+                }
+                let ffmpeg = self.settings.borrow().ffmpeg.path.clone();
+                self.joining = true;
+                sender.spawn_oneshot_command(move || {
+                    RenderQueueCmd::Joined(
+                        postprocess::concat(&ffmpeg, &clips, &output).map_err(|e| e.to_string()),
+                    )
+                });
+            }
+            RenderQueueMsg::PaddingBefore(secs) => {
+                self.settings.borrow_mut().recording.padding_before = secs;
+                self.settings.borrow().save();
+                return;
+            }
+            RenderQueueMsg::PaddingAfter(secs) => {
+                self.settings.borrow_mut().recording.padding_after = secs;
+                self.settings.borrow().save();
+                return;
+            }
+        }
+        self.fill(&widgets.job_list, &sender);
+        self.update_view(widgets, sender);
+    }
+
+    fn update_cmd_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::CommandOutput,
+        sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        match message {
+            RenderQueueCmd::Status(id, status) => {
+                self.current = status.map(|s| (id, s));
+                let _ = sender.output(RenderQueueOut::Status(
+                    status.or(Some(RecordStatus::Loading)),
+                ));
+            }
+            RenderQueueCmd::Joined(res) => {
+                self.joining = false;
+                match res {
+                    Ok(output) => super::util::notice_dialog(
+                        root,
+                        "Clips joined",
+                        &gtk::glib::markup_escape_text(&output.display().to_string()),
+                    ),
+                    Err(e) => super::util::notice_dialog(
+                        root,
+                        "Joining clips failed",
+                        &gtk::glib::markup_escape_text(&e),
+                    ),
+                }
+            }
+            RenderQueueCmd::Done(recorded) => {
+                self.running = false;
+                self.current = None;
+                let _ = sender.output(RenderQueueOut::Status(None));
+                let failed = self
+                    .queue
+                    .lock()
+                    .unwrap()
+                    .jobs
+                    .iter()
+                    .filter(|j| matches!(j.status, JobStatus::Failed(_)))
+                    .count();
+                super::util::notice_dialog(
+                    root,
+                    "Render queue finished",
+                    &format!("{recorded} clips recorded, {failed} failed"),
+                );
+            }
+        }
+        self.fill(&widgets.job_list, &sender);
+        self.update_view(widgets, sender);
+    }
+}
+
+impl RenderQueueModel {
+    fn fill(&self, list: &gtk::ListBox, sender: &ComponentSender<Self>) {
+        list.remove_all();
+        let queue = self.queue.lock().unwrap();
+        for job in &queue.jobs {
+            let status = match (&job.status, self.current) {
+                (JobStatus::Recording, Some((id, RecordStatus::Recording(size))))
+                    if id == job.id =>
+                {
+                    format!("Recording ({} MB)", size / 1_000_000)
+                }
+                (JobStatus::Recording, Some((id, RecordStatus::Collecting))) if id == job.id => {
+                    "Collecting files".to_owned()
+                }
+                (JobStatus::Recording, Some((id, RecordStatus::Processing(progress))))
+                    if id == job.id =>
+                {
+                    format!("Processing ({:.0}%)", progress * 100.0)
+                }
+                (JobStatus::Recording, _) => "Loading demo".to_owned(),
+                (JobStatus::Pending, _) => "Pending".to_owned(),
+                (JobStatus::Done(files), _) => format!("Done, {} files", files.len()),
+                (JobStatus::Failed(e), _) => format!("Failed: {e}"),
+            };
+            let row = adw::ActionRow::builder()
+                .title(&job.label)
+                .subtitle(format!(
+                    "{} ({} - {})\n{status}",
+                    job.demo
+                        .file_name()
+                        .map_or(String::new(), |n| n.to_string_lossy().into_owned()),
+                    job.start_tick,
+                    job.end_tick
+                ))
+                .use_markup(false)
+                .build();
+
+            if job.status.is_finished() {
+                let retry_button = gtk::Button::builder()
+                    .icon_name("view-refresh-symbolic")
+                    .tooltip_text("Record again")
+                    .valign(gtk::Align::Center)
+                    .css_classes(["flat"])
+                    .build();
+                let id = job.id;
+                let button_sender = sender.clone();
+                retry_button.connect_clicked(move |_| {
+                    button_sender.input(RenderQueueMsg::Retry(id));
+                });
+                row.add_suffix(&retry_button);
+            }
+            if job.status != JobStatus::Recording {
+                let remove_button = gtk::Button::builder()
+                    .icon_name("list-remove-symbolic")
+                    .tooltip_text("Remove from queue")
+                    .valign(gtk::Align::Center)
+                    .css_classes(["flat"])
+                    .build();
+                let id = job.id;
+                let button_sender = sender.clone();
+                remove_button.connect_clicked(move |_| {
+                    button_sender.input(RenderQueueMsg::Remove(id));
+                });
+                row.add_suffix(&remove_button);
+            }
+            list.append(&row);
+        }
+        if queue.jobs.is_empty() {
+            list.append(
+                &gtk::Label::builder()
+                    .label("Add bookmarks with the button in the event list")
+                    .margin_top(10)
+                    .margin_bottom(10)
+                    .build(),
+            );
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use adw::prelude::*;
+use regex::Regex;
+use relm4::prelude::*;
+
+use crate::{
+    search::{self, Query, SearchIndex, SearchResult},
+    settings::Settings,
+};
+
+/// Matches shown per demo, the rest is only counted
+const MAX_MATCHES: usize = 50;
+
+pub struct SearchModel {
+    parent: adw::Window,
+    index: Arc<Mutex<SearchIndex>>,
+    settings: Rc<RefCell<Settings>>,
+
+    results: Vec<SearchResult>,
+    indexed: usize,
+    indexing: Option<(usize, usize)>,
+}
+
+#[derive(Debug)]
+pub enum SearchMsg {
+    Show,
+    Search,
+    IndexLibrary,
+    Open(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum SearchOut {
+    /// Open the folder of the demo and select it
+    Open(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum SearchCmd {
+    Progress(usize, usize),
+    Done(Result<usize, String>),
+}
+
+#[relm4::component(pub)]
+impl Component for SearchModel {
+    type Init = (adw::Window, Arc<Mutex<SearchIndex>>, Rc<RefCell<Settings>>);
+    type Input = SearchMsg;
+    type Output = SearchOut;
+    type CommandOutput = SearchCmd;
+
+    view! {
+        adw::Window {
+            set_hide_on_close: true,
+            set_title: Some("Search demos"),
+            set_default_size: (700, 700),
+
+            #[wrap(Some)]
+            set_content = &adw::ToolbarView {
+                add_top_bar = &adw::HeaderBar {
+                    #[wrap(Some)]
+                    set_title_widget = &adw::WindowTitle {
+                        set_title: "Search demos",
+                        #[watch]
+                        set_subtitle: &match model.indexing {
+                            Some((current, total)) => format!("Indexing {current}/{total}"),
+                            None => format!("{} demos indexed", model.indexed),
+                        },
+                    },
+
+                    pack_start = &gtk::Button {
+                        set_icon_name: "view-refresh-symbolic",
+                        set_tooltip_text: Some("Analyse and index all demos of the library"),
+                        #[watch]
+                        set_sensitive: model.indexing.is_none(),
+                        connect_clicked => SearchMsg::IndexLibrary,
+                    },
+                },
+
+                add_top_bar = &gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 5,
+                    set_margin_all: 10,
+
+                    gtk::Box {
+                        set_spacing: 5,
+                        set_homogeneous: true,
+
+                        #[name = "player_entry"]
+                        gtk::Entry {
+                            set_placeholder_text: Some("Player name or steam ID"),
+                            connect_activate => SearchMsg::Search,
+                        },
+                        #[name = "map_entry"]
+                        gtk::Entry {
+                            set_placeholder_text: Some("Map"),
+                            connect_activate => SearchMsg::Search,
+                        },
+                    },
+                    gtk::Box {
+                        set_spacing: 5,
+                        set_homogeneous: true,
+
+                        #[name = "chat_entry"]
+                        gtk::Entry {
+                            set_placeholder_text: Some("Chat (regex)"),
+                            connect_activate => SearchMsg::Search,
+                        },
+                        #[name = "weapon_entry"]
+                        gtk::Entry {
+                            set_placeholder_text: Some("Kill weapon"),
+                            connect_activate => SearchMsg::Search,
+                        },
+                    },
+                    gtk::Button {
+                        set_label: "Search",
+                        set_halign: gtk::Align::End,
+                        add_css_class: "suggested-action",
+                        connect_clicked => SearchMsg::Search,
+                    },
+                },
+
+                #[wrap(Some)]
+                set_content = &gtk::ScrolledWindow {
+                    set_vexpand: true,
+
+                    #[name = "result_list"]
+                    gtk::ListBox {
+                        set_margin_all: 10,
+                        set_valign: gtk::Align::Start,
+                        set_selection_mode: gtk::SelectionMode::None,
+                        add_css_class: "boxed-list",
+                    },
+                },
+            },
+        }
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = SearchModel {
+            parent: init.0,
+            index: init.1,
+            settings: init.2,
+            results: vec![],
+            indexed: 0,
+            indexing: None,
+        };
+
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        match message {
+            SearchMsg::Show => {
+                self.indexed = self.index.lock().unwrap().len();
+                root.set_transient_for(Some(&self.parent));
+                root.present();
+            }
+            SearchMsg::Search => {
+                let text = |entry: &gtk::Entry| {
+                    Some(entry.text().trim().to_owned()).filter(|t| !t.is_empty())
+                };
+                let chat = match text(&widgets.chat_entry).as_deref().map(Regex::new) {
+                    Some(Err(e)) => {
+                        super::util::notice_dialog(
+                            root,
+                            "Invalid chat regex",
+                            &gtk::glib::markup_escape_text(&e.to_string()),
+                        );
+                        return;
+                    }
+                    chat => chat.and_then(Result::ok),
+                };
+                let query = Query {
+                    player: text(&widgets.player_entry),
+                    map: text(&widgets.map_entry),
+                    chat,
+                    weapon: text(&widgets.weapon_entry),
+                };
+                self.results = if query.is_empty() {
+                    vec![]
+                } else {
+                    self.index.lock().unwrap().search(&query)
+                };
+                self.fill(&widgets.result_list, &sender);
+            }
+            SearchMsg::IndexLibrary => {
+                let (library, folder) = {
+                    let settings = self.settings.borrow();
+                    (settings.library.clone(), settings.demo_folder_path.clone())
+                };
+                if library.folders.is_empty() && folder.is_none() {
+                    super::util::notice_dialog(
+                        root,
+                        "No demo folder",
+                        "Add library folders in the settings or open a demo folder first",
+                    );
+                    return;
+                }
+                let index = self.index.clone();
+                self.indexing = Some((0, 0));
+                sender.spawn_command(move |s| {
+                    let demos = search::library_demos(&library, folder.as_deref());
+                    let res = search::index_demos(&index, demos, false, |current, total| {
+                        s.emit(SearchCmd::Progress(current, total))
+                    });
+                    s.emit(SearchCmd::Done(res.map_err(|e| e.to_string())));
+                });
+            }
+            SearchMsg::Open(path) => {
+                let _ = sender.output(SearchOut::Open(path));
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+
+    fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        _sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        match message {
+            SearchCmd::Progress(current, total) => self.indexing = Some((current, total)),
+            SearchCmd::Done(res) => {
+                self.indexing = None;
+                self.indexed = self.index.lock().unwrap().len();
+                if let Err(e) = res {
+                    super::util::notice_dialog(root, "Failed to index demos", &e);
+                }
+            }
+        }
+    }
+}
+
+impl SearchModel {
+    fn fill(&self, list: &gtk::ListBox, sender: &ComponentSender<Self>) {
+        list.remove_all();
+        for result in &self.results {
+            let row = adw::ExpanderRow::builder()
+                .title(&result.demo.filename)
+                .subtitle(format!(
+                    "{} - {} matches",
+                    result.demo.map,
+                    result.matches.len()
+                ))
+                .use_markup(false)
+                .build();
+
+            let open_button = gtk::Button::builder()
+                .icon_name("find-location-symbolic")
+                .tooltip_text("Show in library")
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build();
+            let path = result.demo.path.clone();
+            let button_sender = sender.clone();
+            open_button.connect_clicked(move |_| {
+                button_sender.input(SearchMsg::Open(path.clone()));
+            });
+            row.add_suffix(&open_button);
+
+            for m in result.matches.iter().take(MAX_MATCHES) {
+                let match_row = adw::ActionRow::builder()
+                    .title(&m.text)
+                    .use_markup(false)
+                    .build();
+                if let Some(tick) = m.tick {
+                    match_row.set_subtitle(&format!(
+                        "{} ({tick})",
+                        crate::util::ticks_to_timestamp(tick, result.demo.tps)
+                    ));
+                }
+                row.add_row(&match_row);
+            }
+            if result.matches.len() > MAX_MATCHES {
+                row.add_row(
+                    &adw::ActionRow::builder()
+                        .title(format!("and {} more", result.matches.len() - MAX_MATCHES))
+                        .build(),
+                );
+            }
+            list.append(&row);
+        }
+        if self.results.is_empty() {
+            list.append(
+                &gtk::Label::builder()
+                    .label("No results")
+                    .margin_top(10)
+                    .margin_bottom(10)
+                    .build(),
+            );
+        }
+    }
+}
@@ -1,20 +1,49 @@
 use adw::prelude::*;
 use relm4::prelude::*;
 
-use crate::{rcon_manager::RconManager, settings::Settings};
+use crate::{
+    macros::CommandMacro,
+    rcon_manager::{PathMapping, RconManager, RconProfile},
+    settings::{PlaybackAction, Settings},
+};
 
 #[derive(Debug)]
 pub enum PreferencesMsg {
     Show,
-    ConnectionTest(String, u16),
+    ConnectionTest,
     Close,
 
     DoubleclickPlay(bool),
     PauseAfterSeek(bool),
     EventSkipOffset(f64),
     TF2FolderPath,
-    RConPassword(String),
-    RConPort(f64),
+    AddLibraryFolder,
+    RemoveLibraryFolder(usize),
+    LibraryInclude(String),
+    LibraryExclude(String),
+    AddProfile,
+    RemoveProfile(usize),
+    Profile(usize, ProfileField),
+    PositionInterval(f64),
+    SpectateVictim(bool),
+    FfmpegPath(String),
+    Shortcut(PlaybackAction, String),
+    AddMacro,
+    RemoveMacro(usize),
+    MacroName(usize, String),
+    MacroCommands(usize, String),
+    MacroShortcut(usize, String),
+    MacroAfterSeek(usize, bool),
+}
+
+#[derive(Debug)]
+pub enum ProfileField {
+    Name(String),
+    Host(String),
+    Port(u16),
+    Password(String),
+    LocalFolder(String),
+    RemoteFolder(String),
 }
 
 #[derive(Debug)]
@@ -26,6 +55,13 @@ pub struct PreferencesModel {
     parent: adw::Window,
 
     settings: Settings,
+    /// Rebuilt whenever a macro is added or removed
+    macros_group: adw::PreferencesGroup,
+    macro_rows: Vec<adw::ExpanderRow>,
+    library_group: adw::PreferencesGroup,
+    library_rows: Vec<adw::ActionRow>,
+    rcon_group: adw::PreferencesGroup,
+    profile_rows: Vec<adw::ExpanderRow>,
     connection_test_msg: String,
     connection_test_active: bool,
 }
@@ -34,6 +70,7 @@ pub struct PreferencesModel {
 pub enum PreferencesCmd {
     ConnectionTestResult(String),
     FolderBrowseResult(std::path::PathBuf),
+    LibraryFolderPicked(std::path::PathBuf),
 }
 
 #[relm4::component(pub)]
@@ -105,35 +142,91 @@ impl Component for PreferencesModel {
                         }
                     },
                 },
-                adw::PreferencesGroup {
-                    set_title: "RCon",
+                add = &model.library_group.clone() {
+                    set_title: "Library",
+                    set_description: Some("Folders that are searched for demos, including their subfolders"),
+                    #[wrap(Some)]
+                    set_header_suffix = &gtk::Button {
+                        set_icon_name: "list-add-symbolic",
+                        set_tooltip_text: Some("Add folder"),
+                        add_css_class: "flat",
+                        connect_clicked => PreferencesMsg::AddLibraryFolder,
+                    },
 
-                    adw::PasswordEntryRow {
-                        set_title: "Password",
-                        set_text: &model.settings.rcon_pw,
-                        connect_changed[sender] => move |per|{
-                            sender.input(PreferencesMsg::RConPassword(per.text().as_str().to_owned()))
+                    adw::EntryRow {
+                        set_title: "Include patterns",
+                        set_tooltip_text: Some("Comma separated, e.g. \"*/2024/*, auto-*\". Matched against the path inside the library folder, empty includes all demos"),
+                        set_text: &model.settings.library.include.join(", "),
+                        connect_changed[sender] => move |er| {
+                            sender.input(PreferencesMsg::LibraryInclude(er.text().into()));
                         }
                     },
 
+                    adw::EntryRow {
+                        set_title: "Exclude patterns",
+                        set_tooltip_text: Some("Comma separated, e.g. \"*/old/*\""),
+                        set_text: &model.settings.library.exclude.join(", "),
+                        connect_changed[sender] => move |er| {
+                            sender.input(PreferencesMsg::LibraryExclude(er.text().into()));
+                        }
+                    },
+                },
+                adw::PreferencesGroup {
+                    set_title: "Inspector",
+
                     adw::SpinRow {
-                        set_title: "Port",
+                        set_title: "Position sample interval",
+                        set_subtitle: "Ticks between player position samples for the minimap, 0 disables it. Sampling makes parsing slower",
                         set_digits: 0,
                         #[wrap(Some)]
                         set_adjustment = &gtk::Adjustment {
                             set_lower: 0.0,
-                            set_upper: u16::MAX as f64,
+                            set_upper: 1000.0,
                             set_page_increment: 10.0,
                             set_step_increment: 1.0,
-                            set_value: model.settings.rcon_port.into(),
+                            set_value: model.settings.position_interval.into(),
                             connect_value_changed[sender] => move |adj| {
-                                sender.input(PreferencesMsg::RConPort(adj.value()));
+                                sender.input(PreferencesMsg::PositionInterval(adj.value()));
                             },
                         }
                     },
 
+                    adw::SwitchRow {
+                        set_title: "Spectate victims",
+                        set_subtitle: "Follow the victim instead of the killer when jumping to a kill",
+                        set_active: model.settings.spectate_victim,
+                        connect_active_notify[sender] => move |sr| {
+                            sender.input(PreferencesMsg::SpectateVictim(sr.is_active()));
+                        }
+                    },
+                },
+                adw::PreferencesGroup {
+                    set_title: "Recording",
+                    set_description: Some("The ffmpeg presets can be edited in settings.json"),
+
+                    adw::EntryRow {
+                        set_title: "ffmpeg executable",
+                        set_text: &model.settings.ffmpeg.path.display().to_string(),
+                        connect_changed[sender] => move |er| {
+                            sender.input(PreferencesMsg::FfmpegPath(er.text().as_str().to_owned()))
+                        }
+                    },
+                },
+                add = &model.rcon_group.clone() {
+                    set_title: "RCon",
+                    set_description: Some("One profile per TF2 instance, the one in use is picked in the header bar"),
+                    #[wrap(Some)]
+                    set_header_suffix = &gtk::Button {
+                        set_icon_name: "list-add-symbolic",
+                        set_tooltip_text: Some("Add profile"),
+                        add_css_class: "flat",
+                        connect_clicked => PreferencesMsg::AddProfile,
+                    },
+
                     adw::ActionRow {
                         set_title: "Connection Test",
+                        #[watch]
+                        set_tooltip_text: Some(&format!("Tests the profile in use ({})", model.settings.rcon().name)),
                         set_subtitle_selectable: true,
                         set_activatable_widget: Some(&connection_test_button),
                         #[watch]
@@ -144,13 +237,34 @@ impl Component for PreferencesModel {
                             set_label: "Test",
                             #[watch]
                             set_sensitive: !model.connection_test_active,
-                            connect_clicked[sender, pw = model.settings.rcon_pw.clone(), port = model.settings.rcon_port] => move |_|{
-                                sender.input(PreferencesMsg::ConnectionTest(pw.clone(), port))
-                            }
+                            connect_clicked => PreferencesMsg::ConnectionTest,
                         }
                     }
                 }
             },
+
+            add = &adw::PreferencesPage {
+                set_icon_name: Some(relm4_icons::icon_names::KEYBOARD_SHORTCUTS),
+                set_title: "Shortcuts",
+
+                #[name = "shortcuts_group"]
+                adw::PreferencesGroup {
+                    set_title: "Playback",
+                    set_description: Some("Written like &lt;Control&gt;space or &lt;Shift&gt;F5, leave empty to disable"),
+                },
+
+                add = &model.macros_group.clone() {
+                    set_title: "Command macros",
+                    set_description: Some("Console commands sent to TF2, {tick}, {player} and {demo} are replaced with the playhead tick, the recording player and the demo name"),
+                    #[wrap(Some)]
+                    set_header_suffix = &gtk::Button {
+                        set_icon_name: "list-add-symbolic",
+                        set_tooltip_text: Some("Add macro"),
+                        add_css_class: "flat",
+                        connect_clicked => PreferencesMsg::AddMacro,
+                    },
+                },
+            },
         }
     }
 
@@ -159,47 +273,179 @@ impl Component for PreferencesModel {
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
-        let model = PreferencesModel {
+        let mut model = PreferencesModel {
             settings,
             parent,
+            macros_group: adw::PreferencesGroup::new(),
+            macro_rows: Vec::new(),
+            library_group: adw::PreferencesGroup::new(),
+            library_rows: Vec::new(),
+            rcon_group: adw::PreferencesGroup::new(),
+            profile_rows: Vec::new(),
             connection_test_msg: "".to_owned(),
             connection_test_active: false,
         };
 
         let widgets = view_output!();
 
+        for action in PlaybackAction::ALL {
+            let row = adw::EntryRow::builder()
+                .title(action.label())
+                .text(model.settings.shortcuts.get(action))
+                .build();
+            let sender = sender.clone();
+            row.connect_changed(move |er| {
+                let text = er.text();
+                if text.is_empty() || gtk::accelerator_parse(&text).is_some() {
+                    er.remove_css_class("error");
+                    sender.input(PreferencesMsg::Shortcut(action, text.into()));
+                } else {
+                    er.add_css_class("error");
+                }
+            });
+            widgets.shortcuts_group.add(&row);
+        }
+        model.fill_macros(&sender);
+        model.fill_library(&sender);
+        model.fill_profiles(&sender);
+
         ComponentParts { model, widgets }
     }
 
     fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, root: &Self::Root) {
         match message {
-            PreferencesMsg::ConnectionTest(pw, port) => sender.oneshot_command(async move {
-                let mut manager = RconManager::new(&pw, port);
-                let res = manager.connect().await;
-                PreferencesCmd::ConnectionTestResult(match res {
-                    Ok(_) => "Connection Successful!".to_owned(),
-                    Err(e) => match e.downcast().unwrap() {
-                        rcon::Error::Auth => {
-                            "Authorization failed, probably incorrect password".to_owned()
-                        }
-                        rcon::Error::CommandTooLong => "Command too long?".to_owned(),
-                        rcon::Error::Io(e) => format!("Connection error: {:?}", e),
-                    },
-                })
-            }),
+            PreferencesMsg::ConnectionTest => {
+                self.connection_test_active = true;
+                let profile = self.settings.rcon();
+                sender.oneshot_command(async move {
+                    let manager = RconManager::new(&profile);
+                    let res = manager.connect().await;
+                    PreferencesCmd::ConnectionTestResult(match res {
+                        Ok(_) => "Connection Successful!".to_owned(),
+                        Err(e) => match e.downcast() {
+                            Ok(rcon::Error::Auth) => {
+                                "Authorization failed, probably incorrect password".to_owned()
+                            }
+                            Ok(rcon::Error::CommandTooLong) => "Command too long?".to_owned(),
+                            Ok(rcon::Error::Io(e)) => format!("Connection error: {:?}", e),
+                            Err(e) => format!("Connection error: {}", e),
+                        },
+                    })
+                });
+            }
             PreferencesMsg::Show => {
                 self.connection_test_msg = "".to_owned();
                 root.present(Some(&self.parent));
             }
             PreferencesMsg::Close => {
+                self.settings.store_passwords();
                 self.settings.save();
                 let _ = sender.output(PreferencesOut::Save(self.settings.clone()));
             }
             PreferencesMsg::DoubleclickPlay(p) => self.settings.doubleclick_play = p,
             PreferencesMsg::PauseAfterSeek(p) => self.settings.pause_after_seek = p,
             PreferencesMsg::EventSkipOffset(off) => self.settings.event_skip_predelay = off as f32,
-            PreferencesMsg::RConPassword(pass) => self.settings.rcon_pw = pass,
-            PreferencesMsg::RConPort(port) => self.settings.rcon_port = port as u16,
+            PreferencesMsg::AddLibraryFolder => {
+                let dia = gtk::FileDialog::new();
+                let sender = sender.clone();
+                dia.select_folder(
+                    Some(&self.parent),
+                    None::<&gtk::gio::Cancellable>,
+                    move |res| match res {
+                        Ok(file) => sender
+                            .command_sender()
+                            .emit(PreferencesCmd::LibraryFolderPicked(file.path().unwrap())),
+                        Err(e) => log::warn!("Error while picking folder: {e}"),
+                    },
+                );
+            }
+            PreferencesMsg::RemoveLibraryFolder(i) => {
+                if i < self.settings.library.folders.len() {
+                    self.settings.library.folders.remove(i);
+                    self.fill_library(&sender);
+                }
+            }
+            PreferencesMsg::LibraryInclude(patterns) => {
+                self.settings.library.include = split_patterns(&patterns);
+            }
+            PreferencesMsg::LibraryExclude(patterns) => {
+                self.settings.library.exclude = split_patterns(&patterns);
+            }
+            PreferencesMsg::AddProfile => {
+                self.settings.rcon_profiles.push(RconProfile {
+                    name: format!("Profile {}", self.settings.rcon_profiles.len() + 1),
+                    ..Default::default()
+                });
+                self.fill_profiles(&sender);
+                if let Some(row) = self.profile_rows.last() {
+                    row.set_expanded(true);
+                }
+            }
+            PreferencesMsg::RemoveProfile(i) => {
+                if i < self.settings.rcon_profiles.len() && self.settings.rcon_profiles.len() > 1 {
+                    self.settings.rcon_profiles.remove(i);
+                    if self.settings.rcon_profile >= i {
+                        self.settings.rcon_profile = self.settings.rcon_profile.saturating_sub(1);
+                    }
+                    self.fill_profiles(&sender);
+                }
+            }
+            PreferencesMsg::Profile(i, field) => {
+                let Some(profile) = self.settings.rcon_profiles.get_mut(i) else {
+                    return;
+                };
+                let mut mapping = profile.path_mapping.take().unwrap_or_default();
+                match field {
+                    ProfileField::Name(name) => profile.name = name,
+                    ProfileField::Host(host) => profile.host = host,
+                    ProfileField::Port(port) => profile.port = port,
+                    ProfileField::Password(password) => profile.password = password,
+                    ProfileField::LocalFolder(folder) => mapping.local = folder.into(),
+                    ProfileField::RemoteFolder(folder) => mapping.remote = folder,
+                }
+                if mapping != PathMapping::default() {
+                    profile.path_mapping = Some(mapping);
+                }
+            }
+            PreferencesMsg::PositionInterval(interval) => {
+                self.settings.position_interval = interval as u32
+            }
+            PreferencesMsg::SpectateVictim(victim) => self.settings.spectate_victim = victim,
+            PreferencesMsg::FfmpegPath(path) => self.settings.ffmpeg.path = path.into(),
+            PreferencesMsg::Shortcut(action, accel) => self.settings.shortcuts.set(action, accel),
+            PreferencesMsg::AddMacro => {
+                self.settings.macros.push(CommandMacro::default());
+                self.fill_macros(&sender);
+                if let Some(row) = self.macro_rows.last() {
+                    row.set_expanded(true);
+                }
+            }
+            PreferencesMsg::RemoveMacro(i) => {
+                if i < self.settings.macros.len() {
+                    self.settings.macros.remove(i);
+                    self.fill_macros(&sender);
+                }
+            }
+            PreferencesMsg::MacroName(i, name) => {
+                if let Some(command_macro) = self.settings.macros.get_mut(i) {
+                    command_macro.name = name;
+                }
+            }
+            PreferencesMsg::MacroCommands(i, commands) => {
+                if let Some(command_macro) = self.settings.macros.get_mut(i) {
+                    command_macro.commands = commands;
+                }
+            }
+            PreferencesMsg::MacroShortcut(i, accel) => {
+                if let Some(command_macro) = self.settings.macros.get_mut(i) {
+                    command_macro.shortcut = accel;
+                }
+            }
+            PreferencesMsg::MacroAfterSeek(i, after_seek) => {
+                if let Some(command_macro) = self.settings.macros.get_mut(i) {
+                    command_macro.after_seek = after_seek;
+                }
+            }
             PreferencesMsg::TF2FolderPath => {
                 let dia = gtk::FileDialog::new();
                 let initial = self
@@ -226,7 +472,7 @@ impl Component for PreferencesModel {
     fn update_cmd(
         &mut self,
         message: Self::CommandOutput,
-        _: ComponentSender<Self>,
+        sender: ComponentSender<Self>,
         _: &Self::Root,
     ) {
         match message {
@@ -244,6 +490,207 @@ impl Component for PreferencesModel {
                 }
                 self.settings.tf_folder_path = Some(path);
             }
+            PreferencesCmd::LibraryFolderPicked(path) => {
+                if !self.settings.library.folders.contains(&path) {
+                    self.settings.library.folders.push(path);
+                    self.fill_library(&sender);
+                }
+            }
+        }
+    }
+}
+
+impl PreferencesModel {
+    fn fill_macros(&mut self, sender: &ComponentSender<Self>) {
+        for row in self.macro_rows.drain(..) {
+            self.macros_group.remove(&row);
+        }
+        for (i, command_macro) in self.settings.macros.iter().enumerate() {
+            let row = adw::ExpanderRow::builder()
+                .use_markup(false)
+                .title(&command_macro.name)
+                .subtitle(&command_macro.shortcut)
+                .build();
+
+            let remove = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text("Remove macro")
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build();
+            let s = sender.clone();
+            remove.connect_clicked(move |_| s.input(PreferencesMsg::RemoveMacro(i)));
+            row.add_suffix(&remove);
+
+            let name = adw::EntryRow::builder()
+                .title("Name")
+                .text(&command_macro.name)
+                .build();
+            let (s, expander) = (sender.clone(), row.clone());
+            name.connect_changed(move |er| {
+                expander.set_title(&er.text());
+                s.input(PreferencesMsg::MacroName(i, er.text().into()));
+            });
+            row.add_row(&name);
+
+            let commands = adw::EntryRow::builder()
+                .title("Commands")
+                .text(&command_macro.commands)
+                .build();
+            let s = sender.clone();
+            commands.connect_changed(move |er| {
+                s.input(PreferencesMsg::MacroCommands(i, er.text().into()));
+            });
+            row.add_row(&commands);
+
+            let shortcut = adw::EntryRow::builder()
+                .title("Shortcut")
+                .text(&command_macro.shortcut)
+                .build();
+            let (s, expander) = (sender.clone(), row.clone());
+            shortcut.connect_changed(move |er| {
+                let text = er.text();
+                if text.is_empty() || gtk::accelerator_parse(&text).is_some() {
+                    er.remove_css_class("error");
+                    expander.set_subtitle(&text);
+                    s.input(PreferencesMsg::MacroShortcut(i, text.into()));
+                } else {
+                    er.add_css_class("error");
+                }
+            });
+            row.add_row(&shortcut);
+
+            let after_seek = adw::SwitchRow::builder()
+                .title("Run after jumping to an event")
+                .active(command_macro.after_seek)
+                .build();
+            let s = sender.clone();
+            after_seek.connect_active_notify(move |sr| {
+                s.input(PreferencesMsg::MacroAfterSeek(i, sr.is_active()));
+            });
+            row.add_row(&after_seek);
+
+            self.macros_group.add(&row);
+            self.macro_rows.push(row);
+        }
+    }
+
+    fn fill_library(&mut self, sender: &ComponentSender<Self>) {
+        for row in self.library_rows.drain(..) {
+            self.library_group.remove(&row);
+        }
+        for (i, folder) in self.settings.library.folders.iter().enumerate() {
+            let row = adw::ActionRow::builder()
+                .use_markup(false)
+                .title(folder.display().to_string())
+                .build();
+
+            let remove = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text("Remove folder")
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build();
+            let s = sender.clone();
+            remove.connect_clicked(move |_| s.input(PreferencesMsg::RemoveLibraryFolder(i)));
+            row.add_suffix(&remove);
+
+            self.library_group.add(&row);
+            self.library_rows.push(row);
+        }
+    }
+
+    fn fill_profiles(&mut self, sender: &ComponentSender<Self>) {
+        for row in self.profile_rows.drain(..) {
+            self.rcon_group.remove(&row);
+        }
+        let removable = self.settings.rcon_profiles.len() > 1;
+        for (i, profile) in self.settings.rcon_profiles.iter().enumerate() {
+            let row = adw::ExpanderRow::builder()
+                .use_markup(false)
+                .title(&profile.name)
+                .subtitle(format!("{}:{}", profile.host, profile.port))
+                .build();
+
+            let remove = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text("Remove profile")
+                .valign(gtk::Align::Center)
+                .sensitive(removable)
+                .css_classes(["flat"])
+                .build();
+            let s = sender.clone();
+            remove.connect_clicked(move |_| s.input(PreferencesMsg::RemoveProfile(i)));
+            row.add_suffix(&remove);
+
+            let entry = |title: &str, text: &str, field: fn(String) -> ProfileField| {
+                let entry = adw::EntryRow::builder().title(title).text(text).build();
+                let s = sender.clone();
+                entry.connect_changed(move |er| {
+                    s.input(PreferencesMsg::Profile(i, field(er.text().into())));
+                });
+                row.add_row(&entry);
+                entry
+            };
+
+            let name = entry("Name", &profile.name, ProfileField::Name);
+            let expander = row.clone();
+            name.connect_changed(move |er| expander.set_title(&er.text()));
+            entry("Host", &profile.host, ProfileField::Host);
+
+            let port = adw::SpinRow::with_range(0.0, u16::MAX as f64, 1.0);
+            port.set_title("Port");
+            port.set_value(profile.port.into());
+            let s = sender.clone();
+            port.connect_value_notify(move |sr| {
+                s.input(PreferencesMsg::Profile(
+                    i,
+                    ProfileField::Port(sr.value() as u16),
+                ));
+            });
+            row.add_row(&port);
+
+            let password = adw::PasswordEntryRow::builder()
+                .title("Password")
+                .text(&profile.password)
+                .build();
+            let s = sender.clone();
+            password.connect_changed(move |er| {
+                s.input(PreferencesMsg::Profile(
+                    i,
+                    ProfileField::Password(er.text().into()),
+                ));
+            });
+            row.add_row(&password);
+
+            let mapping = profile.path_mapping.clone().unwrap_or_default();
+            entry(
+                "Local demo folder",
+                &mapping.local.display().to_string(),
+                ProfileField::LocalFolder,
+            )
+            .set_tooltip_text(Some("Leave empty if the game runs on this machine"));
+            entry(
+                "Demo folder on the game's machine",
+                &mapping.remote,
+                ProfileField::RemoteFolder,
+            )
+            .set_tooltip_text(Some(
+                "Demo paths under the local folder are sent with this folder instead",
+            ));
+
+            self.rcon_group.add(&row);
+            self.profile_rows.push(row);
         }
     }
 }
+
+/// Splits a comma separated list of glob patterns
+fn split_patterns(patterns: &str) -> Vec<String> {
+    patterns
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
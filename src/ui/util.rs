@@ -18,6 +18,20 @@ pub async fn delete_dialog(root: &adw::Window, count: usize) -> bool {
     }
 }
 
+pub async fn confirm_dialog(root: &adw::Window, title: &str, message: &str, confirm: &str) -> bool {
+    let ad = adw::AlertDialog::builder()
+        .default_response("confirm")
+        .close_response("cancel")
+        .body(message)
+        .heading(title)
+        .build();
+
+    ad.add_responses(&[("cancel", "Cancel"), ("confirm", confirm)]);
+    ad.set_response_appearance("confirm", adw::ResponseAppearance::Suggested);
+
+    ad.choose_future(root).await == "confirm"
+}
+
 pub fn notice_dialog(root: &adw::Window, title: &str, message: &str) {
     let ad = adw::AlertDialog::builder()
         .default_response("ok")
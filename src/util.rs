@@ -32,6 +32,31 @@ pub fn steamid_32_to_64(steamid32: &str) -> Option<String> {
     Some(format!("{}", id32 + 76561197960265728))
 }
 
+/// Normalizes a steamid in the legacy (`STEAM_0:1:1234`), steamid3 (`[U:1:2469]`) or steamid64
+/// format to steamid3
+pub fn steamid_to_32(steamid: &str) -> Option<String> {
+    let steamid = steamid.trim();
+    let id32: u64 = if let Some(legacy) = steamid.strip_prefix("STEAM_") {
+        let segments: Vec<&str> = legacy.split(':').collect();
+        let y: u64 = segments.get(1)?.parse().ok()?;
+        let z: u64 = segments.get(2)?.parse().ok()?;
+        z * 2 + y
+    } else if steamid.starts_with("[U:") {
+        steamid
+            .trim_end_matches(']')
+            .split(':')
+            .nth(2)?
+            .parse()
+            .ok()?
+    } else {
+        steamid
+            .parse::<u64>()
+            .ok()?
+            .checked_sub(76561197960265728)?
+    };
+    Some(format!("[U:1:{id32}]"))
+}
+
 pub async fn find_obsolete_replays(
     replay_folder: impl Into<async_std::path::PathBuf>,
 ) -> Result<Vec<std::path::PathBuf>> {
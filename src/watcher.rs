@@ -0,0 +1,47 @@
+//! Watches demo folders for demos being added, recorded, changed or removed
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Stops watching when dropped
+pub struct DemoWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl DemoWatcher {
+    /// Calls `on_change` from a background thread with every path that was created, written to,
+    /// renamed or removed in the folders
+    pub fn new(
+        folders: &[PathBuf],
+        recursive: bool,
+        on_change: impl Fn(PathBuf) + Send + 'static,
+    ) -> Result<Self> {
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) => {
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) {
+                        for path in event.paths {
+                            on_change(path);
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Error while watching demo folders, {}", e),
+            })?;
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        for folder in folders {
+            if let Err(e) = watcher.watch(folder, mode) {
+                log::warn!("Couldn't watch {}, {}", folder.display(), e);
+            }
+        }
+        Ok(Self { _watcher: watcher })
+    }
+}